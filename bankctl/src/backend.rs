@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use chrono::Utc;
+
+use bank_of_dad::{
+    db::Db,
+    handlers::record_transaction::{
+        record_transaction_quietly_for_child, GiveMoney, TransactionType,
+    },
+    model::{
+        amount::Amount,
+        error::ApiError,
+        integrity::IntegrityReport,
+        transaction::{ChildBalance, Transaction},
+    },
+};
+use bank_of_dad_client::{BankClient, ClientError};
+
+/// Where bankctl reads and writes the ledger. Both apply the server's
+/// validation, but changes made directly to the file notify no one and
+/// don't trigger, or use up the cooldown of, any alert rules.
+pub enum Backend {
+    Http(BankClient),
+    Sqlite(Db),
+}
+
+fn api_error(error: ApiError) -> String {
+    format!("{} ({})", error.public_reason(), error.code().as_str())
+}
+
+fn client_error(error: ClientError) -> String {
+    error.to_string()
+}
+
+impl Backend {
+    pub fn http(base_url: &str) -> Result<Backend, String> {
+        BankClient::new(base_url)
+            .map(Backend::Http)
+            .map_err(client_error)
+    }
+
+    /// Won't create a database that isn't there.
+    pub fn sqlite(path: &Path) -> Result<Backend, String> {
+        if !path.is_file() {
+            return Err(format!("no database at {}", path.display()));
+        }
+
+        let db = Db::open(path).map_err(|e| format!("can't open {}: {}", path.display(), e))?;
+        Ok(Backend::Sqlite(db))
+    }
+
+    pub async fn children(&self) -> Result<Vec<ChildBalance>, String> {
+        match self {
+            Self::Http(client) => client.children().await.map_err(client_error),
+            Self::Sqlite(db) => db.get_child_balances().map_err(api_error),
+        }
+    }
+
+    pub async fn balance(&self, child_name: &str) -> Result<ChildBalance, String> {
+        let balance = match self {
+            Self::Http(client) => {
+                client
+                    .get_child(child_name)
+                    .await
+                    .map_err(client_error)?
+                    .balance
+            }
+            Self::Sqlite(db) => {
+                db.get_ledger_version_for_child(child_name)
+                    .map_err(api_error)?
+                    .balance
+            }
+        };
+
+        Ok(ChildBalance {
+            child_name: String::from(child_name),
+            balance,
+        })
+    }
+
+    /// Oldest first.
+    pub async fn transactions(&self, child_name: &str) -> Result<Vec<Transaction>, String> {
+        match self {
+            Self::Http(client) => client.transactions(child_name).await.map_err(client_error),
+            Self::Sqlite(db) => db
+                .get_transactions_for_child(String::from(child_name))
+                .map_err(api_error),
+        }
+    }
+
+    pub async fn record_transaction(
+        &self,
+        child_name: &str,
+        amount: Amount,
+        purpose: &str,
+        transaction_type: TransactionType,
+    ) -> Result<Transaction, String> {
+        match self {
+            Self::Http(client) => match transaction_type {
+                TransactionType::Give => client.give(child_name, amount, purpose).await,
+                TransactionType::Spend => client.spend(child_name, amount, purpose).await,
+            }
+            .map_err(client_error),
+            Self::Sqlite(db) => record_transaction_quietly_for_child(
+                db,
+                String::from(child_name),
+                GiveMoney {
+                    amount,
+                    purpose: String::from(purpose),
+                },
+                transaction_type,
+            )
+            .map_err(api_error),
+        }
+    }
+
+    pub async fn reverse(&self, child_name: &str, id: i64) -> Result<Transaction, String> {
+        match self {
+            Self::Http(client) => client.reverse(child_name, id).await.map_err(client_error),
+            Self::Sqlite(db) => db
+                .reverse_transaction_for_child(child_name, id, Utc::now())
+                .map_err(api_error),
+        }
+    }
+
+    pub async fn check_integrity(&self) -> Result<IntegrityReport, String> {
+        match self {
+            Self::Http(client) => client.check_integrity().await.map_err(client_error),
+            Self::Sqlite(db) => db.check_integrity().map_err(api_error),
+        }
+    }
+}
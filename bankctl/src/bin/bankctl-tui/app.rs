@@ -0,0 +1,474 @@
+use bank_of_dad::{
+    handlers::record_transaction::TransactionType,
+    model::{
+        amount::Amount,
+        transaction::{ChildBalance, Transaction},
+    },
+};
+use bank_of_dad_client::Notification;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// What the event loop should do after a key press or notification.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    None,
+    Quit,
+    /// Re-read every child and the selected child's history.
+    Refresh,
+    LoadHistory(String),
+    Submit(TransactionRequest),
+}
+
+/// A validated give or spend from the dialog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionRequest {
+    pub child_name: String,
+    pub amount: Amount,
+    pub purpose: String,
+    pub transaction_type: TransactionType,
+    /// The child wasn't in the list, so isn't subscribed to yet.
+    pub new_child: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Children,
+    History,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    ChildName,
+    Amount,
+    Purpose,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dialog {
+    pub transaction_type: TransactionType,
+    pub child_name: String,
+    /// Only a give to a new child lets the name be typed.
+    pub new_child: bool,
+    pub amount: String,
+    pub purpose: String,
+    pub field: Field,
+    pub error: Option<String>,
+}
+
+impl Dialog {
+    fn new(transaction_type: TransactionType, child_name: Option<&str>) -> Dialog {
+        Dialog {
+            transaction_type,
+            child_name: child_name.map(String::from).unwrap_or_default(),
+            new_child: child_name.is_none(),
+            amount: String::new(),
+            purpose: String::new(),
+            field: if child_name.is_none() {
+                Field::ChildName
+            } else {
+                Field::Amount
+            },
+            error: None,
+        }
+    }
+
+    pub fn fields(&self) -> &'static [Field] {
+        if self.new_child {
+            &[Field::ChildName, Field::Amount, Field::Purpose]
+        } else {
+            &[Field::Amount, Field::Purpose]
+        }
+    }
+
+    fn move_field(&mut self, step: isize) {
+        let fields = self.fields();
+        let i = fields.iter().position(|f| *f == self.field).unwrap_or(0) as isize;
+        self.field = fields[(i + step).rem_euclid(fields.len() as isize) as usize];
+    }
+
+    fn input(&mut self) -> &mut String {
+        match self.field {
+            Field::ChildName => &mut self.child_name,
+            Field::Amount => &mut self.amount,
+            Field::Purpose => &mut self.purpose,
+        }
+    }
+
+    /// Checks the form the way the server would, so mistakes are shown
+    /// before anything is sent.
+    fn validate(&self) -> Result<TransactionRequest, String> {
+        let child_name = self.child_name.trim();
+        if child_name.is_empty() {
+            return Err(String::from("Enter the child's name"));
+        }
+
+        let amount: Amount = serde_json::from_str(self.amount.trim())
+            .map_err(|_| String::from("Enter an amount like 2 or 1.50"))?;
+        if !amount.is_positive_nonzero() {
+            return Err(String::from("The amount must be more than 0.00"));
+        }
+
+        let purpose = self.purpose.trim();
+        if purpose.is_empty() {
+            return Err(String::from("Enter what it's for"));
+        }
+
+        Ok(TransactionRequest {
+            child_name: String::from(child_name),
+            amount,
+            purpose: String::from(purpose),
+            transaction_type: self.transaction_type,
+            new_child: self.new_child,
+        })
+    }
+}
+
+pub struct App {
+    /// By name.
+    pub children: Vec<ChildBalance>,
+    pub selected: usize,
+    /// The selected child's transactions, newest first.
+    pub history: Vec<Transaction>,
+    pub history_offset: usize,
+    pub focus: Focus,
+    pub dialog: Option<Dialog>,
+    pub status: String,
+}
+
+impl App {
+    pub fn new() -> App {
+        App {
+            children: Vec::new(),
+            selected: 0,
+            history: Vec::new(),
+            history_offset: 0,
+            focus: Focus::Children,
+            dialog: None,
+            status: String::new(),
+        }
+    }
+
+    pub fn selected_child(&self) -> Option<&str> {
+        self.children
+            .get(self.selected)
+            .map(|child| child.child_name.as_str())
+    }
+
+    /// Replaces the children, keeping the same child selected if it's still
+    /// there.
+    pub fn set_children(&mut self, children: Vec<ChildBalance>) {
+        let selected = self.selected_child().map(String::from);
+        self.children = children;
+        self.selected = selected
+            .and_then(|name| self.children.iter().position(|c| c.child_name == name))
+            .unwrap_or(0);
+    }
+
+    /// `transactions` oldest first, as the API returns them.
+    pub fn set_history(&mut self, child_name: &str, mut transactions: Vec<Transaction>) {
+        if self.selected_child() != Some(child_name) {
+            return;
+        }
+        transactions.reverse();
+        self.history = transactions;
+        self.history_offset = 0;
+    }
+
+    fn select(&mut self, i: usize) -> Action {
+        if i >= self.children.len() || i == self.selected {
+            return Action::None;
+        }
+        self.selected = i;
+        self.history.clear();
+        self.history_offset = 0;
+        Action::LoadHistory(self.children[i].child_name.clone())
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+
+        if self.dialog.is_some() {
+            return self.handle_dialog_key(key);
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            KeyCode::Char('r') => Action::Refresh,
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right => {
+                self.focus = match self.focus {
+                    Focus::Children => Focus::History,
+                    Focus::History => Focus::Children,
+                };
+                Action::None
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Char('g') => self.open_dialog(TransactionType::Give, false),
+            KeyCode::Char('s') => self.open_dialog(TransactionType::Spend, false),
+            KeyCode::Char('n') => self.open_dialog(TransactionType::Give, true),
+            _ => Action::None,
+        }
+    }
+
+    fn move_selection(&mut self, step: isize) -> Action {
+        match self.focus {
+            Focus::Children => {
+                let i = self.selected.saturating_add_signed(step);
+                self.select(i)
+            }
+            Focus::History => {
+                let last = self.history.len().saturating_sub(1);
+                self.history_offset = self.history_offset.saturating_add_signed(step).min(last);
+                Action::None
+            }
+        }
+    }
+
+    fn open_dialog(&mut self, transaction_type: TransactionType, new_child: bool) -> Action {
+        let child_name = if new_child {
+            None
+        } else {
+            match self.selected_child() {
+                Some(child_name) => Some(child_name),
+                None => {
+                    self.status = String::from("No children yet, press n to add one");
+                    return Action::None;
+                }
+            }
+        };
+        self.dialog = Some(Dialog::new(transaction_type, child_name));
+        Action::None
+    }
+
+    fn handle_dialog_key(&mut self, key: KeyEvent) -> Action {
+        let Some(dialog) = self.dialog.as_mut() else {
+            return Action::None;
+        };
+
+        match key.code {
+            KeyCode::Esc => self.dialog = None,
+            KeyCode::Tab | KeyCode::Down => dialog.move_field(1),
+            KeyCode::BackTab | KeyCode::Up => dialog.move_field(-1),
+            KeyCode::Backspace => {
+                dialog.input().pop();
+            }
+            KeyCode::Char(c) => dialog.input().push(c),
+            KeyCode::Enter => match dialog.validate() {
+                Ok(request) => {
+                    dialog.error = None;
+                    return Action::Submit(request);
+                }
+                Err(e) => dialog.error = Some(e),
+            },
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// The server accepted the dialog's transaction. Its balance is updated
+    /// when the notification arrives.
+    pub fn submitted(&mut self, transaction: &Transaction) {
+        self.dialog = None;
+        self.status = if transaction.amount.is_negative() {
+            format!(
+                "{} spent {}",
+                transaction.child_name,
+                transaction.amount.negate()
+            )
+        } else {
+            format!(
+                "{} was given {}",
+                transaction.child_name, transaction.amount
+            )
+        };
+    }
+
+    /// The server rejected the dialog's transaction.
+    pub fn submit_failed(&mut self, error: String) {
+        match self.dialog.as_mut() {
+            Some(dialog) => dialog.error = Some(error),
+            None => self.status = error,
+        }
+    }
+
+    pub fn apply_notification(&mut self, notification: Notification) -> Action {
+        match notification {
+            Notification::Transaction(transaction) => {
+                self.apply_transaction(transaction);
+                Action::None
+            }
+            Notification::Alert(alert) => {
+                self.status = format!("Alert: {}", alert.message);
+                Action::None
+            }
+            Notification::ApprovalRequest(request) => {
+                self.status = format!(
+                    "{} asks to spend {} on {}",
+                    request.child_name, request.amount, request.purpose
+                );
+                Action::None
+            }
+            Notification::ResyncRequired => Action::Refresh,
+        }
+    }
+
+    fn apply_transaction(&mut self, transaction: Transaction) {
+        let selected = self.selected_child().map(String::from);
+
+        match self
+            .children
+            .binary_search_by(|c| c.child_name.as_str().cmp(&transaction.child_name))
+        {
+            Ok(i) => {
+                self.children[i].balance = self.children[i].balance + transaction.amount;
+            }
+            Err(i) => {
+                self.children.insert(
+                    i,
+                    ChildBalance {
+                        child_name: transaction.child_name.clone(),
+                        balance: transaction.amount,
+                    },
+                );
+                if let Some(name) = &selected {
+                    self.selected = self
+                        .children
+                        .iter()
+                        .position(|c| &c.child_name == name)
+                        .unwrap_or(0);
+                }
+            }
+        }
+
+        if selected.as_deref() == Some(transaction.child_name.as_str())
+            && !self.history.iter().any(|t| t.id == transaction.id)
+        {
+            self.history.insert(0, transaction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bank_of_dad::{
+        handlers::record_transaction::TransactionType,
+        model::{
+            amount::Amount,
+            transaction::{ChildBalance, Transaction},
+        },
+    };
+    use bank_of_dad_client::Notification;
+    use chrono::Utc;
+    use ratatui::crossterm::event::{KeyCode, KeyEvent};
+
+    use super::{Action, App, TransactionRequest};
+
+    fn press(app: &mut App, keys: &str) -> Action {
+        keys.chars()
+            .map(|c| app.handle_key(KeyEvent::from(KeyCode::Char(c))))
+            .last()
+            .unwrap()
+    }
+
+    fn child(child_name: &str, pence: i64) -> ChildBalance {
+        ChildBalance {
+            child_name: String::from(child_name),
+            balance: Amount::from_pence(pence),
+        }
+    }
+
+    fn transaction(id: i64, child_name: &str, pence: i64) -> Transaction {
+        Transaction::new(
+            id,
+            Utc::now(),
+            String::from(child_name),
+            Amount::from_pence(pence),
+            String::from("test"),
+        )
+    }
+
+    #[test]
+    fn notifications_test() {
+        let mut app = App::new();
+        app.set_children(vec![child("a", 100), child("c", 300)]);
+        assert_eq!(
+            app.handle_key(KeyEvent::from(KeyCode::Down)),
+            Action::LoadHistory(String::from("c"))
+        );
+        app.set_history("c", vec![transaction(1, "c", 300)]);
+
+        app.apply_notification(Notification::Transaction(transaction(2, "c", -50)));
+        app.apply_notification(Notification::Transaction(transaction(3, "b", 200)));
+        app.apply_notification(Notification::Transaction(transaction(4, "a", 25)));
+
+        assert_eq!(
+            app.children,
+            vec![child("a", 125), child("b", 200), child("c", 250)]
+        );
+        assert_eq!(app.selected_child(), Some("c"));
+        assert_eq!(
+            app.history.iter().map(|t| t.id).collect::<Vec<i64>>(),
+            vec![2, 1]
+        );
+
+        assert_eq!(
+            app.apply_notification(Notification::ResyncRequired),
+            Action::Refresh
+        );
+    }
+
+    #[test]
+    fn dialog_test() {
+        let mut app = App::new();
+        app.set_children(vec![child("a", 100)]);
+
+        press(&mut app, "s");
+        assert!(app.dialog.is_some());
+
+        press(&mut app, "1.5");
+        app.handle_key(KeyEvent::from(KeyCode::Tab));
+        press(&mut app, "sweets");
+        assert_eq!(app.handle_key(KeyEvent::from(KeyCode::Enter)), Action::None);
+        assert_eq!(
+            app.dialog.as_ref().unwrap().error.as_deref(),
+            Some("Enter an amount like 2 or 1.50")
+        );
+
+        app.handle_key(KeyEvent::from(KeyCode::BackTab));
+        press(&mut app, "0");
+        assert_eq!(
+            app.handle_key(KeyEvent::from(KeyCode::Enter)),
+            Action::Submit(TransactionRequest {
+                child_name: String::from("a"),
+                amount: Amount::from_pence(150),
+                purpose: String::from("sweets"),
+                transaction_type: TransactionType::Spend,
+                new_child: false,
+            })
+        );
+
+        app.submit_failed(String::from("Insufficient funds"));
+        assert_eq!(
+            app.dialog.as_ref().unwrap().error.as_deref(),
+            Some("Insufficient funds")
+        );
+        app.handle_key(KeyEvent::from(KeyCode::Esc));
+        assert!(app.dialog.is_none());
+
+        press(&mut app, "n");
+        press(&mut app, "b");
+        app.handle_key(KeyEvent::from(KeyCode::Tab));
+        press(&mut app, "2");
+        app.handle_key(KeyEvent::from(KeyCode::Tab));
+        press(&mut app, "birthday");
+        match app.handle_key(KeyEvent::from(KeyCode::Enter)) {
+            Action::Submit(request) => {
+                assert_eq!(request.child_name, "b");
+                assert!(request.new_child);
+            }
+            other => panic!("expected a submit, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::process::ExitCode;
+
+use bank_of_dad::handlers::record_transaction::TransactionType;
+use bank_of_dad_client::{BankClient, ClientError, Notifications};
+use clap::Parser;
+use futures::StreamExt;
+use ratatui::{
+    crossterm::event::{self, Event, KeyEventKind},
+    DefaultTerminal,
+};
+use tokio::sync::mpsc;
+
+use crate::app::{Action, App, TransactionRequest};
+
+mod app;
+mod ui;
+
+/// Shows every child's balance live, with their history and quick give
+/// and spend dialogs.
+#[derive(Debug, Parser)]
+#[command(name = "bankctl-tui", version)]
+struct Cli {
+    /// The server's root url, e.g. http://localhost:3000
+    #[arg(long, env = "BANKCTL_SERVER")]
+    server: String,
+}
+
+/// Reads terminal events on their own thread, as crossterm blocks.
+fn terminal_events() -> mpsc::UnboundedReceiver<Event> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+async fn refresh(
+    client: &BankClient,
+    notifications: &mut Notifications,
+    app: &mut App,
+) -> Result<(), ClientError> {
+    let children = client.children().await?;
+    notifications
+        .subscribe(children.iter().map(|c| c.child_name.clone()).collect())
+        .await?;
+    app.set_children(children);
+
+    if let Some(child_name) = app.selected_child().map(String::from) {
+        load_history(client, app, child_name).await?;
+    }
+    Ok(())
+}
+
+async fn load_history(
+    client: &BankClient,
+    app: &mut App,
+    child_name: String,
+) -> Result<(), ClientError> {
+    let transactions = client.transactions(&child_name).await?;
+    app.set_history(&child_name, transactions);
+    Ok(())
+}
+
+async fn submit(
+    client: &BankClient,
+    notifications: &mut Notifications,
+    request: TransactionRequest,
+) -> Result<bank_of_dad::model::transaction::Transaction, ClientError> {
+    // Subscribe first so the new child's first transaction isn't missed
+    if request.new_child {
+        notifications
+            .subscribe(vec![request.child_name.clone()])
+            .await?;
+    }
+
+    match request.transaction_type {
+        TransactionType::Give => {
+            client
+                .give(&request.child_name, request.amount, &request.purpose)
+                .await
+        }
+        TransactionType::Spend => {
+            client
+                .spend(&request.child_name, request.amount, &request.purpose)
+                .await
+        }
+    }
+}
+
+/// Server errors are shown in the status line or dialog rather than
+/// ending the UI.
+fn describe(error: ClientError) -> String {
+    match error {
+        ClientError::Api(error_response) => error_response.detail,
+        other => other.to_string(),
+    }
+}
+
+async fn run(terminal: &mut DefaultTerminal, client: BankClient) -> Result<(), String> {
+    let mut notifications = client
+        .household_notifications(Vec::new())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut events = terminal_events();
+    let mut app = App::new();
+    let mut action = Action::Refresh;
+
+    loop {
+        match action {
+            Action::None => {}
+            Action::Quit => return Ok(()),
+            Action::Refresh => match refresh(&client, &mut notifications, &mut app).await {
+                Ok(()) => app.status.clear(),
+                Err(e) => app.status = describe(e),
+            },
+            Action::LoadHistory(child_name) => {
+                if let Err(e) = load_history(&client, &mut app, child_name).await {
+                    app.status = describe(e);
+                }
+            }
+            Action::Submit(request) => match submit(&client, &mut notifications, request).await {
+                Ok(transaction) => app.submitted(&transaction),
+                Err(e) => app.submit_failed(describe(e)),
+            },
+        }
+
+        terminal
+            .draw(|frame| ui::draw(frame, &app))
+            .map_err(|e| e.to_string())?;
+
+        action = tokio::select! {
+            event = events.recv() => match event {
+                Some(Event::Key(key)) if key.kind == KeyEventKind::Press => app.handle_key(key),
+                Some(_) => Action::None,
+                None => return Err(String::from("can't read from the terminal")),
+            },
+            notification = notifications.next() => match notification {
+                Some(Ok(notification)) => app.apply_notification(notification),
+                Some(Err(e)) => {
+                    app.status = describe(e);
+                    Action::None
+                }
+                None => return Err(String::from("the server closed the notifications socket")),
+            },
+        };
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let client = match BankClient::new(&cli.server) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("bankctl-tui: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, client).await;
+    ratatui::restore();
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("bankctl-tui: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use bank_of_dad::handlers::record_transaction::TransactionType;
+use ratatui::{
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState},
+    Frame,
+};
+
+use crate::app::{App, Dialog, Field, Focus};
+
+const HELP: &str = "↑↓ select  tab switch pane  g give  s spend  n new child  r refresh  q quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+    let [children, history] =
+        Layout::horizontal([Constraint::Length(28), Constraint::Min(20)]).areas(main);
+
+    draw_children(frame, app, children);
+    draw_history(frame, app, history);
+
+    let status_line = if app.status.is_empty() {
+        Line::from(HELP).dim()
+    } else {
+        Line::from(app.status.as_str())
+    };
+    frame.render_widget(Paragraph::new(status_line), status);
+
+    if let Some(dialog) = &app.dialog {
+        draw_dialog(frame, dialog);
+    }
+}
+
+fn pane(title: &str, focused: bool) -> Block<'_> {
+    let block = Block::bordered().title(title);
+    if focused {
+        block.border_style(Style::new().fg(Color::Cyan))
+    } else {
+        block
+    }
+}
+
+fn amount_style(negative: bool) -> Style {
+    if negative {
+        Style::new().fg(Color::Red)
+    } else {
+        Style::new().fg(Color::Green)
+    }
+}
+
+fn draw_children(frame: &mut Frame, app: &App, area: Rect) {
+    let width = area.width.saturating_sub(4) as usize;
+    let items: Vec<ListItem> = app
+        .children
+        .iter()
+        .map(|child| {
+            let balance = child.balance.to_string();
+            let name_width = width.saturating_sub(balance.len() + 1);
+            ListItem::new(Line::from(vec![
+                format!("{:<name_width$.name_width$} ", child.child_name).into(),
+                Span::styled(balance, amount_style(child.balance.is_negative())),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(pane(" Children ", app.focus == Focus::Children))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(app.selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_history(frame: &mut Frame, app: &App, area: Rect) {
+    let title = match app.selected_child() {
+        Some(child_name) => format!(" {} ", child_name),
+        None => String::from(" No children yet "),
+    };
+
+    let rows = app.history.iter().map(|transaction| {
+        Row::new([
+            Cell::from(transaction.id.to_string()),
+            Cell::from(transaction.timestamp.format("%Y-%m-%d %H:%M").to_string()),
+            Cell::from(Line::from(transaction.amount.to_string()).right_aligned())
+                .style(amount_style(transaction.amount.is_negative())),
+            Cell::from(transaction.purpose.as_str()),
+        ])
+    });
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(4),
+            Constraint::Length(16),
+            Constraint::Length(10),
+            Constraint::Min(10),
+        ],
+    )
+    .header(Row::new(["ID", "WHEN", "AMOUNT", "PURPOSE"]).bold())
+    .block(pane(&title, app.focus == Focus::History))
+    .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+    let selected =
+        (app.focus == Focus::History && !app.history.is_empty()).then_some(app.history_offset);
+    let mut state = TableState::default()
+        .with_offset(app.history_offset)
+        .with_selected(selected);
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn draw_dialog(frame: &mut Frame, dialog: &Dialog) {
+    let title = match dialog.transaction_type {
+        TransactionType::Give => " Give ",
+        TransactionType::Spend => " Spend ",
+    };
+    let fields = dialog.fields();
+    let height = fields.len() as u16 + if dialog.new_child { 3 } else { 4 };
+    let area = centered(frame.area(), 48, height);
+
+    let mut lines = Vec::new();
+    if !dialog.new_child {
+        lines.push(Line::from(format!("Child:   {}", dialog.child_name)));
+    }
+    for field in fields {
+        let (label, value) = match field {
+            Field::ChildName => ("Child:  ", &dialog.child_name),
+            Field::Amount => ("Amount: ", &dialog.amount),
+            Field::Purpose => ("Purpose:", &dialog.purpose),
+        };
+        let line = if *field == dialog.field {
+            Line::from(format!("{} {}_", label, value)).bold()
+        } else {
+            Line::from(format!("{} {}", label, value))
+        };
+        lines.push(line);
+    }
+    lines.push(match &dialog.error {
+        Some(error) => Line::from(error.as_str()).red(),
+        None => Line::from("enter save  tab next field  esc cancel").dim(),
+    });
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+#[cfg(test)]
+mod tests {
+    use bank_of_dad::model::{amount::Amount, transaction::ChildBalance};
+    use ratatui::{
+        backend::TestBackend,
+        crossterm::event::{KeyCode, KeyEvent},
+        Terminal,
+    };
+
+    use crate::app::App;
+
+    #[test]
+    fn draw_test() {
+        let mut app = App::new();
+        app.set_children(vec![ChildBalance {
+            child_name: String::from("alice"),
+            balance: Amount::from_pence(-150),
+        }]);
+        app.handle_key(KeyEvent::from(KeyCode::Char('g')));
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+        terminal.draw(|frame| super::draw(frame, &app)).unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("alice"));
+        assert!(screen.contains("-1.50"));
+        assert!(screen.contains(" Give "));
+        assert!(screen.contains("Amount:  _"));
+    }
+}
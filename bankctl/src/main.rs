@@ -0,0 +1,140 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use bank_of_dad::{handlers::record_transaction::TransactionType, model::amount::Amount};
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+
+use crate::{
+    backend::Backend,
+    output::{OutputFormat, Render},
+    statement::Statement,
+};
+
+mod backend;
+mod output;
+mod statement;
+
+/// Administers a Bank of Dad ledger, through a running server or directly
+/// on its SQLite file while it's stopped.
+#[derive(Debug, Parser)]
+#[command(name = "bankctl", version)]
+struct Cli {
+    /// The server's root url, e.g. http://localhost:3000
+    #[arg(long, env = "BANKCTL_SERVER", required_unless_present = "db")]
+    server: Option<String>,
+
+    /// The server's database file. Changes made this way send no
+    /// notifications, webhooks or emails.
+    #[arg(long, env = "BANKCTL_DB", conflicts_with = "server")]
+    db: Option<PathBuf>,
+
+    #[arg(long, short, value_enum, default_value_t = OutputFormat::Table, global = true)]
+    output: OutputFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Lists every child with a transaction, and their balances
+    Children,
+    /// Shows a child's balance
+    Balance { child_name: String },
+    /// Gives a child money
+    Give {
+        child_name: String,
+        #[arg(value_parser = parse_amount)]
+        amount: Amount,
+        purpose: String,
+    },
+    /// Records a child spending money
+    Spend {
+        child_name: String,
+        #[arg(value_parser = parse_amount)]
+        amount: Amount,
+        purpose: String,
+    },
+    /// Records a transaction cancelling out an earlier one
+    Reverse { child_name: String, id: i64 },
+    /// Exports a child's transactions with running balances
+    Statement {
+        child_name: String,
+        /// First day to include, e.g. 2026-10-01
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day to include
+        #[arg(long)]
+        to: Option<NaiveDate>,
+    },
+    /// Checks the ledger's invariants, exiting with 1 if any fail
+    Check,
+}
+
+/// Amounts as the API takes them, e.g. 1.50 or 2.
+fn parse_amount(amount: &str) -> Result<Amount, String> {
+    serde_json::from_str(amount).map_err(|e| e.to_string())
+}
+
+async fn run(cli: Cli) -> Result<ExitCode, String> {
+    let backend = match (&cli.server, &cli.db) {
+        (Some(server), _) => Backend::http(server)?,
+        (None, Some(db)) => Backend::sqlite(db)?,
+        (None, None) => unreachable!("clap requires one of them"),
+    };
+
+    let output = match cli.command {
+        Command::Children => backend.children().await?.render(cli.output),
+        Command::Balance { child_name } => backend.balance(&child_name).await?.render(cli.output),
+        Command::Give {
+            child_name,
+            amount,
+            purpose,
+        } => backend
+            .record_transaction(&child_name, amount, &purpose, TransactionType::Give)
+            .await?
+            .render(cli.output),
+        Command::Spend {
+            child_name,
+            amount,
+            purpose,
+        } => backend
+            .record_transaction(&child_name, amount, &purpose, TransactionType::Spend)
+            .await?
+            .render(cli.output),
+        Command::Reverse { child_name, id } => {
+            backend.reverse(&child_name, id).await?.render(cli.output)
+        }
+        Command::Statement {
+            child_name,
+            from,
+            to,
+        } => {
+            let transactions = backend.transactions(&child_name).await?;
+            Statement::build(&child_name, transactions, from, to).render(cli.output)
+        }
+        Command::Check => {
+            let report = backend.check_integrity().await?;
+            println!("{}", report.render(cli.output));
+            return Ok(if report.passed() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        }
+    };
+
+    println!("{}", output);
+    Ok(ExitCode::SUCCESS)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("bankctl: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
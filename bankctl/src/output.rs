@@ -0,0 +1,228 @@
+use bank_of_dad::model::{
+    integrity::IntegrityReport,
+    transaction::{ChildBalance, Transaction},
+};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::statement::Statement;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Rows of text, printed aligned or as CSV.
+pub struct Table {
+    columns: Vec<(&'static str, Align)>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<(&'static str, Align)>) -> Table {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn to_text(&self) -> String {
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                self.rows
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([name.len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let headers = self.columns.iter().map(|(name, _)| name.to_string());
+        std::iter::once(headers.collect())
+            .chain(self.rows.iter().cloned())
+            .map(|row: Vec<String>| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&self.columns)
+                    .zip(&widths)
+                    .map(|((cell, (_, align)), width)| match align {
+                        Align::Left => format!("{:<width$}", cell),
+                        Align::Right => format!("{:>width$}", cell),
+                    })
+                    .collect();
+                cells.join("  ").trim_end().to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn to_csv(&self) -> String {
+        let headers = self.columns.iter().map(|(name, _)| name.to_lowercase());
+        std::iter::once(headers.collect())
+            .chain(self.rows.iter().cloned())
+            .map(|row: Vec<String>| {
+                row.iter()
+                    .map(|cell| csv_field(cell))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        String::from(cell)
+    }
+}
+
+/// Anything bankctl prints. JSON output is the value as the API serves it.
+pub trait Render: Serialize {
+    fn table(&self) -> Table;
+
+    fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Table => self.table().to_text(),
+            OutputFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+            OutputFormat::Csv => self.table().to_csv(),
+        }
+    }
+}
+
+impl Render for Vec<ChildBalance> {
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec![("CHILD", Align::Left), ("BALANCE", Align::Right)]);
+        for child_balance in self {
+            table.push(vec![
+                child_balance.child_name.clone(),
+                child_balance.balance.to_string(),
+            ]);
+        }
+        table
+    }
+}
+
+impl Render for ChildBalance {
+    fn table(&self) -> Table {
+        vec![self.clone()].table()
+    }
+}
+
+impl Render for Transaction {
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec![
+            ("ID", Align::Right),
+            ("TIMESTAMP", Align::Left),
+            ("CHILD", Align::Left),
+            ("AMOUNT", Align::Right),
+            ("PURPOSE", Align::Left),
+        ]);
+        table.push(vec![
+            self.id.to_string(),
+            self.timestamp.to_rfc3339(),
+            self.child_name.clone(),
+            self.amount.to_string(),
+            self.purpose.clone(),
+        ]);
+        table
+    }
+}
+
+impl Render for Statement {
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec![
+            ("ID", Align::Right),
+            ("DATE", Align::Left),
+            ("PURPOSE", Align::Left),
+            ("AMOUNT", Align::Right),
+            ("BALANCE", Align::Right),
+        ]);
+        table.push(vec![
+            String::new(),
+            self.from.map(|d| d.to_string()).unwrap_or_default(),
+            String::from("Opening balance"),
+            String::new(),
+            self.opening_balance.to_string(),
+        ]);
+        for line in &self.lines {
+            table.push(vec![
+                line.transaction.id.to_string(),
+                line.transaction.timestamp.date_naive().to_string(),
+                line.transaction.purpose.clone(),
+                line.transaction.amount.to_string(),
+                line.balance.to_string(),
+            ]);
+        }
+        table.push(vec![
+            String::new(),
+            self.to.map(|d| d.to_string()).unwrap_or_default(),
+            String::from("Closing balance"),
+            String::new(),
+            self.closing_balance.to_string(),
+        ]);
+        table
+    }
+}
+
+impl Render for IntegrityReport {
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec![
+            ("CHECK", Align::Left),
+            ("RESULT", Align::Left),
+            ("PROBLEM", Align::Left),
+        ]);
+        for check in &self.checks {
+            if check.passed() {
+                table.push(vec![check.name.clone(), String::from("ok"), String::new()]);
+            }
+            for problem in &check.problems {
+                table.push(vec![
+                    check.name.clone(),
+                    String::from("FAILED"),
+                    problem.clone(),
+                ]);
+            }
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Align, Table};
+
+    #[test]
+    fn table_test() {
+        let mut table = Table::new(vec![("CHILD", Align::Left), ("BALANCE", Align::Right)]);
+        table.push(vec![String::from("alice"), String::from("12.50")]);
+        table.push(vec![String::from("bob, jr"), String::from("-1.00")]);
+
+        assert_eq!(
+            table.to_text(),
+            "CHILD    BALANCE\nalice      12.50\nbob, jr    -1.00"
+        );
+        assert_eq!(
+            table.to_csv(),
+            "child,balance\nalice,12.50\n\"bob, jr\",-1.00"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use bank_of_dad::model::{amount::Amount, transaction::Transaction};
+use chrono::NaiveDate;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementLine {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    /// The balance after this transaction.
+    pub balance: Amount,
+}
+
+/// A child's transactions between two dates, inclusive and in UTC, with
+/// the balance before, after and as of each one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Statement {
+    pub child_name: String,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub opening_balance: Amount,
+    pub closing_balance: Amount,
+    pub lines: Vec<StatementLine>,
+}
+
+impl Statement {
+    /// `transactions` must be every one of the child's transactions, oldest
+    /// first, so the opening balance includes those before `from`.
+    pub fn build(
+        child_name: &str,
+        transactions: Vec<Transaction>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Statement {
+        let mut opening_balance = Amount::from_pence(0);
+        let mut balance = opening_balance;
+        let mut lines = Vec::new();
+
+        for transaction in transactions {
+            let date = transaction.timestamp.date_naive();
+            if to.is_some_and(|to| date > to) {
+                break;
+            }
+
+            balance = balance + transaction.amount;
+            if from.is_some_and(|from| date < from) {
+                opening_balance = balance;
+            } else {
+                lines.push(StatementLine {
+                    transaction,
+                    balance,
+                });
+            }
+        }
+
+        Statement {
+            child_name: String::from(child_name),
+            from,
+            to,
+            opening_balance,
+            closing_balance: balance,
+            lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bank_of_dad::model::{amount::Amount, transaction::Transaction};
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use super::Statement;
+
+    fn transaction(id: i64, day: u32, amount: i64) -> Transaction {
+        Transaction::new(
+            id,
+            Utc.with_ymd_and_hms(2026, 10, day, 12, 0, 0).unwrap(),
+            String::from("a"),
+            Amount::from_pence(amount),
+            format!("purpose {}", id),
+        )
+    }
+
+    #[test]
+    fn build_test() {
+        let transactions = vec![
+            transaction(1, 1, 500),
+            transaction(2, 2, -100),
+            transaction(3, 3, 250),
+            transaction(4, 4, -50),
+        ];
+
+        let statement = Statement::build(
+            "a",
+            transactions.clone(),
+            NaiveDate::from_ymd_opt(2026, 10, 2),
+            NaiveDate::from_ymd_opt(2026, 10, 3),
+        );
+        assert_eq!(statement.opening_balance, Amount::from_pence(500));
+        assert_eq!(statement.closing_balance, Amount::from_pence(650));
+        assert_eq!(
+            statement
+                .lines
+                .iter()
+                .map(|line| (line.transaction.id, line.balance.to_pence()))
+                .collect::<Vec<(i64, i64)>>(),
+            vec![(2, 400), (3, 650)]
+        );
+
+        let statement = Statement::build("a", transactions, None, None);
+        assert_eq!(statement.opening_balance, Amount::from_pence(0));
+        assert_eq!(statement.closing_balance, Amount::from_pence(600));
+        assert_eq!(statement.lines.len(), 4);
+    }
+}
@@ -0,0 +1,207 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::process::Command;
+
+use bank_of_dad::model::alert::{AlertCondition, NewAlertRule};
+use bank_of_dad::model::amount::Amount;
+use bank_of_dad::model::integrity::IntegrityReport;
+use bank_of_dad::model::transaction::{ChildBalance, Transaction};
+use bank_of_dad::{db::Db, router};
+use log::info;
+
+struct Output {
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+async fn bankctl(args: Vec<String>) -> Output {
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_bankctl"))
+            .args(args)
+            .env_remove("BANKCTL_SERVER")
+            .env_remove("BANKCTL_DB")
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+
+    Output {
+        success: output.status.success(),
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
+
+fn args(target: &[&str], args: &[&str]) -> Vec<String> {
+    target.iter().chain(args).map(|a| a.to_string()).collect()
+}
+
+#[tokio::test]
+async fn bankctl_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db_path: PathBuf =
+        std::env::temp_dir().join(format!("bankctl_e2e_test_{}.db", nanoid::nanoid!(8)));
+    let db = Db::open(&db_path).unwrap();
+    let app = router(db);
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("bankctl_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let server_url = format!("http://{addr}");
+    let http = ["--server", server_url.as_str()];
+    let db_path_arg = db_path.to_str().unwrap();
+    let sqlite = ["--db", db_path_arg];
+
+    //
+    // Over HTTP
+    //
+    let output = bankctl(args(
+        &http,
+        &["give", "a", "5", "pocket money", "-o", "json"],
+    ))
+    .await;
+    assert!(output.success, "{}", output.stderr);
+    let given: Transaction = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(given.amount.to_pence(), 500);
+
+    let output = bankctl(args(&http, &["spend", "a", "1.50", "sweets", "-o", "json"])).await;
+    let spent: Transaction = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(spent.amount.to_pence(), -150);
+
+    let output = bankctl(args(&http, &["spend", "a", "10", "bike"])).await;
+    assert!(!output.success);
+    assert!(
+        output.stderr.contains("(insufficient_funds)"),
+        "{}",
+        output.stderr
+    );
+
+    let output = bankctl(args(&http, &["spend", "a", "1.5", "sweets"])).await;
+    assert!(!output.success);
+    assert!(
+        output.stderr.contains("Failed to parse amount"),
+        "{}",
+        output.stderr
+    );
+
+    let output = bankctl(args(
+        &http,
+        &["reverse", "a", &spent.id.to_string(), "-o", "json"],
+    ))
+    .await;
+    assert!(output.success, "{}", output.stderr);
+    let reversal: Transaction = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(reversal.amount.to_pence(), 150);
+    assert_eq!(reversal.purpose, "Reversal of #2: sweets");
+
+    let output = bankctl(args(&http, &["reverse", "a", &spent.id.to_string()])).await;
+    assert!(!output.success);
+    assert!(output.stderr.contains("(conflict)"), "{}", output.stderr);
+
+    let output = bankctl(args(&http, &["children", "-o", "csv"])).await;
+    assert_eq!(output.stdout, "child,balance\na,5.00\n");
+
+    let output = bankctl(args(&http, &["statement", "a"])).await;
+    let lines: Vec<Vec<&str>> = output
+        .stdout
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(lines.len(), 6, "{}", output.stdout);
+    assert_eq!(lines[1], vec!["Opening", "balance", "0.00"]);
+    assert_eq!(lines[3][2..], ["sweets", "-1.50", "3.50"]);
+    assert_eq!(lines[5], vec!["Closing", "balance", "5.00"]);
+
+    let output = bankctl(args(&http, &["check"])).await;
+    assert!(output.success, "{}", output.stdout);
+    assert!(output.stdout.starts_with("CHECK"));
+
+    //
+    // Directly on the file, which the server shares
+    //
+    let output = bankctl(args(&sqlite, &["give", "b", "2", "birthday"])).await;
+    assert!(output.success, "{}", output.stderr);
+
+    let output = bankctl(args(&sqlite, &["balance", "b", "-o", "json"])).await;
+    let balance: ChildBalance = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(balance.balance.to_pence(), 200);
+
+    let output = bankctl(args(&http, &["children", "-o", "json"])).await;
+    let children: Vec<ChildBalance> = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(
+        children
+            .iter()
+            .map(|c| (c.child_name.as_str(), c.balance.to_pence()))
+            .collect::<Vec<(&str, i64)>>(),
+        vec![("a", 500), ("b", 200)]
+    );
+
+    let output = bankctl(args(&sqlite, &["spend", "b", "3", "toy"])).await;
+    assert!(!output.success);
+    assert!(output.stderr.contains("(insufficient_funds)"));
+
+    //
+    // Spends made directly on the file don't trigger alert rules
+    //
+    let rules_db = Db::open(&db_path).unwrap();
+    let rule = rules_db
+        .create_alert_rule(
+            String::from("b"),
+            NewAlertRule {
+                condition: AlertCondition::BalanceBelow {
+                    amount: Amount::from_pence(500),
+                },
+                cooldown_seconds: 3600,
+            },
+        )
+        .unwrap();
+
+    let output = bankctl(args(&sqlite, &["spend", "b", "1", "sweets"])).await;
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(rules_db.get_alert_rules_for_child("b").unwrap(), vec![rule]);
+
+    //
+    // Integrity problems fail the check
+    //
+    rusqlite::Connection::open(&db_path)
+        .unwrap()
+        .execute(
+            "INSERT INTO transactions (timestamp, child_name, amount, purpose) VALUES (0, 'c', -100, '')",
+            (),
+        )
+        .unwrap();
+
+    let output = bankctl(args(&sqlite, &["check", "-o", "json"])).await;
+    assert!(!output.success);
+    let report: IntegrityReport = serde_json::from_str(&output.stdout).unwrap();
+    assert_eq!(
+        report
+            .checks
+            .iter()
+            .filter(|check| !check.passed())
+            .map(|check| check.name.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["valid_transactions", "balances"]
+    );
+
+    //
+    // Bad targets
+    //
+    let output = bankctl(args(&["--db", "/nonexistent/bank.db"], &["children"])).await;
+    assert!(!output.success);
+    assert_eq!(
+        output.stderr,
+        "bankctl: no database at /nonexistent/bank.db\n"
+    );
+
+    let output = bankctl(args(&[], &["children"])).await;
+    assert!(!output.success);
+
+    std::fs::remove_file(&db_path).ok();
+}
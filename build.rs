@@ -0,0 +1,57 @@
+use std::{
+    env, fs,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Records the git commit and build time for `/version`. Either can be
+/// given instead, with `BANK_OF_DAD_GIT_SHA` for builds outside a checkout
+/// and `SOURCE_DATE_EPOCH` for reproducible builds.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=BANK_OF_DAD_GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let git_sha = env::var("BANK_OF_DAD_GIT_SHA")
+        .ok()
+        .or_else(git_head)
+        .unwrap_or_else(|| String::from("unknown"));
+    println!("cargo:rustc-env=BANK_OF_DAD_GIT_SHA={}", git_sha);
+
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        });
+    println!(
+        "cargo:rustc-env=BANK_OF_DAD_BUILD_TIMESTAMP={}",
+        build_timestamp
+    );
+
+    // Rebuild when HEAD moves, whether it's checked out elsewhere or committed to
+    let git_dir = Path::new(".git");
+    if let Ok(head) = fs::read_to_string(git_dir.join("HEAD")) {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        if let Some(reference) = head.trim().strip_prefix("ref: ") {
+            if git_dir.join(reference).exists() {
+                println!("cargo:rerun-if-changed=.git/{}", reference);
+            }
+        }
+    }
+}
+
+fn git_head() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
@@ -0,0 +1,82 @@
+use std::fmt::Display;
+
+use bank_of_dad::model::error::{ErrorCode, ErrorResponse, FieldError};
+use hyper::StatusCode;
+use tokio_tungstenite::tungstenite;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The server rejected the request with problem details.
+    Api(Box<ErrorResponse>),
+    /// The server responded with something other than what the API
+    /// documents, e.g. a proxy's error page.
+    UnexpectedResponse {
+        status: StatusCode,
+        body: String,
+    },
+    /// A websocket frame that isn't any known notification.
+    UnexpectedMessage(String),
+    InvalidUrl(String),
+    Http(hyper::Error),
+    WebSocket(Box<tungstenite::Error>),
+}
+
+impl ClientError {
+    /// The server's error code, if the server sent one.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::Api(error_response) => Some(error_response.code),
+            _ => None,
+        }
+    }
+
+    pub fn field_errors(&self) -> &[FieldError] {
+        match self {
+            Self::Api(error_response) => &error_response.errors,
+            _ => &[],
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Api(error_response) => write!(
+                f,
+                "{} ({}): {}",
+                error_response.title,
+                error_response.code.as_str(),
+                error_response.detail
+            ),
+            Self::UnexpectedResponse { status, body } => {
+                write!(f, "unexpected {} response: {}", status, body)
+            }
+            Self::UnexpectedMessage(msg) => write!(f, "unexpected websocket message: {}", msg),
+            Self::InvalidUrl(url) => write!(f, "invalid url {}", url),
+            Self::Http(e) => write!(f, "request failed: {}", e),
+            Self::WebSocket(e) => write!(f, "websocket failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            Self::WebSocket(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<hyper::Error> for ClientError {
+    fn from(value: hyper::Error) -> ClientError {
+        ClientError::Http(value)
+    }
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(value: tungstenite::Error) -> ClientError {
+        ClientError::WebSocket(Box::new(value))
+    }
+}
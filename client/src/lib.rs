@@ -0,0 +1,219 @@
+//! A typed async client for the Bank of Dad API, sharing its models with
+//! the server so amounts and transactions round trip exactly.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), bank_of_dad_client::ClientError> {
+//! use bank_of_dad_client::{Amount, BankClient};
+//!
+//! let client = BankClient::new("http://localhost:3000")?;
+//! client.give("alice", Amount::from_pence(250), "chores").await?;
+//! let account = client.get_child("alice").await?;
+//! println!("{} has {}", account.child_name, account.balance);
+//! # Ok(())
+//! # }
+//! ```
+
+use bank_of_dad::{
+    api_version::ApiVersion, handlers::record_transaction::GiveMoney, model::error::ErrorResponse,
+};
+use hyper::{
+    body::Bytes, client::HttpConnector, header, Body, Client, Method, Request, StatusCode, Uri,
+};
+use hyper_rustls::HttpsConnector;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+
+pub use bank_of_dad::handlers::child::ChildAccountResponse;
+pub use bank_of_dad::model::{
+    amount::Amount,
+    integrity::IntegrityReport,
+    transaction::{ChildBalance, Transaction},
+};
+pub use error::ClientError;
+pub use notifications::{Notification, Notifications};
+
+pub mod error;
+pub mod notifications;
+
+/// Characters left alone in a child's name when it's put in a path.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Clone)]
+pub struct BankClient {
+    /// The server root with the API version, e.g. `http://localhost:3000/v1`.
+    api_url: String,
+    http: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl BankClient {
+    /// `base_url` is the server root, e.g. `http://localhost:3000`. Plain
+    /// `ws://` is used for notifications from an `http://` server.
+    pub fn new(base_url: &str) -> Result<BankClient, ClientError> {
+        let base_url = base_url.trim_end_matches('/');
+        match base_url.parse::<Uri>() {
+            Ok(uri) if uri.scheme().is_some() && uri.host().is_some() => {}
+            _ => return Err(ClientError::InvalidUrl(String::from(base_url))),
+        }
+
+        let http = Client::builder().build(
+            hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http()
+                .enable_http1()
+                .build(),
+        );
+
+        Ok(BankClient {
+            api_url: format!("{}{}", base_url, ApiVersion::V1.prefix()),
+            http,
+        })
+    }
+
+    fn child_url(&self, child_name: &str, path: &str) -> String {
+        format!(
+            "{}/child/{}{}",
+            self.api_url,
+            utf8_percent_encode(child_name, PATH_SEGMENT),
+            path
+        )
+    }
+
+    /// The child's balance and every transaction, oldest first. A child
+    /// with no transactions yet has a zero balance.
+    pub async fn get_child(&self, child_name: &str) -> Result<ChildAccountResponse, ClientError> {
+        let request = Request::get(self.child_url(child_name, ""))
+            .body(Body::empty())
+            .unwrap();
+        self.send(request).await
+    }
+
+    pub async fn transactions(&self, child_name: &str) -> Result<Vec<Transaction>, ClientError> {
+        Ok(self.get_child(child_name).await?.transactions)
+    }
+
+    pub async fn give(
+        &self,
+        child_name: &str,
+        amount: Amount,
+        purpose: &str,
+    ) -> Result<Transaction, ClientError> {
+        self.record_transaction(child_name, "/give", amount, purpose)
+            .await
+    }
+
+    /// Fails with `ErrorCode::InsufficientFunds` rather than overdrawing.
+    /// The recorded transaction has a negative amount.
+    pub async fn spend(
+        &self,
+        child_name: &str,
+        amount: Amount,
+        purpose: &str,
+    ) -> Result<Transaction, ClientError> {
+        self.record_transaction(child_name, "/spend", amount, purpose)
+            .await
+    }
+
+    async fn record_transaction(
+        &self,
+        child_name: &str,
+        path: &str,
+        amount: Amount,
+        purpose: &str,
+    ) -> Result<Transaction, ClientError> {
+        let give_money = GiveMoney {
+            amount,
+            purpose: String::from(purpose),
+        };
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.child_url(child_name, path))
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(serde_json::to_vec(&give_money).unwrap()))
+            .unwrap();
+        self.send(request).await
+    }
+
+    /// Records a transaction cancelling out `id`. Fails with
+    /// `ErrorCode::Conflict` if it was already reversed.
+    pub async fn reverse(&self, child_name: &str, id: i64) -> Result<Transaction, ClientError> {
+        let request =
+            Request::post(self.child_url(child_name, &format!("/transactions/{}/reverse", id)))
+                .body(Body::empty())
+                .unwrap();
+        self.send(request).await
+    }
+
+    /// Every child with at least one transaction, by name.
+    pub async fn children(&self) -> Result<Vec<ChildBalance>, ClientError> {
+        let request = Request::get(format!("{}/children", self.api_url))
+            .body(Body::empty())
+            .unwrap();
+        self.send(request).await
+    }
+
+    pub async fn check_integrity(&self) -> Result<IntegrityReport, ClientError> {
+        let request = Request::get(format!("{}/diagnostics/integrity", self.api_url))
+            .body(Body::empty())
+            .unwrap();
+        self.send(request).await
+    }
+
+    /// Opens the child's notifications websocket.
+    pub async fn notifications(&self, child_name: &str) -> Result<Notifications, ClientError> {
+        self.connect(self.child_url(child_name, "/notifications"))
+            .await
+    }
+
+    /// Opens the household notifications websocket, subscribed to
+    /// `children` by the time it returns. More can be added with
+    /// `Notifications::subscribe`.
+    pub async fn household_notifications(
+        &self,
+        children: Vec<String>,
+    ) -> Result<Notifications, ClientError> {
+        let mut notifications = self
+            .connect(format!("{}/notifications", self.api_url))
+            .await?;
+        if !children.is_empty() {
+            notifications.subscribe(children).await?;
+        }
+        Ok(notifications)
+    }
+
+    async fn connect(&self, url: String) -> Result<Notifications, ClientError> {
+        let url = match url.split_once("://") {
+            Some(("https", rest)) => format!("wss://{}", rest),
+            Some((_, rest)) => format!("ws://{}", rest),
+            None => return Err(ClientError::InvalidUrl(url)),
+        };
+
+        let (socket, _response) = tokio_tungstenite::connect_async(url).await?;
+        Ok(Notifications::new(socket))
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: Request<Body>) -> Result<T, ClientError> {
+        let response = self.http.request(request).await?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        if status.is_success() {
+            serde_json::from_slice(&body).map_err(|_| unexpected_response(status, &body))
+        } else {
+            match serde_json::from_slice::<ErrorResponse>(&body) {
+                Ok(error_response) => Err(ClientError::Api(Box::new(error_response))),
+                Err(_) => Err(unexpected_response(status, &body)),
+            }
+        }
+    }
+}
+
+fn unexpected_response(status: StatusCode, body: &Bytes) -> ClientError {
+    ClientError::UnexpectedResponse {
+        status,
+        body: String::from_utf8_lossy(body).into_owned(),
+    }
+}
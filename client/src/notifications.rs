@@ -0,0 +1,190 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use bank_of_dad::model::{
+    alert::{Alert, AlertNotification},
+    transaction::Transaction,
+    websocket_command::{ApprovalRequest, ApprovalRequestNotification, ChildCommandReply},
+    websocket_msg::{HouseholdCommand, HouseholdReply, ResyncRequired},
+};
+use futures::{SinkExt, Stream, StreamExt};
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::error::ClientError;
+
+/// Something that happened to a child's account, as pushed by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notification {
+    Transaction(Transaction),
+    Alert(Alert),
+    ApprovalRequest(ApprovalRequest),
+    /// Notifications were dropped because the client fell behind. Re-read
+    /// the account rather than trust anything built from earlier ones.
+    ResyncRequired,
+}
+
+/// Every JSON text frame the server sends on a notifications socket.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frame {
+    Transaction(Transaction),
+    Alert(AlertNotification),
+    ApprovalRequest(ApprovalRequestNotification),
+    // Only parsed to tell these frames apart
+    ResyncRequired(#[allow(dead_code)] ResyncRequired),
+    CommandReply(#[allow(dead_code)] ChildCommandReply),
+    HouseholdReply(HouseholdReply),
+}
+
+/// Parses a text frame. Command and subscription replies aren't
+/// notifications, so they give None.
+fn parse_notification(text: &str) -> Option<Result<Notification, ClientError>> {
+    match serde_json::from_str::<Frame>(text) {
+        Ok(Frame::Transaction(transaction)) => Some(Ok(Notification::Transaction(transaction))),
+        Ok(Frame::Alert(notification)) => Some(Ok(Notification::Alert(notification.alert))),
+        Ok(Frame::ApprovalRequest(notification)) => Some(Ok(Notification::ApprovalRequest(
+            notification.approval_request,
+        ))),
+        Ok(Frame::ResyncRequired(_)) => Some(Ok(Notification::ResyncRequired)),
+        Ok(Frame::CommandReply(_) | Frame::HouseholdReply(_)) => None,
+        Err(_) => Some(Err(ClientError::UnexpectedMessage(String::from(text)))),
+    }
+}
+
+/// A child's or the household's notifications websocket. Ends when the
+/// server closes it.
+pub struct Notifications {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// Notifications that arrived while waiting for a subscription reply.
+    pending: VecDeque<Notification>,
+}
+
+impl Notifications {
+    pub(crate) fn new(socket: WebSocketStream<MaybeTlsStream<TcpStream>>) -> Notifications {
+        Notifications {
+            socket,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Adds children to a household socket's subscriptions, returning all
+    /// of them once the server has applied it. Only for household sockets.
+    pub async fn subscribe(&mut self, children: Vec<String>) -> Result<Vec<String>, ClientError> {
+        self.send_household_command(HouseholdCommand::Subscribe { children })
+            .await
+    }
+
+    pub async fn unsubscribe(&mut self, children: Vec<String>) -> Result<Vec<String>, ClientError> {
+        self.send_household_command(HouseholdCommand::Unsubscribe { children })
+            .await
+    }
+
+    async fn send_household_command(
+        &mut self,
+        command: HouseholdCommand,
+    ) -> Result<Vec<String>, ClientError> {
+        let text = serde_json::to_string(&command).unwrap();
+        self.socket.send(Message::Text(text)).await?;
+
+        loop {
+            let text = match self.socket.next().await {
+                Some(Ok(Message::Text(text))) => text,
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(ClientError::UnexpectedMessage(String::from(
+                        "socket closed before the subscription was applied",
+                    )))
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.into()),
+            };
+
+            match serde_json::from_str::<Frame>(&text) {
+                Ok(Frame::HouseholdReply(HouseholdReply::Subscriptions(children))) => {
+                    return Ok(children)
+                }
+                Ok(Frame::HouseholdReply(HouseholdReply::Error(e))) => {
+                    return Err(ClientError::UnexpectedMessage(e))
+                }
+                _ => {
+                    if let Some(notification) = parse_notification(&text) {
+                        self.pending.push_back(notification?);
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn close(mut self) -> Result<(), ClientError> {
+        self.socket.close(None).await?;
+        Ok(())
+    }
+}
+
+impl Stream for Notifications {
+    type Item = Result<Notification, ClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(notification) = self.pending.pop_front() {
+            return Poll::Ready(Some(Ok(notification)));
+        }
+
+        loop {
+            let msg = match ready!(self.socket.poll_next_unpin(cx)) {
+                Some(Ok(msg)) => msg,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            };
+
+            match msg {
+                Message::Text(text) => {
+                    if let Some(notification) = parse_notification(&text) {
+                        return Poll::Ready(Some(notification));
+                    }
+                }
+                Message::Close(_) => return Poll::Ready(None),
+                // tungstenite answers pings itself
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_notification, Notification};
+
+    #[test]
+    fn parse_notification_test() {
+        let notification = parse_notification(
+            r#"{"id":3,"timestamp":"2023-11-14T22:13:20Z","child_name":"a","amount":1.50,"purpose":"chores"}"#,
+        );
+        match notification {
+            Some(Ok(Notification::Transaction(transaction))) => {
+                assert_eq!(transaction.id, 3);
+                assert_eq!(transaction.amount.to_pence(), 150);
+            }
+            other => panic!("expected a transaction, got {:?}", other),
+        }
+
+        assert_eq!(
+            parse_notification(r#"{"resync_required":true}"#)
+                .unwrap()
+                .unwrap(),
+            Notification::ResyncRequired
+        );
+        assert!(matches!(
+            parse_notification(
+                r#"{"approval_request":{"child_name":"a","amount":2.00,"purpose":"toy"}}"#
+            ),
+            Some(Ok(Notification::ApprovalRequest(_)))
+        ));
+        assert!(parse_notification(r#"{"id":"1","result":{"balance":1.50}}"#).is_none());
+        assert!(parse_notification(r#"{"subscriptions":["a","b"]}"#).is_none());
+        assert!(parse_notification(r#"{"hello":"world"}"#).unwrap().is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use bank_of_dad::model::error::{ErrorCode, FieldErrorCode};
+use bank_of_dad::{db::Db, router};
+use bank_of_dad_client::{Amount, BankClient, ClientError, Notification};
+use futures::StreamExt;
+use log::info;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn client_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("client_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let client = BankClient::new(&format!("http://{addr}/")).unwrap();
+
+    //
+    // A new child
+    //
+    let account = client.get_child("a").await.unwrap();
+    assert_eq!(account.child_name, "a");
+    assert_eq!(account.balance, Amount::from_pence(0));
+    assert!(account.transactions.is_empty());
+
+    let mut notifications = client.notifications("a").await.unwrap();
+
+    //
+    // Give and spend
+    //
+    let given = client
+        .give("a", Amount::from_pence(250), "chores")
+        .await
+        .unwrap();
+    assert_eq!(given.child_name, "a");
+    assert_eq!(given.amount, Amount::from_pence(250));
+
+    let spent = client
+        .spend("a", Amount::from_pence(100), "sweets")
+        .await
+        .unwrap();
+    assert_eq!(spent.amount, Amount::from_pence(-100));
+
+    let account = client.get_child("a").await.unwrap();
+    assert_eq!(account.balance, Amount::from_pence(150));
+    // Stored timestamps are to the millisecond, so compare ids
+    assert_eq!(
+        account
+            .transactions
+            .iter()
+            .map(|t| t.id)
+            .collect::<Vec<i64>>(),
+        vec![given.id, spent.id]
+    );
+    assert_eq!(
+        client.transactions("a").await.unwrap(),
+        account.transactions
+    );
+
+    for expected in [given, spent] {
+        let notification = timeout(Duration::from_secs(1), notifications.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification, Notification::Transaction(expected));
+    }
+    notifications.close().await.unwrap();
+
+    //
+    // Household notifications only carry subscribed children
+    //
+    let mut household = client
+        .household_notifications(vec![String::from("b")])
+        .await
+        .unwrap();
+    assert_eq!(
+        household.subscribe(vec![String::from("c")]).await.unwrap(),
+        vec![String::from("b"), String::from("c")]
+    );
+
+    client
+        .give("a", Amount::from_pence(1), "unsubscribed")
+        .await
+        .unwrap();
+    let for_c = client.give("c", Amount::from_pence(2), "c").await.unwrap();
+    let for_b = client.give("b", Amount::from_pence(3), "b").await.unwrap();
+    for expected in [for_c, for_b] {
+        let notification = timeout(Duration::from_secs(1), household.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(notification, Notification::Transaction(expected));
+    }
+    household.close().await.unwrap();
+
+    //
+    // Names are escaped in paths
+    //
+    let transaction = client
+        .give("Mary Jane/2", Amount::from_pence(1), "penny")
+        .await
+        .unwrap();
+    assert_eq!(transaction.child_name, "Mary Jane/2");
+    assert_eq!(
+        client.get_child("Mary Jane/2").await.unwrap().balance,
+        Amount::from_pence(1)
+    );
+
+    //
+    // Problem details come back typed
+    //
+    let error = client
+        .spend("a", Amount::from_pence(1000), "bike")
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Some(ErrorCode::InsufficientFunds));
+
+    let error = client
+        .give("a", Amount::from_pence(0), "")
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Some(ErrorCode::ValidationFailed));
+    assert_eq!(
+        error
+            .field_errors()
+            .iter()
+            .map(|e| (e.field.as_str(), e.code))
+            .collect::<Vec<(&str, FieldErrorCode)>>(),
+        vec![
+            ("amount", FieldErrorCode::NotPositive),
+            ("purpose", FieldErrorCode::Empty)
+        ]
+    );
+    match error {
+        ClientError::Api(error_response) => assert_eq!(error_response.status, 400),
+        other => panic!("expected an api error, got {:?}", other),
+    }
+
+    //
+    // Anything else is still an error
+    //
+    assert!(matches!(
+        BankClient::new("not a url"),
+        Err(ClientError::InvalidUrl(_))
+    ));
+
+    let unreachable = BankClient::new("http://127.0.0.1:1").unwrap();
+    assert!(matches!(
+        unreachable.get_child("a").await,
+        Err(ClientError::Http(_))
+    ));
+}
@@ -0,0 +1,59 @@
+use crate::{
+    db::Db,
+    model::{
+        alert::{Alert, SpendContext},
+        error::ApiError,
+        transaction::Transaction,
+    },
+};
+
+/// Checks a just-recorded transaction against the child's alert rules and
+/// returns an alert for each rule that holds and isn't cooling down. Only
+/// spends are checked; reversing a give takes money out of the account but
+/// isn't the child spending it.
+pub fn evaluate_alert_rules(
+    db: &Db,
+    transaction: &Transaction,
+    is_reversal: bool,
+) -> Result<Vec<Alert>, ApiError> {
+    if is_reversal || !transaction.amount.is_negative() {
+        return Ok(Vec::new());
+    }
+
+    let rules = db.get_alert_rules_for_child(&transaction.child_name)?;
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let start_of_day = transaction
+        .timestamp
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    let context = SpendContext {
+        spent: transaction.amount.negate(),
+        balance_after: db.get_account_balance_for_child(transaction.child_name.clone())?,
+        spends_today: db.count_spends_for_child_since(&transaction.child_name, start_of_day)?,
+    };
+
+    let mut alerts = Vec::new();
+    for rule in rules {
+        let Some(message) = rule.condition.check(&transaction.child_name, &context) else {
+            continue;
+        };
+
+        if db.trigger_alert_rule(rule.id, transaction.timestamp)? {
+            alerts.push(Alert {
+                rule_id: rule.id,
+                child_name: rule.child_name,
+                condition: rule.condition,
+                message,
+                transaction_id: transaction.id,
+                triggered_at: transaction.timestamp,
+            });
+        }
+    }
+
+    Ok(alerts)
+}
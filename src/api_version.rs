@@ -0,0 +1,20 @@
+/// A version of the HTTP API, mounted under its own path prefix. The
+/// unprefixed paths are deprecated aliases for `V1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+}
+
+impl ApiVersion {
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::V1 => "/v1",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+        }
+    }
+}
@@ -1,49 +1,262 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use log::info;
-use tokio::sync::RwLock;
+use futures::future::join_all;
+use tokio::{
+    sync::{mpsc::error::TrySendError, watch, RwLock},
+    time::Instant,
+};
+use tracing::{info, instrument, warn, Span};
 
 use crate::{
+    config::Features,
     db::Db,
-    model::websocket_msg::{ActiveWebsocket, WebSocketMsg},
+    middleware::metrics::metrics,
+    model::{
+        event::BankEvent,
+        health::{Readiness, ReadinessCheck},
+        websocket_msg::{
+            ActiveWebsocket, CloseReason, HouseholdCommand, Subscription, WebSocketMsg,
+            WebsocketCounts,
+        },
+    },
+    notifications::EventListener,
 };
 
+/// Server pings every `ping_interval`. A socket that sends nothing back,
+/// pongs included, for `ping_interval + pong_timeout` is closed and
+/// deregistered. Each listener, socket or stream, queues up to
+/// `channel_capacity` messages before it's told to resync.
+#[derive(Debug, Clone, Copy)]
+pub struct WebsocketConfig {
+    pub ping_interval: Duration,
+    pub pong_timeout: Duration,
+    pub channel_capacity: usize,
+}
+
+impl Default for WebsocketConfig {
+    fn default() -> Self {
+        WebsocketConfig {
+            ping_interval: Duration::from_secs(30),
+            pong_timeout: Duration::from_secs(10),
+            channel_capacity: 32,
+        }
+    }
+}
+
 pub struct AppState {
     db: Arc<Db>,
-    open_websockets: RwLock<Vec<ActiveWebsocket>>,
+    open_websockets: RwLock<HashMap<String, ActiveWebsocket>>,
+    websocket_config: WebsocketConfig,
+    features: Features,
+    event_listeners: Vec<Arc<dyn EventListener>>,
+    shutting_down: AtomicBool,
+    closing: watch::Sender<Option<CloseReason>>,
 }
 
 impl AppState {
     pub fn new(db: Db) -> AppState {
         AppState {
             db: Arc::new(db),
-            open_websockets: RwLock::new(Vec::new()),
+            open_websockets: RwLock::new(HashMap::new()),
+            websocket_config: WebsocketConfig::default(),
+            features: Features::default(),
+            event_listeners: Vec::new(),
+            shutting_down: AtomicBool::new(false),
+            closing: watch::channel(None).0,
         }
     }
 
+    pub fn with_event_listener(mut self, event_listener: Arc<dyn EventListener>) -> AppState {
+        self.event_listeners.push(event_listener);
+        self
+    }
+
+    pub fn with_websocket_config(self, websocket_config: WebsocketConfig) -> AppState {
+        AppState {
+            websocket_config,
+            ..self
+        }
+    }
+
+    pub fn with_features(self, features: Features) -> AppState {
+        AppState { features, ..self }
+    }
+
     pub fn get_db(&self) -> Arc<Db> {
         return self.db.clone();
     }
 
+    pub fn get_websocket_config(&self) -> WebsocketConfig {
+        self.websocket_config
+    }
+
+    pub fn get_features(&self) -> Features {
+        self.features
+    }
+
+    /// Marks the server unready so it's taken out of rotation while it
+    /// drains.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::Release);
+    }
+
+    /// Runs every readiness check. Reads the database, so don't call it
+    /// more than a supervisor would.
+    pub fn check_readiness(&self) -> Readiness {
+        let mut checks = vec![
+            ReadinessCheck::new(
+                "shutdown",
+                self.shutting_down
+                    .load(Ordering::Acquire)
+                    .then(|| String::from("Shutting down")),
+            ),
+            ReadinessCheck::new("database", self.db.check_ready().err()),
+        ];
+        for event_listener in &self.event_listeners {
+            checks.push(ReadinessCheck::new(
+                event_listener.name(),
+                (!event_listener.is_running()).then(|| String::from("Background task has stopped")),
+            ));
+        }
+
+        Readiness::new(checks)
+    }
+
     pub async fn register_open_websocket(&self, websocket: ActiveWebsocket) {
-        let mut websockets: tokio::sync::RwLockWriteGuard<'_, Vec<ActiveWebsocket>> =
-            self.open_websockets.write().await;
-        let child_name = websocket.get_child_name();
-        websockets.push(websocket);
+        let mut websockets = self.open_websockets.write().await;
+        let subscription = websocket.get_subscription();
+        websockets.insert(websocket.get_id(), websocket);
         info!(
             "registered websocket for {}. {} websockets registered",
-            child_name,
+            subscription,
             websockets.len()
         )
     }
 
     pub async fn deregister_open_websocket(&self, id: String) {
-        let mut websockets: tokio::sync::RwLockWriteGuard<'_, Vec<ActiveWebsocket>> =
-            self.open_websockets.write().await;
-        websockets.retain(|ws| ws.get_id().ne(&id));
+        let mut websockets = self.open_websockets.write().await;
+        websockets.remove(&id);
         info!("{} websockets registered", websockets.len())
     }
 
+    pub async fn count_open_websockets(&self) -> WebsocketCounts {
+        let websockets = self.open_websockets.read().await;
+        let mut counts = WebsocketCounts {
+            total: websockets.len(),
+            ..WebsocketCounts::default()
+        };
+
+        for websocket in websockets.values() {
+            match websocket.get_subscription() {
+                Subscription::Child(child_name) => {
+                    *counts.by_child.entry(child_name).or_default() += 1;
+                }
+                Subscription::Children(child_names) => {
+                    for child_name in child_names {
+                        *counts.by_child.entry(child_name).or_default() += 1;
+                    }
+                }
+                Subscription::AllChildren => counts.all_children += 1,
+            }
+        }
+
+        counts
+    }
+
+    /// For sockets that aren't registered for fan-out, such as GraphQL
+    /// websockets: changes to the reason once `close_open_websockets` is
+    /// called. The socket counts as open until the receiver is dropped.
+    pub fn watch_for_close(&self) -> watch::Receiver<Option<CloseReason>> {
+        self.closing.subscribe()
+    }
+
+    /// Asks every registered listener and watching socket to close, waiting
+    /// for room in the channel of any that are behind. Listeners deregister
+    /// themselves once closed.
+    pub async fn close_open_websockets(&self, reason: CloseReason) {
+        self.closing.send_replace(Some(reason));
+
+        let websockets: Vec<ActiveWebsocket> = self
+            .open_websockets
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect();
+        info!("closing {} websockets: {:?}", websockets.len(), reason);
+
+        join_all(websockets.iter().map(|websocket| async move {
+            if websocket
+                .send_message(WebSocketMsg::CloseSocket(reason))
+                .await
+                .is_err()
+            {
+                info!(
+                    websocket_id = %websocket.get_id(),
+                    "closed, awaiting deregistration"
+                );
+            }
+        }))
+        .await;
+    }
+
+    /// Waits until no listeners are registered and no sockets are watching
+    /// for close, or the deadline passes. Returns how many are still open.
+    pub async fn wait_for_websockets_closed(&self, deadline: Instant) -> usize {
+        loop {
+            let open = self.open_websockets.read().await.len() + self.closing.receiver_count();
+            if open == 0 || Instant::now() >= deadline {
+                return open;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Changes which children a registered household websocket receives
+    /// messages for. Returns the updated subscription, or None if the
+    /// websocket isn't registered or can't change its subscription.
+    pub async fn apply_household_command(
+        &self,
+        id: String,
+        command: &HouseholdCommand,
+    ) -> Option<Subscription> {
+        let mut websockets = self.open_websockets.write().await;
+        let websocket = websockets.get_mut(&id)?;
+
+        if websocket.apply_household_command(command) {
+            info!("subscriptions updated: {}", websocket.get_subscription());
+            Some(websocket.get_subscription())
+        } else {
+            None
+        }
+    }
+
+    /// The single source of account events: fans the event out to open
+    /// websockets and event streams, then hands it to every notification
+    /// channel.
+    pub async fn publish_event(&self, event: BankEvent) {
+        self.queue_messages_to_active_websockets_for_child(
+            event.child_name().to_string(),
+            event.to_websocket_msg(),
+        )
+        .await;
+
+        for event_listener in &self.event_listeners {
+            event_listener.on_event(&event);
+        }
+    }
+
+    /// Fans a message out to every listener subscribed to the child. Sends
+    /// never wait, a listener whose channel is full misses the message and
+    /// is told to resync instead of holding up the caller.
+    #[instrument(name = "fan_out", skip_all, fields(queued))]
     pub async fn queue_messages_to_active_websockets_for_child(
         &self,
         child_name: String,
@@ -51,19 +264,120 @@ impl AppState {
     ) {
         let websockets = self.open_websockets.read().await;
 
-        let eligible_websockets: Vec<&ActiveWebsocket> = websockets
-            .iter()
-            .filter(|ws| ws.get_child_name() == child_name)
-            .collect();
+        let eligible_websockets = websockets
+            .values()
+            .filter(|ws| ws.get_subscription().matches(&child_name));
 
+        let mut queued = 0;
         for eligible_websocket in eligible_websockets {
-            info!(
-                "sending message to websocket {}",
-                eligible_websocket.get_id()
-            );
-            let _ = eligible_websocket.send_message(msg.clone()).await;
+            match eligible_websocket.queue_message(msg.clone()) {
+                Ok(()) => queued += 1,
+                Err(TrySendError::Full(_)) => {
+                    metrics().fan_out_dropped.inc();
+                    warn!(
+                        websocket_id = %eligible_websocket.get_id(),
+                        "lagging, message dropped and resync required"
+                    )
+                }
+                Err(TrySendError::Closed(_)) => info!(
+                    websocket_id = %eligible_websocket.get_id(),
+                    "closed, awaiting deregistration"
+                ),
+            }
         }
+        metrics().fan_out_messages.inc_by(queued);
+        Span::current().record("queued", queued);
+        info!("queued message for {} to {} websockets", child_name, queued);
+    }
+}
+
+/// Deregisters a listener from fan-out when dropped, for listeners such as
+/// event streams whose lifetime is whenever axum drops their response.
+pub struct ListenerRegistration {
+    app_state: Arc<AppState>,
+    id: String,
+}
 
-        return;
+impl ListenerRegistration {
+    pub fn new(app_state: Arc<AppState>, id: String) -> ListenerRegistration {
+        ListenerRegistration { app_state, id }
+    }
+}
+
+impl Drop for ListenerRegistration {
+    fn drop(&mut self) {
+        let app_state = self.app_state.clone();
+        let id = self.id.clone();
+        tokio::spawn(async move { app_state.deregister_open_websocket(id).await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chrono::Utc;
+
+    use super::AppState;
+    use crate::{
+        db::Db,
+        model::{
+            amount::Amount,
+            transaction::Transaction,
+            websocket_msg::{ActiveWebsocket, Subscription, WebSocketMsg},
+        },
+    };
+
+    fn transaction_msg(id: i64) -> WebSocketMsg {
+        WebSocketMsg::Transaction(Transaction::new(
+            id,
+            Utc::now(),
+            String::from("a"),
+            Amount::from_pence(100),
+            String::from("test"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn lagging_websocket_does_not_block_fan_out_test() {
+        let app_state = AppState::new(Db::new());
+
+        let (stalled_sender, _stalled_receiver) = tokio::sync::mpsc::channel(4);
+        let stalled = ActiveWebsocket::new(
+            String::from("stalled"),
+            Subscription::Child(String::from("a")),
+            stalled_sender,
+        );
+        app_state.register_open_websocket(stalled.clone()).await;
+
+        let (reading_sender, mut reading_receiver) = tokio::sync::mpsc::channel(64);
+        let reading = ActiveWebsocket::new(
+            String::from("reading"),
+            Subscription::AllChildren,
+            reading_sender,
+        );
+        app_state.register_open_websocket(reading.clone()).await;
+
+        let started = Instant::now();
+        for id in 0..10 {
+            app_state
+                .queue_messages_to_active_websockets_for_child(
+                    String::from("a"),
+                    transaction_msg(id),
+                )
+                .await;
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        assert!(stalled.take_resync_required());
+        assert!(!stalled.take_resync_required());
+
+        assert!(!reading.take_resync_required());
+        for id in 0..10 {
+            match reading_receiver.recv().await {
+                Some(WebSocketMsg::Transaction(t)) => assert_eq!(t.id, id),
+                other => panic!("unexpected message {other:?}"),
+            }
+        }
     }
 }
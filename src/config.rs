@@ -0,0 +1,969 @@
+use std::{
+    env,
+    fmt::Display,
+    fs, io,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use axum::http::{header, HeaderName, HeaderValue, Method, Uri};
+use chrono::Weekday;
+use clap::{Parser, ValueEnum};
+use lettre::message::Mailbox;
+use serde::{Deserialize, Deserializer};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    appstate::WebsocketConfig,
+    notifications::{email::EmailConfig, mqtt::MqttConfig},
+};
+
+/// Command line flags for the server. Each overrides the same setting from
+/// the environment and the configuration file.
+#[derive(Debug, Default, Parser)]
+#[command(version, about = "Runs the Bank of Dad server")]
+pub struct Args {
+    /// TOML configuration file
+    #[arg(long, env = "BANK_OF_DAD_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
+
+    /// Port to listen on
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// SQLite database file, created if missing. In memory if not given
+    #[arg(long)]
+    pub db: Option<PathBuf>,
+
+    #[arg(long)]
+    pub log_format: Option<LogFormat>,
+
+    /// Log filter, e.g. `info` or `info,bank_of_dad=debug`
+    #[arg(long)]
+    pub log_level: Option<String>,
+
+    /// Origin allowed to call the API from a browser, e.g.
+    /// `https://bank.example`, or `*` for any. Repeat for more
+    #[arg(long = "allowed-origin")]
+    pub allowed_origins: Vec<String>,
+}
+
+/// Server settings. Defaults are overridden by the configuration file, then
+/// by `BANK_OF_DAD_*` environment variables, then by command line flags.
+/// See `bank_of_dad.example.toml` for every setting.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerSection,
+    pub database: DatabaseSection,
+    pub log: LogSection,
+    pub websocket: WebsocketSection,
+    pub features: Features,
+    /// Email is off unless there's an `[email]` section or
+    /// `BANK_OF_DAD_SMTP_HOST` is set.
+    pub email: Option<EmailSection>,
+    /// MQTT is off unless there's an `[mqtt]` section or
+    /// `BANK_OF_DAD_MQTT_HOST` is set.
+    pub mqtt: Option<MqttSection>,
+    /// Span export is off unless there's a `[telemetry]` section or
+    /// `BANK_OF_DAD_OTLP_ENDPOINT` is set, and needs the `otel` feature.
+    pub telemetry: Option<TelemetrySection>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Origins browsers may call the API from. Same origin only when empty.
+    pub allowed_origins: Vec<String>,
+    /// How long to keep serving on shutdown after going unready, so
+    /// supervisors stop sending requests before the listener closes.
+    pub shutdown_grace_seconds: u64,
+    /// How long to wait on shutdown for requests and websockets to finish.
+    pub shutdown_timeout_seconds: u64,
+}
+
+impl Default for ServerSection {
+    fn default() -> Self {
+        ServerSection {
+            bind: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            port: 3000,
+            allowed_origins: Vec::new(),
+            shutdown_grace_seconds: 5,
+            shutdown_timeout_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSection {
+    /// In memory when not set.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <LogFormat as ValueEnum>::from_str(s, true)
+            .map_err(|_| String::from("expected text or json"))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSection {
+    pub format: LogFormat,
+    /// A `tracing_subscriber` filter, e.g. `info,bank_of_dad=debug`.
+    pub level: String,
+}
+
+impl Default for LogSection {
+    fn default() -> Self {
+        LogSection {
+            format: LogFormat::Text,
+            level: String::from("info"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebsocketSection {
+    /// Messages queued per listener before it's told to resync.
+    pub channel_capacity: usize,
+    pub ping_interval_seconds: u64,
+    pub pong_timeout_seconds: u64,
+}
+
+impl Default for WebsocketSection {
+    fn default() -> Self {
+        let defaults = WebsocketConfig::default();
+        WebsocketSection {
+            channel_capacity: defaults.channel_capacity,
+            ping_interval_seconds: defaults.ping_interval.as_secs(),
+            pong_timeout_seconds: defaults.pong_timeout.as_secs(),
+        }
+    }
+}
+
+impl WebsocketSection {
+    pub fn websocket_config(&self) -> WebsocketConfig {
+        WebsocketConfig {
+            ping_interval: Duration::from_secs(self.ping_interval_seconds),
+            pong_timeout: Duration::from_secs(self.pong_timeout_seconds),
+            channel_capacity: self.channel_capacity,
+        }
+    }
+}
+
+/// Optional parts of the server, all on by default. A feature that's off
+/// has no routes, so its paths are 404s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// The web dashboard at `/`.
+    pub dashboard: bool,
+    pub graphql: bool,
+    /// Webhook management and delivery.
+    pub webhooks: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            dashboard: true,
+            graphql: true,
+            webhooks: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailSection {
+    pub smtp_host: String,
+    /// 587 with STARTTLS, otherwise 25.
+    #[serde(default)]
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub smtp_username: Option<String>,
+    #[serde(default)]
+    pub smtp_password: Option<String>,
+    #[serde(default)]
+    pub starttls: bool,
+    #[serde(default, deserialize_with = "deserialize_from_str")]
+    pub from: Option<Mailbox>,
+    #[serde(default)]
+    pub digest_weekday: Option<Weekday>,
+    /// UTC.
+    #[serde(default)]
+    pub digest_hour: Option<u32>,
+}
+
+impl EmailSection {
+    fn new(smtp_host: String) -> EmailSection {
+        EmailSection {
+            smtp_host,
+            smtp_port: None,
+            smtp_username: None,
+            smtp_password: None,
+            starttls: false,
+            from: None,
+            digest_weekday: None,
+            digest_hour: None,
+        }
+    }
+
+    pub fn email_config(&self) -> EmailConfig {
+        let port = self
+            .smtp_port
+            .unwrap_or(if self.starttls { 587 } else { 25 });
+
+        let mut config = EmailConfig::new(&self.smtp_host, port);
+        config.starttls = self.starttls;
+        if let (Some(username), Some(password)) = (&self.smtp_username, &self.smtp_password) {
+            config.smtp_credentials = Some((username.clone(), password.clone()));
+        }
+        if let Some(from) = &self.from {
+            config.from = from.clone();
+        }
+        if let Some(digest_weekday) = self.digest_weekday {
+            config.digest_weekday = digest_weekday;
+        }
+        if let Some(digest_hour) = self.digest_hour {
+            config.digest_hour = digest_hour;
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttSection {
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub topic_prefix: Option<String>,
+    /// Home Assistant discovery prefix. An empty prefix turns discovery off.
+    #[serde(default)]
+    pub discovery_prefix: Option<String>,
+}
+
+impl MqttSection {
+    fn new(host: String) -> MqttSection {
+        MqttSection {
+            host,
+            port: None,
+            username: None,
+            password: None,
+            client_id: None,
+            topic_prefix: None,
+            discovery_prefix: None,
+        }
+    }
+
+    pub fn mqtt_config(&self) -> MqttConfig {
+        let mut config = MqttConfig::new(&self.host, self.port.unwrap_or(1883));
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            config.credentials = Some((username.clone(), password.clone()));
+        }
+        if let Some(client_id) = &self.client_id {
+            config.client_id = client_id.clone();
+        }
+        if let Some(topic_prefix) = &self.topic_prefix {
+            config.topic_prefix = topic_prefix.clone();
+        }
+        if let Some(discovery_prefix) = &self.discovery_prefix {
+            config.discovery_prefix = Some(discovery_prefix.clone()).filter(|p| !p.is_empty());
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetrySection {
+    /// An OpenTelemetry collector's OTLP/gRPC endpoint, e.g.
+    /// `http://localhost:4317`.
+    pub otlp_endpoint: String,
+    #[serde(default)]
+    pub service_name: Option<String>,
+}
+
+impl TelemetrySection {
+    fn new(otlp_endpoint: String) -> TelemetrySection {
+        TelemetrySection {
+            otlp_endpoint,
+            service_name: None,
+        }
+    }
+
+    pub fn service_name(&self) -> &str {
+        self.service_name.as_deref().unwrap_or("bank_of_dad")
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file couldn't be read.
+    Read(PathBuf, io::Error),
+    /// The configuration file isn't TOML, or has unknown or mistyped
+    /// settings.
+    Parse(PathBuf, toml::de::Error),
+    /// An environment variable that can't be parsed as its setting.
+    Env { name: String, reason: String },
+    /// Settings that parsed but can't be used, each as `setting: reason`.
+    Invalid(Vec<String>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+            Self::Parse(path, e) => write!(f, "invalid configuration in {}: {}", path.display(), e),
+            Self::Env { name, reason } => write!(f, "invalid {}: {}", name, reason),
+            Self::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(_, e) => Some(e),
+            Self::Parse(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the file named by `--config` or `BANK_OF_DAD_CONFIG`, if any,
+    /// applies the environment and then the flags, and validates the result.
+    pub fn load(args: Args) -> Result<Config, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        config.apply_args(args);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Overrides settings with `BANK_OF_DAD_*` variables, as looked up by
+    /// `var`. Allowed origins are comma separated.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        let env = EnvVars(var);
+
+        env.parse("BANK_OF_DAD_BIND", &mut self.server.bind)?;
+        env.parse("BANK_OF_DAD_PORT", &mut self.server.port)?;
+        if let Some(origins) = env.get("BANK_OF_DAD_ALLOWED_ORIGINS") {
+            self.server.allowed_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        env.parse(
+            "BANK_OF_DAD_SHUTDOWN_GRACE_SECONDS",
+            &mut self.server.shutdown_grace_seconds,
+        )?;
+        env.parse(
+            "BANK_OF_DAD_SHUTDOWN_TIMEOUT_SECONDS",
+            &mut self.server.shutdown_timeout_seconds,
+        )?;
+        if let Some(path) = env.get("BANK_OF_DAD_DB") {
+            self.database.path = Some(PathBuf::from(path));
+        }
+        env.parse("BANK_OF_DAD_LOG_FORMAT", &mut self.log.format)?;
+        env.parse("BANK_OF_DAD_LOG_LEVEL", &mut self.log.level)?;
+        env.parse(
+            "BANK_OF_DAD_WEBSOCKET_CHANNEL_CAPACITY",
+            &mut self.websocket.channel_capacity,
+        )?;
+        env.parse(
+            "BANK_OF_DAD_WEBSOCKET_PING_INTERVAL_SECONDS",
+            &mut self.websocket.ping_interval_seconds,
+        )?;
+        env.parse(
+            "BANK_OF_DAD_WEBSOCKET_PONG_TIMEOUT_SECONDS",
+            &mut self.websocket.pong_timeout_seconds,
+        )?;
+        env.parse(
+            "BANK_OF_DAD_FEATURE_DASHBOARD",
+            &mut self.features.dashboard,
+        )?;
+        env.parse("BANK_OF_DAD_FEATURE_GRAPHQL", &mut self.features.graphql)?;
+        env.parse("BANK_OF_DAD_FEATURE_WEBHOOKS", &mut self.features.webhooks)?;
+
+        if let Some(smtp_host) = env.get("BANK_OF_DAD_SMTP_HOST") {
+            match &mut self.email {
+                Some(email) => email.smtp_host = smtp_host,
+                None => self.email = Some(EmailSection::new(smtp_host)),
+            }
+        }
+        if let Some(email) = &mut self.email {
+            env.parse_some("BANK_OF_DAD_SMTP_PORT", &mut email.smtp_port)?;
+            env.parse_some("BANK_OF_DAD_SMTP_USERNAME", &mut email.smtp_username)?;
+            env.parse_some("BANK_OF_DAD_SMTP_PASSWORD", &mut email.smtp_password)?;
+            env.parse("BANK_OF_DAD_SMTP_STARTTLS", &mut email.starttls)?;
+            env.parse_some("BANK_OF_DAD_EMAIL_FROM", &mut email.from)?;
+        }
+
+        if let Some(host) = env.get("BANK_OF_DAD_MQTT_HOST") {
+            match &mut self.mqtt {
+                Some(mqtt) => mqtt.host = host,
+                None => self.mqtt = Some(MqttSection::new(host)),
+            }
+        }
+        if let Some(mqtt) = &mut self.mqtt {
+            env.parse_some("BANK_OF_DAD_MQTT_PORT", &mut mqtt.port)?;
+            env.parse_some("BANK_OF_DAD_MQTT_USERNAME", &mut mqtt.username)?;
+            env.parse_some("BANK_OF_DAD_MQTT_PASSWORD", &mut mqtt.password)?;
+            env.parse_some("BANK_OF_DAD_MQTT_CLIENT_ID", &mut mqtt.client_id)?;
+            env.parse_some("BANK_OF_DAD_MQTT_TOPIC_PREFIX", &mut mqtt.topic_prefix)?;
+            env.parse_some(
+                "BANK_OF_DAD_MQTT_DISCOVERY_PREFIX",
+                &mut mqtt.discovery_prefix,
+            )?;
+        }
+
+        if let Some(otlp_endpoint) = env.get("BANK_OF_DAD_OTLP_ENDPOINT") {
+            match &mut self.telemetry {
+                Some(telemetry) => telemetry.otlp_endpoint = otlp_endpoint,
+                None => self.telemetry = Some(TelemetrySection::new(otlp_endpoint)),
+            }
+        }
+        if let Some(telemetry) = &mut self.telemetry {
+            env.parse_some("BANK_OF_DAD_OTLP_SERVICE_NAME", &mut telemetry.service_name)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn apply_args(&mut self, args: Args) {
+        if let Some(bind) = args.bind {
+            self.server.bind = bind;
+        }
+        if let Some(port) = args.port {
+            self.server.port = port;
+        }
+        if !args.allowed_origins.is_empty() {
+            self.server.allowed_origins = args.allowed_origins;
+        }
+        if let Some(path) = args.db {
+            self.database.path = Some(path);
+        }
+        if let Some(format) = args.log_format {
+            self.log.format = format;
+        }
+        if let Some(level) = args.log_level {
+            self.log.level = level;
+        }
+    }
+
+    /// Checks everything that would otherwise fail, or quietly misbehave,
+    /// once the server is running. Reports every problem at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        for origin in &self.server.allowed_origins {
+            if let Err(reason) = check_origin(origin) {
+                problems.push(format!("server.allowed_origins: {origin:?} {reason}"));
+            }
+        }
+        if self.server.allowed_origins.len() > 1
+            && self.server.allowed_origins.iter().any(|o| o == "*")
+        {
+            problems.push(String::from(
+                "server.allowed_origins: \"*\" allows any origin so can't be combined with others",
+            ));
+        }
+
+        if let Some(parent) = self.database.path.as_ref().and_then(|p| p.parent()) {
+            if !parent.as_os_str().is_empty() && !parent.is_dir() {
+                problems.push(format!(
+                    "database.path: directory {} doesn't exist",
+                    parent.display()
+                ));
+            }
+        }
+
+        if let Err(e) = EnvFilter::try_new(&self.log.level) {
+            problems.push(format!("log.level: {:?} {}", self.log.level, e));
+        }
+
+        if self.websocket.channel_capacity == 0 {
+            problems.push(String::from(
+                "websocket.channel_capacity: must be at least 1",
+            ));
+        }
+        if self.websocket.ping_interval_seconds == 0 {
+            problems.push(String::from(
+                "websocket.ping_interval_seconds: must be at least 1",
+            ));
+        }
+        if self.websocket.pong_timeout_seconds == 0 {
+            problems.push(String::from(
+                "websocket.pong_timeout_seconds: must be at least 1",
+            ));
+        }
+
+        if let Some(email) = &self.email {
+            if email.smtp_host.is_empty() {
+                problems.push(String::from("email.smtp_host: must not be empty"));
+            }
+            if email.smtp_username.is_some() != email.smtp_password.is_some() {
+                problems.push(String::from(
+                    "email.smtp_username: needs email.smtp_password, and the other way round",
+                ));
+            }
+            if email.digest_hour.is_some_and(|hour| hour > 23) {
+                problems.push(String::from("email.digest_hour: must be 0 to 23"));
+            }
+        }
+
+        if let Some(mqtt) = &self.mqtt {
+            if mqtt.host.is_empty() {
+                problems.push(String::from("mqtt.host: must not be empty"));
+            }
+            if mqtt.username.is_some() != mqtt.password.is_some() {
+                problems.push(String::from(
+                    "mqtt.username: needs mqtt.password, and the other way round",
+                ));
+            }
+            if let Some(topic_prefix) = &mqtt.topic_prefix {
+                if topic_prefix.is_empty() || topic_prefix.contains(['+', '#']) {
+                    problems.push(format!(
+                        "mqtt.topic_prefix: {topic_prefix:?} must be a topic without wildcards"
+                    ));
+                }
+            }
+        }
+
+        if let Some(telemetry) = &self.telemetry {
+            if !cfg!(feature = "otel") {
+                problems.push(String::from(
+                    "telemetry: this build can't export spans, rebuild with --features otel",
+                ));
+            } else if let Err(reason) = check_endpoint(&telemetry.otlp_endpoint) {
+                problems.push(format!(
+                    "telemetry.otlp_endpoint: {:?} {}",
+                    telemetry.otlp_endpoint, reason
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.server.bind, self.server.port)
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.server.shutdown_grace_seconds)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.server.shutdown_timeout_seconds)
+    }
+
+    /// CORS for the allowed origins, or None to leave browsers at same
+    /// origin only.
+    pub fn cors_layer(&self) -> Option<CorsLayer> {
+        let allowed_origins = &self.server.allowed_origins;
+        if allowed_origins.is_empty() {
+            return None;
+        }
+
+        let allow_origin = if allowed_origins.iter().any(|o| o == "*") {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                allowed_origins
+                    .iter()
+                    .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+            )
+        };
+
+        Some(
+            CorsLayer::new()
+                .allow_origin(allow_origin)
+                .allow_methods([Method::GET, Method::POST, Method::DELETE])
+                .allow_headers([
+                    header::CONTENT_TYPE,
+                    HeaderName::from_static("idempotency-key"),
+                    HeaderName::from_static("last-event-id"),
+                    HeaderName::from_static("traceparent"),
+                ])
+                .expose_headers([
+                    HeaderName::from_static("x-request-id"),
+                    HeaderName::from_static("idempotent-replayed"),
+                    HeaderName::from_static("deprecation"),
+                    HeaderName::from_static("sunset"),
+                ]),
+        )
+    }
+}
+
+/// An origin is a scheme and host, and optionally a port, and nothing else.
+fn check_origin(origin: &str) -> Result<(), &'static str> {
+    if origin == "*" {
+        return Ok(());
+    }
+
+    let uri: Uri = origin.parse().map_err(|_| "isn't a URL")?;
+    match uri.scheme_str() {
+        Some("http" | "https") => {}
+        _ => return Err("must start with http:// or https://"),
+    }
+    if uri.host().is_none() {
+        return Err("has no host");
+    }
+    if origin.ends_with('/') || uri.path() != "/" || uri.query().is_some() {
+        return Err("must not have a path, e.g. https://bank.example");
+    }
+    Ok(())
+}
+
+/// A collector is a scheme, host and port, e.g. `http://localhost:4317`.
+fn check_endpoint(endpoint: &str) -> Result<(), &'static str> {
+    let uri: Uri = endpoint.parse().map_err(|_| "isn't a URL")?;
+    match uri.scheme_str() {
+        Some("http" | "https") => {}
+        _ => return Err("must start with http:// or https://"),
+    }
+    if uri.host().is_none() {
+        return Err("has no host");
+    }
+    Ok(())
+}
+
+struct EnvVars<F>(F);
+
+impl<F: Fn(&str) -> Option<String>> EnvVars<F> {
+    fn get(&self, name: &str) -> Option<String> {
+        (self.0)(name)
+    }
+
+    fn parse<T>(&self, name: &str, setting: &mut T) -> Result<(), ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = self.get(name) {
+            *setting = parse_env(name, value)?;
+        }
+        Ok(())
+    }
+
+    fn parse_some<T>(&self, name: &str, setting: &mut Option<T>) -> Result<(), ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = self.get(name) {
+            *setting = Some(parse_env(name, value)?);
+        }
+        Ok(())
+    }
+}
+
+fn parse_env<T>(name: &str, value: String) -> Result<T, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.parse().map_err(|e: T::Err| ConfigError::Env {
+        name: String::from(name),
+        reason: format!("{:?} {}", value, e),
+    })
+}
+
+/// For settings whose types have `FromStr` but not `Deserialize`.
+fn deserialize_from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| value.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, net::Ipv4Addr, path::PathBuf, time::Duration};
+
+    use chrono::Weekday;
+    use clap::Parser;
+
+    use super::{Args, Config, ConfigError, LogFormat};
+
+    const EXAMPLE: &str = include_str!("../bank_of_dad.example.toml");
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn invalid_settings(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Err(ConfigError::Invalid(problems)) => problems,
+            other => panic!("expected invalid configuration, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn example_file_test() {
+        let config: Config = toml::from_str(EXAMPLE).unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.server.port, 3000);
+        assert_eq!(config.websocket.websocket_config().channel_capacity, 32);
+
+        let email = config.email.unwrap().email_config();
+        assert_eq!(email.smtp_port, 587);
+        assert_eq!(email.digest_weekday, Weekday::Sun);
+        assert_eq!(email.from.to_string(), "Bank of Dad <bank@example.com>");
+
+        let mqtt = config.mqtt.unwrap().mqtt_config();
+        assert_eq!(mqtt.discovery_prefix.as_deref(), Some("homeassistant"));
+    }
+
+    #[test]
+    fn defaults_test() {
+        let config = Config::default();
+        config.validate().unwrap();
+
+        assert_eq!(config.socket_addr().to_string(), "[::]:3000");
+        assert_eq!(config.shutdown_grace(), Duration::from_secs(5));
+        assert_eq!(config.shutdown_timeout(), Duration::from_secs(10));
+        assert_eq!(config.database.path, None);
+        assert_eq!(config.log.level, "info");
+        assert!(config.features.dashboard && config.features.graphql);
+        assert!(config.email.is_none() && config.mqtt.is_none());
+        assert!(config.cors_layer().is_none());
+    }
+
+    #[test]
+    fn precedence_test() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [server]
+            port = 4000
+            allowed_origins = ["https://file.example"]
+
+            [log]
+            level = "debug"
+            format = "json"
+            "#,
+        )
+        .unwrap();
+
+        config
+            .apply_env(env(&[
+                ("BANK_OF_DAD_PORT", "5000"),
+                ("BANK_OF_DAD_LOG_LEVEL", "warn"),
+                ("BANK_OF_DAD_DB", "env.db"),
+            ]))
+            .unwrap();
+        assert_eq!(config.server.port, 5000);
+        assert_eq!(config.log.level, "warn");
+        assert_eq!(config.log.format, LogFormat::Json);
+
+        config.apply_args(Args::parse_from([
+            "bank_of_dad",
+            "--port",
+            "6000",
+            "--bind",
+            "127.0.0.1",
+            "--allowed-origin",
+            "https://a.example",
+            "--allowed-origin",
+            "http://b.example:8080",
+        ]));
+        assert_eq!(config.socket_addr(), (Ipv4Addr::LOCALHOST, 6000).into());
+        assert_eq!(
+            config.server.allowed_origins,
+            ["https://a.example", "http://b.example:8080"]
+        );
+        assert_eq!(config.database.path, Some(PathBuf::from("env.db")));
+        assert_eq!(config.log.level, "warn");
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn notifications_from_env_test() {
+        let mut config = Config::default();
+        config
+            .apply_env(env(&[
+                ("BANK_OF_DAD_SMTP_PORT", "2525"),
+                ("BANK_OF_DAD_MQTT_HOST", "broker"),
+                ("BANK_OF_DAD_MQTT_DISCOVERY_PREFIX", ""),
+            ]))
+            .unwrap();
+        // No host, no email
+        assert!(config.email.is_none());
+        let mqtt = config.mqtt.as_ref().unwrap().mqtt_config();
+        assert_eq!((mqtt.host.as_str(), mqtt.port), ("broker", 1883));
+        assert_eq!(mqtt.discovery_prefix, None);
+
+        config
+            .apply_env(env(&[
+                ("BANK_OF_DAD_SMTP_HOST", "smtp"),
+                ("BANK_OF_DAD_SMTP_STARTTLS", "true"),
+                ("BANK_OF_DAD_SMTP_USERNAME", "dad"),
+                ("BANK_OF_DAD_SMTP_PASSWORD", "secret"),
+            ]))
+            .unwrap();
+        let email = config.email.as_ref().unwrap().email_config();
+        assert_eq!((email.smtp_host.as_str(), email.smtp_port), ("smtp", 587));
+        assert_eq!(
+            email.smtp_credentials,
+            Some((String::from("dad"), String::from("secret")))
+        );
+    }
+
+    #[test]
+    fn telemetry_test() {
+        let mut config = Config::default();
+        config
+            .apply_env(env(&[
+                ("BANK_OF_DAD_OTLP_ENDPOINT", "http://collector:4317"),
+                ("BANK_OF_DAD_OTLP_SERVICE_NAME", "bank"),
+            ]))
+            .unwrap();
+        let telemetry = config.telemetry.as_ref().unwrap();
+        assert_eq!(telemetry.otlp_endpoint, "http://collector:4317");
+        assert_eq!(telemetry.service_name(), "bank");
+
+        if cfg!(feature = "otel") {
+            config.validate().unwrap();
+            config.telemetry.as_mut().unwrap().otlp_endpoint = String::from("collector:4317");
+            assert_eq!(
+                invalid_settings(&config),
+                ["telemetry.otlp_endpoint: \"collector:4317\" must start with http:// or https://"]
+            );
+        } else {
+            assert_eq!(
+                invalid_settings(&config),
+                ["telemetry: this build can't export spans, rebuild with --features otel"]
+            );
+        }
+    }
+
+    #[test]
+    fn unparseable_settings_test() {
+        let error = Config::default()
+            .apply_env(env(&[("BANK_OF_DAD_PORT", "lots")]))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid BANK_OF_DAD_PORT: \"lots\" invalid digit found in string"
+        );
+
+        let error = Config::default()
+            .apply_env(env(&[("BANK_OF_DAD_LOG_FORMAT", "xml")]))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid BANK_OF_DAD_LOG_FORMAT: \"xml\" expected text or json"
+        );
+
+        let error = toml::from_str::<Config>("[server]\nprot = 3000\n").unwrap_err();
+        assert!(
+            error.to_string().contains("unknown field `prot`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn validation_test() {
+        let mut config = Config::default();
+        config.server.allowed_origins = vec![
+            String::from("https://ok.example"),
+            String::from("https://bad.example/app"),
+            String::from("bank.example"),
+            String::from("*"),
+        ];
+        config.database.path = Some(PathBuf::from("/no/such/directory/bank.db"));
+        config.log.level = String::from("info,bank_of_dad=loud");
+        config.websocket.channel_capacity = 0;
+
+        let problems = invalid_settings(&config);
+        assert_eq!(problems.len(), 6, "{problems:#?}");
+        assert!(problems[0].starts_with("server.allowed_origins: \"https://bad.example/app\""));
+        assert!(problems[1].starts_with("server.allowed_origins: \"bank.example\""));
+        assert!(problems[2].contains("can't be combined"));
+        assert!(problems[3].starts_with("database.path:"));
+        assert!(problems[4].starts_with("log.level:"));
+        assert_eq!(
+            problems[5],
+            "websocket.channel_capacity: must be at least 1"
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+            [email]
+            smtp_host = "smtp"
+            smtp_username = "dad"
+            digest_hour = 24
+
+            [mqtt]
+            host = "broker"
+            topic_prefix = "bank/#"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(invalid_settings(&config).len(), 3);
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Path,
+    headers::{ETag, HeaderMapExt, IfNoneMatch},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router, TypedHeader,
+};
+use rust_embed::RustEmbed;
+
+use crate::appstate::AppState;
+
+/// The web dashboard, built into the binary from `web/`. It only talks to
+/// the API, so it needs no server-side state of its own.
+#[derive(RustEmbed)]
+#[folder = "web/"]
+struct Assets;
+
+/// How long browsers may use an icon without asking again. Icons rarely
+/// change and a stale one is harmless.
+const ICON_MAX_AGE_SECONDS: u32 = 7 * 24 * 60 * 60;
+
+/// Serves the dashboard at `/` with its files under `/static`. The web app
+/// manifest and service worker are at the root, as a service worker only
+/// controls pages under its own path. These aren't API paths, so they
+/// aren't versioned and unknown files get a plain 404 rather than problem
+/// details.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(index))
+        .route("/manifest.webmanifest", get(manifest))
+        .route("/sw.js", get(service_worker))
+        .route("/static/*path", get(static_file))
+}
+
+async fn index(if_none_match: Option<TypedHeader<IfNoneMatch>>) -> Response {
+    serve("index.html", if_none_match)
+}
+
+async fn manifest(if_none_match: Option<TypedHeader<IfNoneMatch>>) -> Response {
+    serve("manifest.webmanifest", if_none_match)
+}
+
+async fn service_worker(if_none_match: Option<TypedHeader<IfNoneMatch>>) -> Response {
+    serve("sw.js", if_none_match)
+}
+
+async fn static_file(
+    Path(path): Path<String>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Response {
+    serve(&path, if_none_match)
+}
+
+/// Files are revalidated on every use, which is cheap with their ETags, so
+/// a new build never mixes with an old cached copy. That includes the
+/// service worker, which browsers would otherwise keep for up to a day.
+fn cache_control(path: &str) -> HeaderValue {
+    if path.starts_with("icons/") {
+        HeaderValue::from_str(&format!("public, max-age={}", ICON_MAX_AGE_SECONDS)).unwrap()
+    } else {
+        HeaderValue::from_static("no-cache")
+    }
+}
+
+fn serve(path: &str, if_none_match: Option<TypedHeader<IfNoneMatch>>) -> Response {
+    let Some(file) = Assets::get(path) else {
+        return (StatusCode::NOT_FOUND, "Not found").into_response();
+    };
+
+    let etag: ETag = format!("\"{}\"", hex::encode(file.metadata.sha256_hash()))
+        .parse()
+        .unwrap();
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(etag.clone());
+    headers.insert(header::CACHE_CONTROL, cache_control(path));
+
+    if let Some(TypedHeader(if_none_match)) = if_none_match {
+        if !if_none_match.precondition_passes(&etag) {
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+    }
+
+    if let Ok(content_type) = HeaderValue::from_str(file.metadata.mimetype()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    (headers, file.data.into_owned()).into_response()
+}
@@ -1,51 +1,213 @@
-use std::sync::{Mutex, MutexGuard};
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
 
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, Rows};
+use tracing::instrument;
 
-use crate::model::{amount::Amount, error::ApiError, transaction::Transaction};
+use crate::{
+    middleware::metrics::metrics,
+    model::{
+        amount::Amount,
+        error::ApiError,
+        transaction::{ChildBalance, LedgerVersion, Transaction},
+    },
+};
 
+mod alerts;
+mod email;
+mod idempotency;
+mod integrity;
+mod reversals;
+mod webhooks;
+
+/// Schema changes in the order they were introduced. The database's
+/// `user_version` records how many have been applied.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE transactions (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        child_name TEXT NOT NULL,
+        amount INTEGER NOT NULL,
+        purpose TEXT NOT NULL
+    )",
+    "CREATE TABLE webhooks (
+        id INTEGER PRIMARY KEY,
+        url TEXT NOT NULL,
+        event_types TEXT NOT NULL,
+        secret TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE webhook_deliveries (
+        id INTEGER PRIMARY KEY,
+        webhook_id INTEGER NOT NULL REFERENCES webhooks(id),
+        event_type TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        next_attempt_at INTEGER NOT NULL,
+        last_error TEXT,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX webhook_deliveries_due ON webhook_deliveries (status, next_attempt_at);",
+    "CREATE TABLE email_preferences (
+        id INTEGER PRIMARY KEY,
+        email TEXT NOT NULL UNIQUE,
+        children TEXT NOT NULL,
+        spend_threshold INTEGER,
+        weekly_digest INTEGER NOT NULL
+    )",
+    "CREATE TABLE alert_rules (
+        id INTEGER PRIMARY KEY,
+        child_name TEXT NOT NULL,
+        condition TEXT NOT NULL,
+        cooldown_seconds INTEGER NOT NULL,
+        last_triggered_at INTEGER
+    );
+    CREATE INDEX alert_rules_child_name ON alert_rules (child_name);
+    ALTER TABLE email_preferences ADD COLUMN alerts INTEGER NOT NULL DEFAULT 1;",
+    "CREATE INDEX transactions_child_name ON transactions (child_name, id)",
+    "CREATE TABLE transaction_reversals (
+        transaction_id INTEGER PRIMARY KEY REFERENCES transactions(id),
+        reversal_id INTEGER NOT NULL UNIQUE REFERENCES transactions(id)
+    )",
+    "CREATE TABLE idempotency_keys (
+        key TEXT PRIMARY KEY,
+        fingerprint TEXT NOT NULL,
+        transaction_id INTEGER NOT NULL REFERENCES transactions(id),
+        created_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE weekly_digests (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        last_digest_at INTEGER NOT NULL
+    )",
+    "CREATE INDEX idempotency_keys_created_at ON idempotency_keys (created_at)",
+];
+
+/// How long to wait for another process, e.g. bankctl, to release a lock.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Each query runs in a span named after the method, so it shows up under
+/// the request that made it when spans are exported.
 pub struct Db {
     connection: Mutex<Connection>,
 }
 
+impl Default for Db {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Db {
     pub fn new() -> Db {
-        let connection = Connection::open_in_memory().unwrap();
-
-        connection
-            .execute(
-                "CREATE TABLE transactions (
-                    id INTEGER PRIMARY KEY,
-                    timestamp INTEGER NOT NULL,
-                    child_name TEXT NOT NULL,
-                    amount INTEGER NOT NULL,
-                    purpose TEXT NOT NULL
-                )",
-                (),
-            )
-            .unwrap();
+        let mut connection = Connection::open_in_memory().unwrap();
+        Self::migrate(&mut connection).unwrap();
 
         Db {
             connection: Mutex::new(connection),
         }
     }
 
+    /// Opens, creating if need be, and migrates a database file. Other
+    /// processes may read it while it's open.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Db, rusqlite::Error> {
+        let mut connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Self::migrate(&mut connection)?;
+
+        Ok(Db {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Locks the connection, recording how long that took.
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        let started = Instant::now();
+        let conn = self.connection.lock().unwrap();
+        metrics()
+            .db_lock_wait
+            .observe(started.elapsed().as_secs_f64());
+        conn
+    }
+
+    /// A cheap query that fails unless the database can be read and is
+    /// fully migrated.
+    #[instrument(skip_all)]
+    pub fn check_ready(&self) -> Result<(), String> {
+        let conn = self
+            .connection
+            .lock()
+            .map_err(|_| String::from("Connection is poisoned"))?;
+        let user_version: usize = conn
+            .query_row("SELECT user_version FROM pragma_user_version", (), |r| {
+                r.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+
+        if user_version == MIGRATIONS.len() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Schema is at version {}, expected {}",
+                user_version,
+                MIGRATIONS.len()
+            ))
+        }
+    }
+
+    /// Copies the write-ahead log into the database file and empties it, so
+    /// the file is complete on its own. Waits for any transaction in flight.
+    /// Does nothing for an in-memory database.
+    #[instrument(skip_all)]
+    pub fn checkpoint(&self) -> Result<(), rusqlite::Error> {
+        let conn = self.lock();
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", (), |_| Ok(()))
+    }
+
+    fn migrate(connection: &mut Connection) -> Result<(), rusqlite::Error> {
+        let applied: usize =
+            connection.query_row("SELECT user_version FROM pragma_user_version", (), |r| {
+                r.get(0)
+            })?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            let tx = connection.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
     pub fn record_transaction_for_child(
         &self,
         transaction: Transaction,
     ) -> Result<Transaction, ApiError> {
-        let conn = self.connection.lock().unwrap();
+        let conn = self.lock();
+        Self::record_transaction_internal(&conn, transaction)
+    }
 
+    /// Refuses to take the child's balance negative.
+    fn record_transaction_internal(
+        conn: &Connection,
+        transaction: Transaction,
+    ) -> Result<Transaction, ApiError> {
         if transaction.amount.is_negative() {
-            let new_balance = Self::get_account_balance_for_child_internal(
-                &conn,
-                transaction.child_name.clone(),
-            )? + transaction.amount;
+            let new_balance =
+                Self::get_account_balance_for_child_internal(conn, transaction.child_name.clone())?
+                    + transaction.amount;
 
             if new_balance.is_negative() {
-                return Err(ApiError::InputFailedValidation(format!(
+                metrics().overdrafts_rejected.inc();
+                return Err(ApiError::InsufficientFunds(format!(
                     "Transaction will take account {} negative",
                     transaction.child_name
                 )));
@@ -64,26 +226,159 @@ impl Db {
 
         let transaction_id = conn.last_insert_rowid();
         let transaction_result = Transaction {
-            id: u8::try_from(transaction_id).unwrap(),
+            id: transaction_id,
             ..transaction
         };
 
         Ok(transaction_result)
     }
 
+    #[instrument(skip_all)]
     pub fn get_transactions_for_child(
         &self,
         child_name: String,
     ) -> Result<Vec<Transaction>, ApiError> {
-        let conn = self.connection.lock().unwrap();
-        let mut transactions: Vec<Transaction> = Vec::new();
+        let conn = self.lock();
 
         let mut stmt = conn
             .prepare(
                 "SELECT id, timestamp, child_name, amount, purpose FROM transactions WHERE child_name = ?1",
             )?;
 
-        let mut rows = stmt.query(params![child_name]).unwrap();
+        let rows = stmt.query(params![child_name])?;
+        Self::collect_transactions(rows)
+    }
+
+    /// The child's `limit` most recent transactions, oldest first.
+    #[instrument(skip_all)]
+    pub fn get_last_transactions_for_child(
+        &self,
+        child_name: String,
+        limit: usize,
+    ) -> Result<Vec<Transaction>, ApiError> {
+        let conn = self.lock();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, timestamp, child_name, amount, purpose FROM transactions WHERE child_name = ?1 ORDER BY id DESC LIMIT ?2",
+            )?;
+
+        let rows = stmt.query(params![child_name, limit])?;
+        let mut transactions = Self::collect_transactions(rows)?;
+        transactions.reverse();
+        Ok(transactions)
+    }
+
+    /// Transactions recorded after `after_id`, optionally restricted to one child
+    /// and to the first `limit`. Used to replay events a reconnecting listener
+    /// missed.
+    #[instrument(skip_all)]
+    pub fn get_transactions_after(
+        &self,
+        child_name: Option<String>,
+        after_id: i64,
+        limit: Option<usize>,
+    ) -> Result<Vec<Transaction>, ApiError> {
+        let conn = self.lock();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, timestamp, child_name, amount, purpose FROM transactions WHERE id > ?1 AND (?2 IS NULL OR child_name = ?2) ORDER BY id LIMIT ?3",
+            )?;
+
+        // A negative limit is no limit
+        let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let rows = stmt.query(params![after_id, child_name, limit])?;
+        Self::collect_transactions(rows)
+    }
+
+    /// Every transaction recorded at or after `since`, oldest first.
+    #[instrument(skip_all)]
+    pub fn get_transactions_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Transaction>, ApiError> {
+        let conn = self.lock();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, timestamp, child_name, amount, purpose FROM transactions WHERE timestamp >= ?1 ORDER BY id",
+            )?;
+
+        let rows = stmt.query(params![since.timestamp_millis()])?;
+        Self::collect_transactions(rows)
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_child_names(&self) -> Result<Vec<String>, ApiError> {
+        let conn = self.lock();
+
+        let mut stmt =
+            conn.prepare("SELECT DISTINCT child_name FROM transactions ORDER BY child_name")?;
+        let child_names = stmt
+            .query_map((), |r| r.get::<usize, String>(0))?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+
+        Ok(child_names)
+    }
+
+    /// The first `limit` children with at least one transaction, by name,
+    /// after `after_name`.
+    #[instrument(skip_all)]
+    pub fn get_child_names_after(
+        &self,
+        after_name: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<String>, ApiError> {
+        let conn = self.lock();
+
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT child_name FROM transactions WHERE (?1 IS NULL OR child_name > ?1) ORDER BY child_name LIMIT ?2",
+        )?;
+        let child_names = stmt
+            .query_map(params![after_name, limit], |r| r.get::<usize, String>(0))?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+
+        Ok(child_names)
+    }
+
+    /// Every child with at least one transaction, by name.
+    #[instrument(skip_all)]
+    pub fn get_child_balances(&self) -> Result<Vec<ChildBalance>, ApiError> {
+        let conn = self.lock();
+
+        let mut stmt = conn.prepare(
+            "SELECT child_name, SUM(amount) FROM transactions GROUP BY child_name ORDER BY child_name",
+        )?;
+        let child_balances = stmt
+            .query_map((), |r| {
+                Ok(ChildBalance {
+                    child_name: r.get(0)?,
+                    balance: Amount::deserialize_from_db(r.get::<usize, i64>(1)?),
+                })
+            })?
+            .collect::<Result<Vec<ChildBalance>, rusqlite::Error>>()?;
+
+        Ok(child_balances)
+    }
+
+    /// The sum of every child's balance.
+    #[instrument(skip_all)]
+    pub fn get_total_balance(&self) -> Result<Amount, ApiError> {
+        let conn = self.lock();
+
+        let total: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(amount), 0) FROM transactions",
+            (),
+            |r| r.get(0),
+        )?;
+
+        Ok(Amount::deserialize_from_db(total))
+    }
+
+    fn collect_transactions(mut rows: Rows<'_>) -> Result<Vec<Transaction>, ApiError> {
+        let mut transactions: Vec<Transaction> = Vec::new();
+
         while let Ok(Some(row)) = rows.next() {
             let timestamp = Utc
                 .timestamp_millis_opt(row.get::<usize, i64>(1)?)
@@ -91,7 +386,7 @@ impl Db {
                 .unwrap();
 
             transactions.push(Transaction::new(
-                row.get::<usize, u8>(0)?,
+                row.get::<usize, i64>(0)?,
                 timestamp,
                 row.get::<usize, String>(2)?,
                 Amount::deserialize_from_db(row.get::<usize, i64>(3)?),
@@ -102,13 +397,40 @@ impl Db {
         return Ok(transactions);
     }
 
+    /// The latest transaction and balance for a child, without reading
+    /// every transaction. Cheap enough to answer conditional requests.
+    #[instrument(skip_all)]
+    pub fn get_ledger_version_for_child(
+        &self,
+        child_name: &str,
+    ) -> Result<LedgerVersion, ApiError> {
+        let conn = self.lock();
+
+        let ledger_version = conn.query_row(
+            "SELECT MAX(id), COALESCE(SUM(amount), 0), MAX(timestamp) FROM transactions WHERE child_name = ?1",
+            params![child_name],
+            |row| {
+                Ok(LedgerVersion {
+                    latest_transaction_id: row.get::<usize, Option<i64>>(0)?,
+                    balance: Amount::deserialize_from_db(row.get::<usize, i64>(1)?),
+                    last_modified: row
+                        .get::<usize, Option<i64>>(2)?
+                        .and_then(|timestamp| Utc.timestamp_millis_opt(timestamp).single()),
+                })
+            },
+        )?;
+
+        Ok(ledger_version)
+    }
+
+    #[instrument(skip_all)]
     pub fn get_account_balance_for_child(&self, child_name: String) -> Result<Amount, ApiError> {
-        let conn = self.connection.lock().unwrap();
+        let conn = self.lock();
         Self::get_account_balance_for_child_internal(&conn, child_name)
     }
 
     fn get_account_balance_for_child_internal(
-        conn: &MutexGuard<'_, Connection>,
+        conn: &Connection,
         child_name: String,
     ) -> Result<Amount, ApiError> {
         let balance_amount: Amount = conn.query_row(
@@ -0,0 +1,115 @@
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, Row};
+use tracing::instrument;
+
+use crate::model::{
+    alert::{AlertRule, NewAlertRule},
+    error::ApiError,
+};
+
+use super::Db;
+
+fn alert_rule_from_row(row: &Row<'_>) -> Result<AlertRule, rusqlite::Error> {
+    let condition = row.get::<usize, String>(2)?;
+
+    Ok(AlertRule {
+        id: row.get(0)?,
+        child_name: row.get(1)?,
+        condition: serde_json::from_str(&condition).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        cooldown_seconds: row.get(3)?,
+        last_triggered_at: row
+            .get::<usize, Option<i64>>(4)?
+            .map(|millis| Utc.timestamp_millis_opt(millis).single().unwrap()),
+    })
+}
+
+impl Db {
+    #[instrument(skip_all)]
+    pub fn create_alert_rule(
+        &self,
+        child_name: String,
+        new_rule: NewAlertRule,
+    ) -> Result<AlertRule, ApiError> {
+        let conn = self.lock();
+
+        conn.execute(
+            "INSERT INTO alert_rules (child_name, condition, cooldown_seconds) VALUES (?1, ?2, ?3)",
+            params![
+                child_name,
+                serde_json::to_string(&new_rule.condition).unwrap(),
+                new_rule.cooldown_seconds
+            ],
+        )?;
+
+        Ok(AlertRule {
+            id: conn.last_insert_rowid(),
+            child_name,
+            condition: new_rule.condition,
+            cooldown_seconds: new_rule.cooldown_seconds,
+            last_triggered_at: None,
+        })
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_alert_rules_for_child(&self, child_name: &str) -> Result<Vec<AlertRule>, ApiError> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id, child_name, condition, cooldown_seconds, last_triggered_at FROM alert_rules WHERE child_name = ?1 ORDER BY id",
+        )?;
+
+        let rules = stmt
+            .query_map(params![child_name], alert_rule_from_row)?
+            .collect::<Result<Vec<AlertRule>, rusqlite::Error>>()?;
+
+        Ok(rules)
+    }
+
+    /// Returns false if the child has no such rule.
+    #[instrument(skip_all)]
+    pub fn delete_alert_rule(&self, child_name: &str, id: i64) -> Result<bool, ApiError> {
+        let conn = self.lock();
+        let deleted = conn.execute(
+            "DELETE FROM alert_rules WHERE id = ?1 AND child_name = ?2",
+            params![id, child_name],
+        )?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Marks the rule as triggered at `now` unless it is still cooling down
+    /// from the last time. Returns whether it was triggered, so concurrent
+    /// spends can't both fire the same rule.
+    #[instrument(skip_all)]
+    pub fn trigger_alert_rule(&self, id: i64, now: DateTime<Utc>) -> Result<bool, ApiError> {
+        let conn = self.lock();
+        let now = now.timestamp_millis();
+        let updated = conn.execute(
+            "UPDATE alert_rules SET last_triggered_at = ?2
+             WHERE id = ?1 AND (last_triggered_at IS NULL OR last_triggered_at + cooldown_seconds * 1000 <= ?2)",
+            params![id, now],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    /// Reversals of gives aren't spends, so aren't counted.
+    #[instrument(skip_all)]
+    pub fn count_spends_for_child_since(
+        &self,
+        child_name: &str,
+        since: DateTime<Utc>,
+    ) -> Result<u32, ApiError> {
+        let conn = self.lock();
+        let count = conn.query_row(
+            "SELECT COUNT(*) FROM transactions
+             WHERE child_name = ?1 AND amount < 0 AND timestamp >= ?2
+             AND id NOT IN (SELECT reversal_id FROM transaction_reversals)",
+            params![child_name, since.timestamp_millis()],
+            |r| r.get(0),
+        )?;
+
+        Ok(count)
+    }
+}
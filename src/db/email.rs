@@ -0,0 +1,104 @@
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, OptionalExtension, Row};
+use tracing::instrument;
+
+use crate::model::{
+    amount::Amount,
+    email::{EmailPreferences, NewEmailPreferences},
+    error::ApiError,
+};
+
+use super::Db;
+
+fn email_preferences_from_row(row: &Row<'_>) -> Result<EmailPreferences, rusqlite::Error> {
+    Ok(EmailPreferences {
+        id: row.get(0)?,
+        email: row.get(1)?,
+        children: serde_json::from_str(&row.get::<usize, String>(2)?).unwrap_or_default(),
+        spend_threshold: row
+            .get::<usize, Option<i64>>(3)?
+            .map(Amount::deserialize_from_db),
+        weekly_digest: row.get(4)?,
+        alerts: row.get(5)?,
+    })
+}
+
+impl Db {
+    /// Creates or replaces the preferences for `new_preferences.email`.
+    #[instrument(skip_all)]
+    pub fn upsert_email_preferences(
+        &self,
+        new_preferences: NewEmailPreferences,
+    ) -> Result<EmailPreferences, ApiError> {
+        let conn = self.lock();
+
+        let preferences = conn.query_row(
+            "INSERT INTO email_preferences (email, children, spend_threshold, weekly_digest, alerts) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (email) DO UPDATE SET children = excluded.children, spend_threshold = excluded.spend_threshold, weekly_digest = excluded.weekly_digest, alerts = excluded.alerts
+             RETURNING id, email, children, spend_threshold, weekly_digest, alerts",
+            params![
+                new_preferences.email,
+                serde_json::to_string(&new_preferences.children).unwrap(),
+                new_preferences
+                    .spend_threshold
+                    .map(|a| a.serialize_for_db()),
+                new_preferences.weekly_digest,
+                new_preferences.alerts
+            ],
+            email_preferences_from_row,
+        )?;
+
+        Ok(preferences)
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_email_preferences(&self) -> Result<Vec<EmailPreferences>, ApiError> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id, email, children, spend_threshold, weekly_digest, alerts FROM email_preferences ORDER BY id",
+        )?;
+
+        let preferences = stmt
+            .query_map((), email_preferences_from_row)?
+            .collect::<Result<Vec<EmailPreferences>, rusqlite::Error>>()?;
+
+        Ok(preferences)
+    }
+
+    /// Returns false if there were no such preferences.
+    #[instrument(skip_all)]
+    pub fn delete_email_preferences(&self, id: i64) -> Result<bool, ApiError> {
+        let conn = self.lock();
+        let deleted = conn.execute("DELETE FROM email_preferences WHERE id = ?1", params![id])?;
+
+        Ok(deleted > 0)
+    }
+
+    /// The time of the last weekly digest the scheduler sent, if it has
+    /// ever run.
+    #[instrument(skip_all)]
+    pub fn get_last_digest_at(&self) -> Result<Option<DateTime<Utc>>, ApiError> {
+        let conn = self.lock();
+        let last_digest_at: Option<i64> = conn
+            .query_row(
+                "SELECT last_digest_at FROM weekly_digests WHERE id = 1",
+                (),
+                |r| r.get(0),
+            )
+            .optional()?;
+
+        Ok(last_digest_at.and_then(|millis| Utc.timestamp_millis_opt(millis).single()))
+    }
+
+    #[instrument(skip_all)]
+    pub fn set_last_digest_at(&self, last_digest_at: DateTime<Utc>) -> Result<(), ApiError> {
+        let conn = self.lock();
+        conn.execute(
+            "INSERT INTO weekly_digests (id, last_digest_at) VALUES (1, ?1)
+             ON CONFLICT (id) DO UPDATE SET last_digest_at = excluded.last_digest_at",
+            params![last_digest_at.timestamp_millis()],
+        )?;
+
+        Ok(())
+    }
+}
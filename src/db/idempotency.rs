@@ -0,0 +1,118 @@
+use chrono::Duration;
+use rusqlite::{params, OptionalExtension};
+use tracing::instrument;
+
+use crate::model::{error::ApiError, transaction::Transaction};
+
+use super::Db;
+
+/// How long a key is remembered for. Keys older than this are forgotten as
+/// new ones are recorded, so a request retried after it is recorded again.
+pub const IDEMPOTENCY_KEY_RETENTION: Duration = Duration::hours(24);
+
+impl Db {
+    /// Records a transaction unless `key` was already used, in which case
+    /// the transaction it recorded then is returned instead, with true.
+    /// `fingerprint` identifies the request, a key sent again with a
+    /// different request is a conflict rather than a replay. Keys are kept
+    /// for `IDEMPOTENCY_KEY_RETENTION` from the transaction's timestamp.
+    #[instrument(skip_all)]
+    pub fn record_transaction_idempotently(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        transaction: Transaction,
+    ) -> Result<(Transaction, bool), ApiError> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM idempotency_keys WHERE created_at < ?1",
+            params![(transaction.timestamp - IDEMPOTENCY_KEY_RETENTION).timestamp_millis()],
+        )?;
+
+        let previous: Option<(String, i64)> = tx
+            .query_row(
+                "SELECT fingerprint, transaction_id FROM idempotency_keys WHERE key = ?1",
+                params![key],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?;
+
+        if let Some((previous_fingerprint, transaction_id)) = previous {
+            if previous_fingerprint != fingerprint {
+                return Err(ApiError::Conflict(format!(
+                    "Idempotency-Key {} was already used for a different request",
+                    key
+                )));
+            }
+
+            let mut stmt = tx.prepare(
+                "SELECT id, timestamp, child_name, amount, purpose FROM transactions WHERE id = ?1",
+            )?;
+            let rows = stmt.query(params![transaction_id])?;
+            let recorded = Self::collect_transactions(rows)?.pop().ok_or_else(|| {
+                ApiError::InternalError(format!(
+                    "Idempotency-Key {} refers to missing transaction {}",
+                    key, transaction_id
+                ))
+            })?;
+            return Ok((recorded, true));
+        }
+
+        let recorded = Self::record_transaction_internal(&tx, transaction)?;
+        tx.execute(
+            "INSERT INTO idempotency_keys (key, fingerprint, transaction_id, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![key, fingerprint, recorded.id, recorded.timestamp.timestamp_millis()],
+        )?;
+        tx.commit()?;
+
+        Ok((recorded, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use crate::model::amount::Amount;
+
+    use super::*;
+
+    fn give(timestamp: DateTime<Utc>) -> Transaction {
+        Transaction::new(
+            0,
+            timestamp,
+            String::from("a"),
+            Amount::from_pence(100),
+            String::from("chores"),
+        )
+    }
+
+    #[test]
+    fn keys_are_forgotten_after_the_retention_period_test() {
+        let db = Db::new();
+        let first_recorded = Utc::now() - IDEMPOTENCY_KEY_RETENTION;
+
+        let (first, replayed) = db
+            .record_transaction_idempotently("key", "request", give(first_recorded))
+            .unwrap();
+        assert!(!replayed);
+
+        let (repeat, replayed) = db
+            .record_transaction_idempotently("key", "request", give(first_recorded))
+            .unwrap();
+        assert!(replayed);
+        assert_eq!(repeat.id, first.id);
+
+        let (later, replayed) = db
+            .record_transaction_idempotently(
+                "key",
+                "request",
+                give(first_recorded + IDEMPOTENCY_KEY_RETENTION + Duration::seconds(1)),
+            )
+            .unwrap();
+        assert!(!replayed);
+        assert_ne!(later.id, first.id);
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use rusqlite::Connection;
+use tracing::instrument;
+
+use crate::model::{
+    amount::Amount,
+    error::ApiError,
+    integrity::{IntegrityCheck, IntegrityReport},
+};
+
+use super::{Db, MIGRATIONS};
+
+impl Db {
+    /// Checks the database file and the ledger's invariants. Problems are
+    /// reported, not fixed.
+    #[instrument(skip_all)]
+    pub fn check_integrity(&self) -> Result<IntegrityReport, ApiError> {
+        let conn = self.lock();
+
+        Ok(IntegrityReport {
+            checks: vec![
+                check_sqlite(&conn)?,
+                check_schema_version(&conn)?,
+                check_valid_transactions(&conn)?,
+                check_balances(&conn)?,
+                check_reversals(&conn)?,
+            ],
+        })
+    }
+}
+
+fn check_sqlite(conn: &Connection) -> Result<IntegrityCheck, ApiError> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let problems = stmt
+        .query_map((), |r| r.get::<usize, String>(0))?
+        .collect::<Result<Vec<String>, rusqlite::Error>>()?
+        .into_iter()
+        .filter(|result| result != "ok")
+        .collect();
+
+    Ok(IntegrityCheck::new("sqlite", problems))
+}
+
+fn check_schema_version(conn: &Connection) -> Result<IntegrityCheck, ApiError> {
+    let user_version: usize =
+        conn.query_row("SELECT user_version FROM pragma_user_version", (), |r| {
+            r.get(0)
+        })?;
+
+    let problems = if user_version == MIGRATIONS.len() {
+        Vec::new()
+    } else {
+        vec![format!(
+            "Schema is at version {}, expected {}",
+            user_version,
+            MIGRATIONS.len()
+        )]
+    };
+
+    Ok(IntegrityCheck::new("schema_version", problems))
+}
+
+/// What the API would have refused to record.
+fn check_valid_transactions(conn: &Connection) -> Result<IntegrityCheck, ApiError> {
+    let mut stmt =
+        conn.prepare("SELECT id FROM transactions WHERE amount = 0 OR purpose = '' ORDER BY id")?;
+    let problems = stmt
+        .query_map((), |r| r.get::<usize, i64>(0))?
+        .map(|id| id.map(|id| format!("Transaction {} has no amount or no purpose", id)))
+        .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+
+    Ok(IntegrityCheck::new("valid_transactions", problems))
+}
+
+/// No child's balance may ever have been negative, not just now.
+fn check_balances(conn: &Connection) -> Result<IntegrityCheck, ApiError> {
+    let mut stmt = conn.prepare("SELECT id, child_name, amount FROM transactions ORDER BY id")?;
+    let mut rows = stmt.query(())?;
+
+    let mut balances: BTreeMap<String, i64> = BTreeMap::new();
+    let mut problems = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let child_name: String = row.get(1)?;
+        let balance = balances.entry(child_name.clone()).or_default();
+        let was_negative = *balance < 0;
+        *balance += row.get::<usize, i64>(2)?;
+
+        if *balance < 0 && !was_negative {
+            problems.push(format!(
+                "{} went negative ({}) at transaction {}",
+                child_name,
+                Amount::from_pence(*balance),
+                id
+            ));
+        }
+    }
+
+    Ok(IntegrityCheck::new("balances", problems))
+}
+
+fn check_reversals(conn: &Connection) -> Result<IntegrityCheck, ApiError> {
+    let mut stmt = conn.prepare(
+        "SELECT r.transaction_id, r.reversal_id, o.child_name, v.child_name, o.amount, v.amount
+         FROM transaction_reversals r
+         LEFT JOIN transactions o ON o.id = r.transaction_id
+         LEFT JOIN transactions v ON v.id = r.reversal_id
+         ORDER BY r.transaction_id",
+    )?;
+    let mut rows = stmt.query(())?;
+
+    let mut problems = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let reversal_id: i64 = row.get(1)?;
+        let child_names: (Option<String>, Option<String>) = (row.get(2)?, row.get(3)?);
+        let amounts: (Option<i64>, Option<i64>) = (row.get(4)?, row.get(5)?);
+
+        let problem = match (child_names, amounts) {
+            ((None, _), _) | ((_, None), _) => "refers to a missing transaction",
+            ((Some(original), Some(reversal)), _) if original != reversal => {
+                "is for a different child"
+            }
+            (_, (Some(original), Some(reversal))) if original != -reversal => {
+                "doesn't cancel it out"
+            }
+            _ => continue,
+        };
+        problems.push(format!(
+            "Reversal {} of transaction {} {}",
+            reversal_id, id, problem
+        ));
+    }
+
+    Ok(IntegrityCheck::new("reversals", problems))
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use tracing::instrument;
+
+use crate::model::{error::ApiError, transaction::Transaction};
+
+use super::Db;
+
+impl Db {
+    /// Records a transaction cancelling out one of the child's earlier ones.
+    /// A transaction can only be reversed once, and reversals can't
+    /// themselves be reversed. Reversing a give the child has since spent
+    /// fails like any other overdraft.
+    #[instrument(skip_all)]
+    pub fn reverse_transaction_for_child(
+        &self,
+        child_name: &str,
+        id: i64,
+        timestamp: DateTime<Utc>,
+    ) -> Result<Transaction, ApiError> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+
+        let original = {
+            let mut stmt = tx.prepare(
+                "SELECT id, timestamp, child_name, amount, purpose FROM transactions WHERE id = ?1 AND child_name = ?2",
+            )?;
+            let rows = stmt.query(params![id, child_name])?;
+            Self::collect_transactions(rows)?.pop().ok_or_else(|| {
+                ApiError::PathNotFound(format!("/child/{}/transactions/{}", child_name, id))
+            })?
+        };
+
+        let reversal_id: Option<i64> = tx
+            .query_row(
+                "SELECT reversal_id FROM transaction_reversals WHERE transaction_id = ?1",
+                params![id],
+                |r| r.get(0),
+            )
+            .optional()?;
+        if let Some(reversal_id) = reversal_id {
+            return Err(ApiError::Conflict(format!(
+                "Transaction {} was already reversed by transaction {}",
+                id, reversal_id
+            )));
+        }
+
+        let reversed_id: Option<i64> = tx
+            .query_row(
+                "SELECT transaction_id FROM transaction_reversals WHERE reversal_id = ?1",
+                params![id],
+                |r| r.get(0),
+            )
+            .optional()?;
+        if let Some(reversed_id) = reversed_id {
+            return Err(ApiError::Conflict(format!(
+                "Transaction {} reverses transaction {} and can't itself be reversed",
+                id, reversed_id
+            )));
+        }
+
+        let reversal = Self::record_transaction_internal(
+            &tx,
+            Transaction::new(
+                0,
+                timestamp,
+                original.child_name,
+                original.amount.negate(),
+                format!("Reversal of #{}: {}", original.id, original.purpose),
+            ),
+        )?;
+
+        tx.execute(
+            "INSERT INTO transaction_reversals (transaction_id, reversal_id) VALUES (?1, ?2)",
+            params![original.id, reversal.id],
+        )?;
+        tx.commit()?;
+
+        Ok(reversal)
+    }
+}
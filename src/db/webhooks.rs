@@ -0,0 +1,271 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, Row};
+use tracing::instrument;
+
+use crate::model::{
+    error::ApiError,
+    webhook::{PendingWebhookDelivery, Webhook, WebhookDelivery},
+};
+
+use super::Db;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_DEAD: &str = "dead";
+
+fn from_millis(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis).single().unwrap()
+}
+
+fn webhook_from_row(row: &Row<'_>) -> Result<Webhook, rusqlite::Error> {
+    Ok(Webhook {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        event_types: serde_json::from_str(&row.get::<usize, String>(2)?).unwrap_or_default(),
+        created_at: from_millis(row.get(3)?),
+    })
+}
+
+impl Db {
+    #[instrument(skip_all)]
+    pub fn create_webhook(
+        &self,
+        url: String,
+        event_types: Vec<String>,
+        secret: String,
+    ) -> Result<Webhook, ApiError> {
+        let conn = self.lock();
+        let created_at = Utc::now();
+
+        conn.execute(
+            "INSERT INTO webhooks (url, event_types, secret, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                url,
+                serde_json::to_string(&event_types).unwrap(),
+                secret,
+                created_at.timestamp_millis()
+            ],
+        )?;
+
+        Ok(Webhook {
+            id: conn.last_insert_rowid(),
+            url,
+            event_types,
+            created_at: from_millis(created_at.timestamp_millis()),
+        })
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_webhooks(&self) -> Result<Vec<Webhook>, ApiError> {
+        let conn = self.lock();
+        let mut stmt =
+            conn.prepare("SELECT id, url, event_types, created_at FROM webhooks ORDER BY id")?;
+
+        let webhooks = stmt
+            .query_map((), webhook_from_row)?
+            .collect::<Result<Vec<Webhook>, rusqlite::Error>>()?;
+
+        Ok(webhooks)
+    }
+
+    /// Removes the webhook along with any deliveries still waiting on it.
+    /// Returns false if there was no such webhook.
+    #[instrument(skip_all)]
+    pub fn delete_webhook(&self, id: i64) -> Result<bool, ApiError> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM webhook_deliveries WHERE webhook_id = ?1",
+            params![id],
+        )?;
+        let deleted = tx.execute("DELETE FROM webhooks WHERE id = ?1", params![id])?;
+        tx.commit()?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Queues a delivery of `payload` to every webhook subscribed to
+    /// `event_type`, due immediately. Returns how many were queued.
+    #[instrument(skip_all)]
+    pub fn enqueue_webhook_deliveries(
+        &self,
+        event_type: &str,
+        payload: &str,
+    ) -> Result<usize, ApiError> {
+        let mut conn = self.lock();
+        let tx = conn.transaction()?;
+        let now = Utc::now().timestamp_millis();
+
+        let webhook_ids = {
+            let mut stmt = tx.prepare("SELECT id, url, event_types, created_at FROM webhooks")?;
+            let webhooks = stmt
+                .query_map((), webhook_from_row)?
+                .collect::<Result<Vec<Webhook>, rusqlite::Error>>()?;
+
+            webhooks
+                .into_iter()
+                .filter(|w| {
+                    w.event_types.is_empty() || w.event_types.iter().any(|t| t == event_type)
+                })
+                .map(|w| w.id)
+                .collect::<Vec<i64>>()
+        };
+
+        for webhook_id in &webhook_ids {
+            tx.execute(
+                "INSERT INTO webhook_deliveries (webhook_id, event_type, payload, status, attempts, next_attempt_at, created_at) VALUES (?1, ?2, ?3, ?4, 0, ?5, ?5)",
+                params![webhook_id, event_type, payload, STATUS_PENDING, now],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(webhook_ids.len())
+    }
+
+    /// Each webhook is its own queue, sent one delivery at a time. Returns
+    /// the next due delivery of up to `limit` webhooks, skipping those in
+    /// `busy_webhook_ids` that still have a delivery in flight.
+    #[instrument(skip_all)]
+    pub fn get_due_webhook_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        busy_webhook_ids: &HashSet<i64>,
+        limit: usize,
+    ) -> Result<Vec<PendingWebhookDelivery>, ApiError> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id, webhook_id, url, secret, event_type, payload, attempts FROM (
+                SELECT d.id, d.webhook_id, w.url, w.secret, d.event_type, d.payload, d.attempts, d.next_attempt_at,
+                    ROW_NUMBER() OVER (PARTITION BY d.webhook_id ORDER BY d.next_attempt_at, d.id) AS position
+                FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id
+                WHERE d.status = ?1 AND d.next_attempt_at <= ?2
+             ) WHERE position = 1 ORDER BY next_attempt_at, id LIMIT ?3",
+        )?;
+
+        // One row per webhook, so the busy ones can only take up that many
+        let mut deliveries = stmt
+            .query_map(
+                params![
+                    STATUS_PENDING,
+                    now.timestamp_millis(),
+                    limit + busy_webhook_ids.len()
+                ],
+                |row| {
+                    Ok(PendingWebhookDelivery {
+                        id: row.get(0)?,
+                        webhook_id: row.get(1)?,
+                        url: row.get(2)?,
+                        secret: row.get(3)?,
+                        event_type: row.get(4)?,
+                        payload: row.get(5)?,
+                        attempts: row.get(6)?,
+                    })
+                },
+            )?
+            .collect::<Result<Vec<PendingWebhookDelivery>, rusqlite::Error>>()?;
+        deliveries.retain(|d| !busy_webhook_ids.contains(&d.webhook_id));
+        deliveries.truncate(limit);
+
+        Ok(deliveries)
+    }
+
+    /// When the next pending delivery is due for a webhook not in
+    /// `busy_webhook_ids`, if there is one.
+    #[instrument(skip_all)]
+    pub fn get_next_webhook_delivery_due_at(
+        &self,
+        busy_webhook_ids: &HashSet<i64>,
+    ) -> Result<Option<DateTime<Utc>>, ApiError> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT webhook_id, MIN(next_attempt_at) FROM webhook_deliveries WHERE status = ?1 GROUP BY webhook_id",
+        )?;
+
+        let next = stmt
+            .query_map(params![STATUS_PENDING], |r| {
+                Ok((r.get::<usize, i64>(0)?, r.get::<usize, i64>(1)?))
+            })?
+            .collect::<Result<Vec<(i64, i64)>, rusqlite::Error>>()?
+            .into_iter()
+            .filter(|(webhook_id, _)| !busy_webhook_ids.contains(webhook_id))
+            .map(|(_, next_attempt_at)| next_attempt_at)
+            .min();
+
+        Ok(next.map(from_millis))
+    }
+
+    #[instrument(skip_all)]
+    pub fn complete_webhook_delivery(&self, id: i64) -> Result<(), ApiError> {
+        let conn = self.lock();
+        conn.execute("DELETE FROM webhook_deliveries WHERE id = ?1", params![id])?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt. With no `next_attempt_at` the delivery has
+    /// run out of attempts and moves to the dead-letter view.
+    #[instrument(skip_all)]
+    pub fn fail_webhook_delivery(
+        &self,
+        id: i64,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), ApiError> {
+        let conn = self.lock();
+        let (status, next_attempt_at) = match next_attempt_at {
+            Some(next_attempt_at) => (STATUS_PENDING, next_attempt_at),
+            None => (STATUS_DEAD, Utc::now()),
+        };
+
+        conn.execute(
+            "UPDATE webhook_deliveries SET status = ?2, attempts = attempts + 1, last_error = ?3, next_attempt_at = ?4 WHERE id = ?1",
+            params![id, status, error, next_attempt_at.timestamp_millis()],
+        )?;
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    pub fn get_dead_webhook_deliveries(&self) -> Result<Vec<WebhookDelivery>, ApiError> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT d.id, d.webhook_id, w.url, d.event_type, d.payload, d.attempts, d.last_error, d.next_attempt_at, d.created_at
+             FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id
+             WHERE d.status = ?1 ORDER BY d.id",
+        )?;
+
+        let deliveries = stmt
+            .query_map(params![STATUS_DEAD], |row| {
+                Ok(WebhookDelivery {
+                    id: row.get(0)?,
+                    webhook_id: row.get(1)?,
+                    url: row.get(2)?,
+                    event_type: row.get(3)?,
+                    payload: serde_json::from_str(&row.get::<usize, String>(4)?)
+                        .unwrap_or_default(),
+                    attempts: row.get(5)?,
+                    last_error: row.get(6)?,
+                    next_attempt_at: from_millis(row.get(7)?),
+                    created_at: from_millis(row.get(8)?),
+                })
+            })?
+            .collect::<Result<Vec<WebhookDelivery>, rusqlite::Error>>()?;
+
+        Ok(deliveries)
+    }
+
+    /// Moves a dead delivery back to pending with its attempts reset.
+    /// Returns false if there was no such dead delivery.
+    #[instrument(skip_all)]
+    pub fn retry_dead_webhook_delivery(&self, id: i64) -> Result<bool, ApiError> {
+        let conn = self.lock();
+        let updated = conn.execute(
+            "UPDATE webhook_deliveries SET status = ?2, attempts = 0, next_attempt_at = ?3 WHERE id = ?1 AND status = ?4",
+            params![id, STATUS_PENDING, Utc::now().timestamp_millis(), STATUS_DEAD],
+        )?;
+
+        Ok(updated > 0)
+    }
+}
@@ -0,0 +1,193 @@
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{FromRequest, FromRequestParts, Path},
+    http::{header, request::Parts, HeaderMap, Request},
+    BoxError,
+};
+use serde::de::DeserializeOwned;
+use serde_json::error::Category;
+
+use crate::model::{
+    amount::{INVALID_AMOUNT, UNPARSEABLE_AMOUNT},
+    error::{ApiError, FieldError, FieldErrorCode},
+};
+
+/// Like axum's `Json` extractor, but rejects with an `ApiError` so bad
+/// bodies get the same problem details as every other error, with the
+/// offending field where there is one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        if !has_json_content_type(req.headers()) {
+            return Err(ApiError::UnsupportedMediaType(String::from(
+                "Expected request with `Content-Type: application/json`",
+            )));
+        }
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|rejection| ApiError::InputFailedValidation(rejection.body_text()))?;
+
+        parse_json(&bytes).map(ApiJson)
+    }
+}
+
+/// Like axum's `Path` extractor, but rejects with an `ApiError`, so a
+/// parameter that doesn't parse, e.g. a transaction id that isn't a number,
+/// gets problem details too.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(value)) => Ok(ApiPath(value)),
+            // Routes and extractors that don't match are our mistake
+            Err(rejection) if rejection.status().is_server_error() => {
+                Err(ApiError::InternalError(rejection.body_text()))
+            }
+            Err(rejection) => Err(ApiError::InputFailedValidation(rejection.body_text())),
+        }
+    }
+}
+
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.parse::<mime::Mime>().ok())
+        .is_some_and(|mime| {
+            mime.type_() == "application"
+                && (mime.subtype() == "json" || mime.suffix().is_some_and(|s| s == "json"))
+        })
+}
+
+/// Deserializes a JSON body, reporting which field failed if it's JSON of
+/// the wrong shape.
+pub fn parse_json<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ApiError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        let error = e.into_inner();
+        match error.classify() {
+            Category::Data => ApiError::InvalidBody(vec![to_field_error(&path, &error)]),
+            Category::Syntax | Category::Eof | Category::Io => {
+                ApiError::MalformedJson(format!("Malformed JSON: {}", error))
+            }
+        }
+    })?;
+
+    deserializer
+        .end()
+        .map_err(|e| ApiError::MalformedJson(format!("Malformed JSON: {}", e)))?;
+
+    Ok(value)
+}
+
+fn to_field_error(path: &str, error: &serde_json::Error) -> FieldError {
+    // serde_json appends the position, which means nothing next to a field name
+    let message = error.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(position) => &message[..position],
+        None => &message,
+    };
+
+    // A missing field is reported against the object that should contain it
+    if let Some(missing) = message
+        .strip_prefix("missing field `")
+        .and_then(|m| m.strip_suffix('`'))
+    {
+        let field = match path {
+            "." => String::from(missing),
+            parent => format!("{}.{}", parent, missing),
+        };
+        return FieldError::new(&field, FieldErrorCode::Missing, message);
+    }
+
+    let code = if message.starts_with(INVALID_AMOUNT) || message.starts_with(UNPARSEABLE_AMOUNT) {
+        FieldErrorCode::InvalidAmount
+    } else if [
+        "invalid type",
+        "invalid value",
+        "invalid length",
+        "unknown variant",
+    ]
+    .iter()
+    .any(|prefix| message.starts_with(prefix))
+    {
+        FieldErrorCode::InvalidType
+    } else {
+        FieldErrorCode::InvalidFormat
+    };
+
+    FieldError::new(path, code, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        handlers::record_transaction::GiveMoney,
+        model::error::{ApiError, FieldError, FieldErrorCode},
+    };
+
+    use super::parse_json;
+
+    fn field_errors(body: &str) -> Vec<FieldError> {
+        match parse_json::<GiveMoney>(body.as_bytes()) {
+            Err(ApiError::InvalidBody(errors)) => errors,
+            other => panic!("expected InvalidBody, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_json_test() {
+        assert!(parse_json::<GiveMoney>(br#"{"amount":1.50,"purpose":"sweets"}"#).is_ok());
+
+        assert!(matches!(
+            parse_json::<GiveMoney>(br#"{"amount":1.50,"#),
+            Err(ApiError::MalformedJson(_))
+        ));
+        assert!(matches!(
+            parse_json::<GiveMoney>(br#"{"amount":1.50,"purpose":"sweets"} {}"#),
+            Err(ApiError::MalformedJson(_))
+        ));
+
+        let errors = field_errors(r#"{"amount":1.5,"purpose":"sweets"}"#);
+        assert_eq!(errors[0].field, "amount");
+        assert_eq!(errors[0].code, FieldErrorCode::InvalidAmount);
+
+        let errors = field_errors(r#"{"amount":"1.50","purpose":"sweets"}"#);
+        assert_eq!(errors[0].field, "amount");
+        assert_eq!(errors[0].code, FieldErrorCode::InvalidType);
+
+        assert_eq!(
+            field_errors(r#"{"amount":1.50}"#),
+            vec![FieldError::new(
+                "purpose",
+                FieldErrorCode::Missing,
+                "missing field `purpose`"
+            )]
+        );
+    }
+}
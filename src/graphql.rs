@@ -0,0 +1,411 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Error, ErrorExtensions, Object, Schema, Subscription, Value};
+use futures::{stream, Stream};
+use tokio::sync::mpsc::Receiver;
+use tracing::{info, warn};
+
+use crate::{
+    appstate::{AppState, ListenerRegistration},
+    handlers::record_transaction::{record_transaction_for_child, GiveMoney, TransactionType},
+    model::{
+        amount::Amount,
+        error::{ApiError, FieldError, FieldErrorCode},
+        transaction::Transaction,
+        websocket_msg::{ActiveWebsocket, Subscription as ChildSubscription, WebSocketMsg},
+    },
+};
+
+pub type BankSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+/// Deep enough for any query over children and their transactions.
+const MAX_QUERY_DEPTH: usize = 8;
+
+/// Most transactions or children one field returns, and how many
+/// transactions it returns when not asked for fewer.
+const MAX_PAGE_SIZE: usize = 100;
+
+/// How many children `children` returns when not asked for a number. Each
+/// is charged its own transactions, so a full page of them with a few
+/// recent transactions each still fits the complexity budget.
+const CHILDREN_PAGE_SIZE: usize = 20;
+
+/// Each field costs 1 and a list of transactions costs its page size times
+/// its fields, so this allows a couple of full pages per query.
+const MAX_QUERY_COMPLEXITY: usize = 1000;
+
+pub fn build_schema(app_state: Arc<AppState>) -> BankSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(app_state)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// The error as the HTTP API would have reported it, with the problem
+/// details code and any field errors in the extensions.
+fn to_graphql_error(error: ApiError) -> Error {
+    let details = error.to_error_details();
+    Error::new(details.reason.clone()).extend_with(|_, extensions| {
+        extensions.set("code", details.code.as_str());
+        extensions.set("status", details.status);
+        if !details.errors.is_empty() {
+            if let Ok(errors) = serde_json::to_value(&details.errors) {
+                extensions.set("errors", Value::from_json(errors).unwrap_or_default());
+            }
+        }
+    })
+}
+
+/// Matches the websocket `resync_required` message. The client should
+/// query `transactions(afterId:)` for what it missed.
+fn resync_required_error() -> Error {
+    Error::new("Events were dropped").extend_with(|_, extensions| {
+        extensions.set("code", "resync_required");
+    })
+}
+
+/// The page size an argument asks for, up to `MAX_PAGE_SIZE`, or `default`.
+fn page_size(argument: &str, requested: Option<usize>, default: usize) -> Result<usize, Error> {
+    match requested {
+        Some(size) if size > MAX_PAGE_SIZE => Err(to_graphql_error(ApiError::InvalidFields(vec![
+            FieldError::new(
+                argument,
+                FieldErrorCode::OutOfRange,
+                &format!("Must be at most {}", MAX_PAGE_SIZE),
+            ),
+        ]))),
+        Some(size) => Ok(size),
+        None => Ok(default),
+    }
+}
+
+fn app_state<'a>(ctx: &Context<'a>) -> &'a Arc<AppState> {
+    ctx.data_unchecked::<Arc<AppState>>()
+}
+
+pub struct Child {
+    name: String,
+}
+
+#[Object]
+impl Child {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn balance(&self, ctx: &Context<'_>) -> Result<Amount, Error> {
+        let ledger_version = app_state(ctx)
+            .get_db()
+            .get_ledger_version_for_child(&self.name)
+            .map_err(to_graphql_error)?;
+        Ok(ledger_version.balance)
+    }
+
+    /// The `last` most recent, oldest first. At most 100.
+    #[graphql(complexity = "last.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE) * child_complexity")]
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        last: Option<usize>,
+    ) -> Result<Vec<Transaction>, Error> {
+        let last = page_size("last", last, MAX_PAGE_SIZE)?;
+        app_state(ctx)
+            .get_db()
+            .get_last_transactions_for_child(self.name.clone(), last)
+            .map_err(to_graphql_error)
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The `first` children with at least one transaction, by name, after
+    /// `afterName`. 20 unless asked for up to 100; page with the last name
+    /// returned.
+    #[graphql(
+        complexity = "first.unwrap_or(CHILDREN_PAGE_SIZE).min(MAX_PAGE_SIZE) * child_complexity"
+    )]
+    async fn children(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<usize>,
+        after_name: Option<String>,
+    ) -> Result<Vec<Child>, Error> {
+        let first = page_size("first", first, CHILDREN_PAGE_SIZE)?;
+        let child_names = app_state(ctx)
+            .get_db()
+            .get_child_names_after(after_name, first)
+            .map_err(to_graphql_error)?;
+        Ok(child_names.into_iter().map(|name| Child { name }).collect())
+    }
+
+    /// Any child, including one with no transactions yet.
+    async fn child(&self, name: String) -> Child {
+        Child { name }
+    }
+
+    /// The `first` transactions after `afterId`, for one child or all of
+    /// them, oldest first. At most 100; page with the last id returned.
+    #[graphql(complexity = "first.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE) * child_complexity")]
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        child_name: Option<String>,
+        after_id: Option<i64>,
+        first: Option<usize>,
+    ) -> Result<Vec<Transaction>, Error> {
+        let first = page_size("first", first, MAX_PAGE_SIZE)?;
+        app_state(ctx)
+            .get_db()
+            .get_transactions_after(child_name, after_id.unwrap_or_default(), Some(first))
+            .map_err(to_graphql_error)
+    }
+}
+
+pub struct MutationRoot;
+
+impl MutationRoot {
+    async fn record(
+        ctx: &Context<'_>,
+        child_name: String,
+        amount: Amount,
+        purpose: String,
+        transaction_type: TransactionType,
+    ) -> Result<Transaction, Error> {
+        info!(
+            "graphql {:?} {} for {}",
+            transaction_type, amount, child_name
+        );
+        record_transaction_for_child(
+            app_state(ctx),
+            child_name,
+            GiveMoney { amount, purpose },
+            transaction_type,
+        )
+        .await
+        .map_err(to_graphql_error)
+    }
+}
+
+#[Object]
+impl MutationRoot {
+    async fn give(
+        &self,
+        ctx: &Context<'_>,
+        child_name: String,
+        amount: Amount,
+        purpose: String,
+    ) -> Result<Transaction, Error> {
+        MutationRoot::record(ctx, child_name, amount, purpose, TransactionType::Give).await
+    }
+
+    /// Fails with code `insufficient_funds` rather than overdrawing.
+    async fn spend(
+        &self,
+        ctx: &Context<'_>,
+        child_name: String,
+        amount: Amount,
+        purpose: String,
+    ) -> Result<Transaction, Error> {
+        MutationRoot::record(ctx, child_name, amount, purpose, TransactionType::Spend).await
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// New transactions for one child, or every child if `childName` is
+    /// omitted, delivered through the same fan-out as the websockets.
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        child_name: Option<String>,
+    ) -> impl Stream<Item = Result<Transaction, Error>> {
+        let app_state = app_state(ctx).clone();
+        let subscription = match child_name {
+            Some(child_name) => ChildSubscription::Child(child_name),
+            None => ChildSubscription::AllChildren,
+        };
+
+        let (ch_sender, ch_receiver) = tokio::sync::mpsc::channel::<WebSocketMsg>(
+            app_state.get_websocket_config().channel_capacity,
+        );
+        let listener = ActiveWebsocket::new(nanoid::nanoid!(10), subscription, ch_sender);
+        app_state.register_open_websocket(listener.clone()).await;
+
+        let state = TransactionStreamState {
+            receiver: ch_receiver,
+            _registration: ListenerRegistration::new(app_state, listener.get_id()),
+            listener,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            let item = state.next_item().await?;
+            Some((item, state))
+        })
+    }
+}
+
+struct TransactionStreamState {
+    receiver: Receiver<WebSocketMsg>,
+    listener: ActiveWebsocket,
+    _registration: ListenerRegistration,
+}
+
+impl TransactionStreamState {
+    async fn next_item(&mut self) -> Option<Result<Transaction, Error>> {
+        loop {
+            if self.listener.take_resync_required() {
+                warn!(
+                    subscription_id = %self.listener.get_id(),
+                    "events were dropped, requesting resync"
+                );
+                return Some(Err(resync_required_error()));
+            }
+
+            match self.receiver.recv().await {
+                Some(WebSocketMsg::Transaction(transaction)) => return Some(Ok(transaction)),
+                Some(WebSocketMsg::CloseSocket(_)) | None => return None,
+                Some(_) => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_graphql::Request;
+    use serde_json::json;
+
+    use crate::{appstate::AppState, db::Db};
+
+    use super::build_schema;
+
+    #[tokio::test]
+    async fn amounts_keep_their_digits_test() {
+        let schema = build_schema(Arc::new(AppState::new(Db::new())));
+
+        let response = schema
+            .execute(
+                r#"mutation { give(childName: "a", amount: 1.50, purpose: "chores") { amount } }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            serde_json::to_string(&response.data).unwrap(),
+            r#"{"give":{"amount":1.50}}"#
+        );
+
+        let variables: async_graphql::Variables =
+            serde_json::from_str(r#"{"amount": 0.25}"#).unwrap();
+        let response = schema
+            .execute(
+                Request::new(
+                    r#"mutation($amount: Amount!) { spend(childName: "a", amount: $amount, purpose: "sweets") { amount } }"#,
+                )
+                .variables(variables),
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            serde_json::to_value(&response.data).unwrap(),
+            json!({"spend": {"amount": -0.25}})
+        );
+
+        let response = schema
+            .execute(r#"mutation { give(childName: "a", amount: 1.5, purpose: "chores") { id } }"#)
+            .await;
+        assert_eq!(response.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn children_are_paged_test() {
+        let schema = build_schema(Arc::new(AppState::new(Db::new())));
+        for child_name in ["a", "b", "c"] {
+            let response = schema
+                .execute(format!(
+                    r#"mutation {{ give(childName: "{child_name}", amount: 1, purpose: "chores") {{ id }} }}"#
+                ))
+                .await;
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+        }
+
+        let response = schema
+            .execute(
+                r#"{ children(first: 1, afterName: "a") { name transactions(last: 1) { id } } }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            serde_json::to_value(&response.data).unwrap(),
+            json!({"children": [{"name": "b", "transactions": [{"id": 2}]}]})
+        );
+
+        // Every transaction of every child costs a page of children times a
+        // page of transactions
+        let response = schema
+            .execute(r#"{ children { transactions { id } } }"#)
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(
+            response.errors[0].message.contains("too complex"),
+            "{}",
+            response.errors[0].message
+        );
+    }
+
+    #[tokio::test]
+    async fn transactions_are_paged_test() {
+        let schema = build_schema(Arc::new(AppState::new(Db::new())));
+        for _ in 0..3 {
+            let response = schema
+                .execute(
+                    r#"mutation { give(childName: "a", amount: 1, purpose: "chores") { id } }"#,
+                )
+                .await;
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+        }
+
+        let response = schema
+            .execute(r#"{ transactions(first: 2) { id } child(name: "a") { transactions(last: 1) { id } } }"#)
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            serde_json::to_value(&response.data).unwrap(),
+            json!({
+                "transactions": [{"id": 1}, {"id": 2}],
+                "child": {"transactions": [{"id": 3}]},
+            })
+        );
+
+        let response = schema
+            .execute(r#"{ transactions(first: 101) { id } }"#)
+            .await;
+        assert_eq!(
+            response.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&async_graphql::Value::from("validation_failed"))
+        );
+
+        // Three full pages of every field is more than one query may ask for
+        let response = schema
+            .execute(
+                r#"{
+                    a: transactions { id timestamp childName amount purpose }
+                    b: transactions { id timestamp childName amount purpose }
+                    c: transactions { id timestamp childName amount purpose }
+                }"#,
+            )
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(
+            response.errors[0].message.contains("too complex"),
+            "{}",
+            response.errors[0].message
+        );
+    }
+}
@@ -1,4 +1,14 @@
+pub mod alert_rules;
 pub mod child;
+pub mod children;
+pub mod diagnostics;
+pub mod email_preferences;
+pub mod events;
+pub mod graphql;
+pub mod health;
+pub mod metrics;
+pub mod openapi;
 pub mod path_not_found;
 pub mod record_transaction;
+pub mod webhooks;
 pub mod websocket;
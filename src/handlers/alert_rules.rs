@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, Json};
+use tracing::info;
+
+use crate::{
+    appstate::AppState,
+    extract::{ApiJson, ApiPath},
+    model::{
+        alert::{AlertCondition, AlertRule, NewAlertRule, MAX_COOLDOWN_SECONDS},
+        error::{check_fields, ApiError, FieldError, FieldErrorCode},
+    },
+};
+
+fn validate_new_alert_rule(new_rule: &NewAlertRule) -> Result<(), ApiError> {
+    let mut errors = Vec::new();
+
+    match new_rule.condition {
+        AlertCondition::BalanceBelow { amount } if !amount.is_positive_nonzero() => {
+            errors.push(FieldError::new(
+                "amount",
+                FieldErrorCode::NotPositive,
+                "Amount must be positive",
+            ));
+        }
+        AlertCondition::SpendOverBalancePercent { percent } if percent == 0 || percent > 100 => {
+            errors.push(FieldError::new(
+                "percent",
+                FieldErrorCode::OutOfRange,
+                "Percent must be between 1 and 100",
+            ));
+        }
+        _ => {}
+    }
+
+    if new_rule.cooldown_seconds > MAX_COOLDOWN_SECONDS {
+        errors.push(FieldError::new(
+            "cooldown_seconds",
+            FieldErrorCode::OutOfRange,
+            &format!("Cooldown must be at most {} seconds", MAX_COOLDOWN_SECONDS),
+        ));
+    }
+
+    check_fields(errors)
+}
+
+#[utoipa::path(
+    post,
+    path = "/child/{child_name}/alert_rules",
+    tag = "alert_rules",
+    params(("child_name" = String, Path, description = "The child's name")),
+    request_body = NewAlertRule,
+    responses(
+        (status = 200, body = AlertRule),
+        (status = 400, description = "The request failed validation", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 422, description = "The body isn't a valid NewAlertRule", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 415, description = "The Content-Type isn't application/json", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn create_alert_rule(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath(child_name): ApiPath<String>,
+    ApiJson(new_rule): ApiJson<NewAlertRule>,
+) -> Result<Json<AlertRule>, ApiError> {
+    info!(
+        "create_alert_rule for {} called with {:?}",
+        child_name, new_rule
+    );
+
+    validate_new_alert_rule(&new_rule)?;
+
+    Ok(Json(
+        app_state.get_db().create_alert_rule(child_name, new_rule)?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/child/{child_name}/alert_rules",
+    tag = "alert_rules",
+    params(("child_name" = String, Path, description = "The child's name")),
+    responses(
+        (status = 200, body = [AlertRule]),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_alert_rules(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath(child_name): ApiPath<String>,
+) -> Result<Json<Vec<AlertRule>>, ApiError> {
+    info!("list_alert_rules {}", child_name);
+
+    Ok(Json(
+        app_state.get_db().get_alert_rules_for_child(&child_name)?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/child/{child_name}/alert_rules/{id}",
+    tag = "alert_rules",
+    params(("child_name" = String, Path, description = "The child's name"), ("id" = i64, Path, description = "The rule's id")),
+    responses(
+        (status = 204, description = "The rule was deleted"),
+        (status = 404, description = "Not found", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn delete_alert_rule(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath((child_name, id)): ApiPath<(String, i64)>,
+) -> Result<StatusCode, ApiError> {
+    info!("delete_alert_rule {} {}", child_name, id);
+
+    if app_state.get_db().delete_alert_rule(&child_name, id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::PathNotFound(format!(
+            "/child/{}/alert_rules/{}",
+            child_name, id
+        )))
+    }
+}
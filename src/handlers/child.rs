@@ -1,43 +1,144 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
+    extract::State,
+    headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Extension, Json,
 };
-use log::info;
 use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
 
 use crate::{
+    api_version::ApiVersion,
     appstate::AppState,
-    middleware::request_tracing::RequestTraceData,
-    model::{amount::Amount, error::ApiError, transaction::Transaction},
+    extract::ApiPath,
+    model::{
+        amount::Amount,
+        error::ApiError,
+        transaction::{LedgerVersion, Transaction},
+    },
 };
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, ToSchema)]
 pub struct ChildAccountResponse {
     pub child_name: String,
     pub balance: Amount,
     pub transactions: Vec<Transaction>,
 }
 
+/// A strong ETag for the ledger as one API version renders it, e.g.
+/// `"v1-7-1250"` for latest transaction 7 and a balance of 12.50.
+fn ledger_etag(api_version: ApiVersion, ledger_version: &LedgerVersion) -> ETag {
+    format!(
+        "\"{}-{}-{}\"",
+        api_version.name(),
+        ledger_version.latest_transaction_id.unwrap_or_default(),
+        ledger_version.balance.to_pence()
+    )
+    .parse()
+    .unwrap()
+}
+
+/// `If-Modified-Since` only counts when there's no `If-None-Match`.
+fn is_modified(
+    etag: &ETag,
+    ledger_version: &LedgerVersion,
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+) -> bool {
+    match (
+        if_none_match,
+        if_modified_since,
+        ledger_version.last_modified,
+    ) {
+        (Some(if_none_match), _, _) => if_none_match.precondition_passes(etag),
+        (None, Some(if_modified_since), Some(last_modified)) => {
+            if_modified_since.is_modified(last_modified.into())
+        }
+        _ => true,
+    }
+}
+
+fn cache_headers(etag: ETag, ledger_version: &LedgerVersion) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(etag);
+    if let Some(last_modified) = ledger_version.last_modified {
+        headers.typed_insert(LastModified::from(std::time::SystemTime::from(
+            last_modified,
+        )));
+    }
+    // Caches may keep the account, but must check it's current before use
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    headers
+}
+
+#[utoipa::path(
+    get,
+    path = "/child/{child_name}",
+    tag = "children",
+    params(
+        ("child_name" = String, Path, description = "The child's name"),
+        ("If-None-Match" = Option<String>, Header, description = "ETags the client already has"),
+        ("If-Modified-Since" = Option<String>, Header, description = "Ignored if If-None-Match is sent"),
+    ),
+    responses(
+        (status = 200, description = "The child's balance and transactions, empty for a new child", body = ChildAccountResponse,
+            headers(
+                ("ETag" = String, description = "Changes exactly when the child's ledger does"),
+                ("Last-Modified" = String, description = "When the latest transaction was recorded, absent for a new child"),
+            )),
+        (status = 304, description = "The client's copy is current",
+            headers(
+                ("ETag" = String),
+                ("Last-Modified" = String),
+            )),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
 pub async fn get_child(
     State(app_state): State<Arc<AppState>>,
-    Path(child_name): Path<String>,
-    Extension(request_trace_data): Extension<RequestTraceData>,
-) -> Result<Json<ChildAccountResponse>, ApiError> {
-    info!("[{}] get_child {}", request_trace_data.get_id(), child_name);
+    ApiPath(child_name): ApiPath<String>,
+    Extension(api_version): Extension<ApiVersion>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    info!("get_child {}", child_name);
+
+    let ledger_version = app_state
+        .get_db()
+        .get_ledger_version_for_child(&child_name)?;
+    let etag = ledger_etag(api_version, &ledger_version);
+
+    if !is_modified(
+        &etag,
+        &ledger_version,
+        // Not TypedHeader, which reads a missing If-None-Match as an empty list
+        headers.typed_get(),
+        headers.typed_get(),
+    ) {
+        info!("get_child {} not modified", child_name);
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            cache_headers(etag, &ledger_version),
+        )
+            .into_response());
+    }
 
     let transactions = app_state
         .get_db()
         .get_transactions_for_child(child_name.clone())?;
 
-    let balance = if transactions.len() == 0 {
-        Amount::from_pence(0)
-    } else {
-        app_state
-            .get_db()
-            .get_account_balance_for_child(child_name.clone())?
-    };
+    // Summed from the rows sent rather than read separately, so a transaction
+    // recorded in between can't leave the balance out of step with them
+    let balance = transactions
+        .iter()
+        .fold(Amount::from_pence(0), |balance, t| balance + t.amount);
+
+    // The ledger may have moved on since the version was read, so tag what's sent
+    let ledger_version = LedgerVersion::of(balance, &transactions);
+    let etag = ledger_etag(api_version, &ledger_version);
 
     let response = ChildAccountResponse {
         child_name,
@@ -45,5 +146,59 @@ pub async fn get_child(
         transactions: transactions,
     };
 
-    Ok(Json(response))
+    Ok((cache_headers(etag, &ledger_version), Json(response)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::headers::{ETag, IfNoneMatch};
+    use chrono::{TimeZone, Utc};
+
+    use crate::{
+        api_version::ApiVersion,
+        model::{amount::Amount, transaction::LedgerVersion},
+    };
+
+    use super::{is_modified, ledger_etag};
+
+    #[test]
+    fn ledger_etag_test() {
+        let empty = LedgerVersion {
+            latest_transaction_id: None,
+            balance: Amount::from_pence(0),
+            last_modified: None,
+        };
+        assert_eq!(
+            ledger_etag(ApiVersion::V1, &empty),
+            "\"v1-0-0\"".parse::<ETag>().unwrap()
+        );
+
+        let ledger_version = LedgerVersion {
+            latest_transaction_id: Some(7),
+            balance: Amount::from_pence(1250),
+            last_modified: Some(Utc.timestamp_opt(1_700_000_000, 0).unwrap()),
+        };
+        let etag = ledger_etag(ApiVersion::V1, &ledger_version);
+        assert_eq!(etag, "\"v1-7-1250\"".parse::<ETag>().unwrap());
+
+        assert!(is_modified(&etag, &ledger_version, None, None));
+        assert!(!is_modified(
+            &etag,
+            &ledger_version,
+            Some(IfNoneMatch::from(etag.clone())),
+            None
+        ));
+        assert!(!is_modified(
+            &etag,
+            &ledger_version,
+            Some(IfNoneMatch::any()),
+            None
+        ));
+        assert!(is_modified(
+            &etag,
+            &ledger_version,
+            Some(IfNoneMatch::from("\"v1-6-1250\"".parse::<ETag>().unwrap())),
+            None
+        ));
+    }
 }
@@ -0,0 +1,26 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use tracing::info;
+
+use crate::{
+    appstate::AppState,
+    model::{error::ApiError, transaction::ChildBalance},
+};
+
+#[utoipa::path(
+    get,
+    path = "/children",
+    tag = "children",
+    responses(
+        (status = 200, description = "Every child with at least one transaction, by name", body = [ChildBalance]),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_children(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ChildBalance>>, ApiError> {
+    info!("list_children");
+
+    Ok(Json(app_state.get_db().get_child_balances()?))
+}
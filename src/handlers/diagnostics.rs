@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use tracing::info;
+
+use crate::{
+    appstate::AppState,
+    model::{error::ApiError, integrity::IntegrityReport, websocket_msg::WebsocketCounts},
+};
+
+#[utoipa::path(
+    get,
+    path = "/diagnostics/websockets",
+    tag = "diagnostics",
+    responses(
+        (status = 200, body = WebsocketCounts),
+    ),
+)]
+pub async fn get_websocket_counts(State(app_state): State<Arc<AppState>>) -> Json<WebsocketCounts> {
+    info!("get_websocket_counts");
+
+    Json(app_state.count_open_websockets().await)
+}
+
+/// Runs every check, which reads the whole ledger.
+#[utoipa::path(
+    get,
+    path = "/diagnostics/integrity",
+    tag = "diagnostics",
+    responses(
+        (status = 200, description = "Every check, passed or not", body = IntegrityReport),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn get_integrity(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<IntegrityReport>, ApiError> {
+    info!("get_integrity");
+
+    Ok(Json(app_state.get_db().check_integrity()?))
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, Json};
+use lettre::Address;
+use tracing::info;
+
+use crate::{
+    appstate::AppState,
+    extract::{ApiJson, ApiPath},
+    model::{
+        email::{EmailPreferences, NewEmailPreferences},
+        error::{check_fields, ApiError, FieldError, FieldErrorCode},
+    },
+};
+
+fn validate_new_email_preferences(new_preferences: &NewEmailPreferences) -> Result<(), ApiError> {
+    let mut errors = Vec::new();
+
+    if new_preferences.email.parse::<Address>().is_err() {
+        errors.push(FieldError::new(
+            "email",
+            FieldErrorCode::InvalidFormat,
+            "Must provide a valid email address",
+        ));
+    }
+
+    for (index, _) in new_preferences
+        .children
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.is_empty())
+    {
+        errors.push(FieldError::new(
+            &format!("children[{}]", index),
+            FieldErrorCode::Empty,
+            "Child names must not be empty",
+        ));
+    }
+
+    if let Some(threshold) = new_preferences.spend_threshold {
+        if !threshold.is_positive_nonzero() {
+            errors.push(FieldError::new(
+                "spend_threshold",
+                FieldErrorCode::NotPositive,
+                "Spend threshold must be positive",
+            ));
+        }
+    }
+
+    check_fields(errors)
+}
+
+#[utoipa::path(
+    post,
+    path = "/email_preferences",
+    tag = "email_preferences",
+    request_body = NewEmailPreferences,
+    responses(
+        (status = 200, body = EmailPreferences),
+        (status = 400, description = "The request failed validation", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 422, description = "The body isn't a valid NewEmailPreferences", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 415, description = "The Content-Type isn't application/json", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn upsert_email_preferences(
+    State(app_state): State<Arc<AppState>>,
+    ApiJson(new_preferences): ApiJson<NewEmailPreferences>,
+) -> Result<Json<EmailPreferences>, ApiError> {
+    info!("upsert_email_preferences called with {:?}", new_preferences);
+
+    validate_new_email_preferences(&new_preferences)?;
+
+    Ok(Json(
+        app_state
+            .get_db()
+            .upsert_email_preferences(new_preferences)?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/email_preferences",
+    tag = "email_preferences",
+    responses(
+        (status = 200, body = [EmailPreferences]),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_email_preferences(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<EmailPreferences>>, ApiError> {
+    info!("list_email_preferences");
+
+    Ok(Json(app_state.get_db().get_email_preferences()?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/email_preferences/{id}",
+    tag = "email_preferences",
+    params(("id" = i64, Path, description = "The preferences' id")),
+    responses(
+        (status = 204, description = "The preferences were deleted"),
+        (status = 404, description = "Not found", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn delete_email_preferences(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath(id): ApiPath<i64>,
+) -> Result<StatusCode, ApiError> {
+    info!("delete_email_preferences {}", id);
+
+    if app_state.get_db().delete_email_preferences(id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::PathNotFound(format!("/email_preferences/{}", id)))
+    }
+}
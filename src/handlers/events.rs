@@ -0,0 +1,342 @@
+use std::{collections::VecDeque, convert::Infallible, sync::Arc, time::Duration};
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension,
+};
+use futures::{stream, Stream};
+use tracing::{info, warn, Instrument, Span};
+
+use crate::{
+    appstate::{AppState, ListenerRegistration},
+    extract::ApiPath,
+    middleware::request_tracing::RequestTraceData,
+    model::{
+        alert::{Alert, AlertNotification},
+        error::ApiError,
+        transaction::Transaction,
+        websocket_command::{ApprovalRequest, ApprovalRequestNotification},
+        websocket_msg::{ActiveWebsocket, ResyncRequired, Subscription, WebSocketMsg},
+    },
+};
+
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[utoipa::path(
+    get,
+    path = "/child/{child_name}/events",
+    tag = "notifications",
+    params(
+        ("child_name" = String, Path, description = "The child's name"),
+        ("Last-Event-ID" = Option<i64>, Header, description = "Replay transactions after this id"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent events: `transaction` (a Transaction, with its id as \
+the event id), `approval_request`, `alert` and `resync_required`", content_type = "text/event-stream"),
+        (status = 400, description = "The request failed validation", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn child_events(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath(child_name): ApiPath<String>,
+    Extension(request_trace_data): Extension<RequestTraceData>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    info!("event stream opened");
+
+    open_event_stream(
+        app_state,
+        request_trace_data,
+        Subscription::Child(child_name),
+        &headers,
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "notifications",
+    params(
+        ("Last-Event-ID" = Option<i64>, Header, description = "Replay transactions after this id"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent events for every child, as for \
+/child/{child_name}/events", content_type = "text/event-stream"),
+        (status = 400, description = "The request failed validation", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn all_events(
+    State(app_state): State<Arc<AppState>>,
+    Extension(request_trace_data): Extension<RequestTraceData>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    info!("event stream opened for all children");
+
+    open_event_stream(
+        app_state,
+        request_trace_data,
+        Subscription::AllChildren,
+        &headers,
+    )
+    .await
+}
+
+fn get_last_event_id(headers: &HeaderMap) -> Result<Option<i64>, ApiError> {
+    match headers.get(LAST_EVENT_ID_HEADER) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<i64>().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                ApiError::InputFailedValidation(String::from("Invalid Last-Event-ID header"))
+            }),
+        None => Ok(None),
+    }
+}
+
+async fn open_event_stream(
+    app_state: Arc<AppState>,
+    request_trace_data: RequestTraceData,
+    subscription: Subscription,
+    headers: &HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let last_event_id = get_last_event_id(headers)?;
+    let (ch_sender, ch_receiver) = tokio::sync::mpsc::channel::<WebSocketMsg>(
+        app_state.get_websocket_config().channel_capacity,
+    );
+
+    // Register before reading the backlog so nothing recorded in between is lost.
+    // Anything delivered twice is skipped by id in EventStreamState.
+    let listener =
+        ActiveWebsocket::new(request_trace_data.get_id(), subscription.clone(), ch_sender);
+    app_state.register_open_websocket(listener.clone()).await;
+    let registration = ListenerRegistration::new(app_state.clone(), listener.get_id());
+
+    // Replay a page at most as long as the channel. A client further behind
+    // is told to resync once it has the page, and resumes from its last id.
+    let replay_limit = app_state.get_websocket_config().channel_capacity;
+    let (backlog, backlog_truncated) = match last_event_id {
+        Some(after_id) => {
+            let child_name = match &subscription {
+                Subscription::Child(child_name) => Some(child_name.clone()),
+                _ => None,
+            };
+            let mut transactions = app_state.get_db().get_transactions_after(
+                child_name,
+                after_id,
+                Some(replay_limit + 1),
+            )?;
+            let backlog_truncated = transactions.len() > replay_limit;
+            transactions.truncate(replay_limit);
+            transactions.retain(|t| subscription.matches(&t.child_name));
+            (transactions, backlog_truncated)
+        }
+        None => (Vec::new(), false),
+    };
+    info!("replaying {} missed events", backlog.len());
+
+    let state = EventStreamState::new(
+        backlog,
+        backlog_truncated,
+        ch_receiver,
+        listener,
+        registration,
+    );
+
+    // The stream is polled after the handler returns, so carry the request
+    // span along for its logs
+    let span = Span::current();
+    let event_stream = stream::unfold(state, move |mut state| {
+        async move {
+            let event = state.next_event().await?;
+            Some((Ok(event), state))
+        }
+        .instrument(span.clone())
+    });
+
+    Ok(Sse::new(event_stream).keep_alive(
+        KeepAlive::new()
+            .interval(KEEP_ALIVE_INTERVAL)
+            .text("keep-alive"),
+    ))
+}
+
+fn to_event(transaction: &Transaction) -> Event {
+    Event::default()
+        .id(transaction.id.to_string())
+        .event("transaction")
+        .json_data(transaction)
+        .unwrap()
+}
+
+/// Approval requests aren't stored, so they carry no id and can't be replayed.
+fn approval_request_event(approval_request: ApprovalRequest) -> Event {
+    Event::default()
+        .event("approval_request")
+        .json_data(ApprovalRequestNotification { approval_request })
+        .unwrap()
+}
+
+/// Alerts aren't replayed either.
+fn alert_event(alert: Alert) -> Event {
+    Event::default()
+        .event("alert")
+        .json_data(AlertNotification { alert })
+        .unwrap()
+}
+
+/// Clients should reconnect with `Last-Event-ID` to replay what they missed.
+fn resync_required_event() -> Event {
+    Event::default()
+        .event("resync_required")
+        .json_data(ResyncRequired::default())
+        .unwrap()
+}
+
+struct EventStreamState {
+    backlog: VecDeque<Transaction>,
+    backlog_truncated: bool,
+    /// Live transactions up to the last one replayed were also in the
+    /// backlog. Later ids can arrive out of order, so aren't filtered.
+    replayed_up_to: Option<i64>,
+    finished: bool,
+    receiver: tokio::sync::mpsc::Receiver<WebSocketMsg>,
+    listener: ActiveWebsocket,
+    _registration: ListenerRegistration,
+}
+
+impl EventStreamState {
+    fn new(
+        backlog: Vec<Transaction>,
+        backlog_truncated: bool,
+        receiver: tokio::sync::mpsc::Receiver<WebSocketMsg>,
+        listener: ActiveWebsocket,
+        registration: ListenerRegistration,
+    ) -> EventStreamState {
+        EventStreamState {
+            replayed_up_to: backlog.last().map(|t| t.id),
+            backlog: VecDeque::from(backlog),
+            backlog_truncated,
+            finished: false,
+            receiver,
+            listener,
+            _registration: registration,
+        }
+    }
+
+    async fn next_event(&mut self) -> Option<Event> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            if self.listener.take_resync_required() {
+                warn!("events were dropped, requesting resync");
+                return Some(resync_required_event());
+            }
+
+            if let Some(transaction) = self.backlog.pop_front() {
+                return Some(to_event(&transaction));
+            }
+
+            // Live events would leave a gap after the page, so end the
+            // stream for the client to resume from the page's last id
+            if self.backlog_truncated {
+                warn!("too many missed events to replay, requesting resync");
+                self.finished = true;
+                return Some(resync_required_event());
+            }
+
+            let transaction = match self.receiver.recv().await {
+                Some(WebSocketMsg::Transaction(transaction)) => transaction,
+                Some(WebSocketMsg::ApprovalRequest(approval_request)) => {
+                    return Some(approval_request_event(approval_request));
+                }
+                Some(WebSocketMsg::Alert(alert)) => return Some(alert_event(alert)),
+                Some(WebSocketMsg::HouseholdReply(_))
+                | Some(WebSocketMsg::ChildCommandReply(_)) => continue,
+                Some(WebSocketMsg::CloseSocket(_)) | None => return None,
+            };
+
+            if self.replayed_up_to.is_some_and(|id| transaction.id <= id) {
+                continue;
+            }
+
+            return Some(to_event(&transaction));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use super::EventStreamState;
+    use crate::{
+        appstate::{AppState, ListenerRegistration},
+        db::Db,
+        model::{
+            amount::Amount,
+            transaction::Transaction,
+            websocket_msg::{ActiveWebsocket, CloseReason, Subscription, WebSocketMsg},
+        },
+    };
+
+    fn transaction(id: i64) -> Transaction {
+        Transaction::new(
+            id,
+            Utc::now(),
+            String::from("a"),
+            Amount::from_pence(100),
+            String::from("test"),
+        )
+    }
+
+    #[tokio::test]
+    async fn live_transactions_out_of_order_are_not_dropped_test() {
+        let app_state = Arc::new(AppState::new(Db::new()));
+        let (sender, receiver) = tokio::sync::mpsc::channel(8);
+        let listener = ActiveWebsocket::new(
+            String::from("events"),
+            Subscription::Child(String::from("a")),
+            sender.clone(),
+        );
+        let registration = ListenerRegistration::new(app_state, listener.get_id());
+        let mut state = EventStreamState::new(
+            vec![transaction(4)],
+            false,
+            receiver,
+            listener,
+            registration,
+        );
+
+        // 4 was replayed and is also delivered live, 6 is published before 5
+        for id in [4, 6, 5] {
+            sender
+                .send(WebSocketMsg::Transaction(transaction(id)))
+                .await
+                .unwrap();
+        }
+        sender
+            .send(WebSocketMsg::CloseSocket(CloseReason::Goodbye))
+            .await
+            .unwrap();
+
+        // Events only expose their encoding through Debug
+        let mut events = Vec::new();
+        while let Some(event) = state.next_event().await {
+            events.push(format!("{event:?}"));
+        }
+        assert_eq!(events.len(), 3);
+        for (event, id) in events.iter().zip([4, 6, 5]) {
+            assert!(event.contains(&format!("b\"id:{id}\\n")), "{event}");
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{str::FromStr, sync::Arc};
+
+use async_graphql::http::{WebSocket as GraphQLWebSocket, WebSocketProtocols, WsMessage};
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use futures::{future, StreamExt};
+use tracing::{info, Instrument, Span};
+
+use crate::{
+    appstate::AppState,
+    extract::ApiJson,
+    graphql::BankSchema,
+    handlers::websocket::{close_frame, ping_interval, ping_or_close, send_frame, Heartbeat},
+    model::{error::ApiError, websocket_msg::CloseReason},
+};
+
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "graphql",
+    request_body(content = Object, description = "A GraphQL request: `query`, and optionally \
+`operationName` and `variables`. Amounts are Amount scalars, written like JSON amounts."),
+    responses(
+        (status = 200, description = "The GraphQL response. Errors carry the problem details \
+`code`, `status` and any field `errors` in their extensions.", body = Object),
+        (status = 400, description = "Malformed JSON", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 415, description = "Not JSON", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 422, description = "Not a GraphQL request", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn execute_graphql(
+    Extension(schema): Extension<BankSchema>,
+    ApiJson(request): ApiJson<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    info!(
+        "graphql {}",
+        request.operation_name.as_deref().unwrap_or("(anonymous)")
+    );
+
+    Json(schema.execute(request).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/graphql",
+    tag = "graphql",
+    responses(
+        (status = 101, description = "Upgrades to a websocket for GraphQL subscriptions, \
+speaking whichever of graphql-transport-ws or graphql-ws the client offers in \
+Sec-WebSocket-Protocol."),
+        (status = 400, description = "No supported subprotocol offered", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn accept_graphql_websocket(
+    ws: WebSocketUpgrade,
+    State(app_state): State<Arc<AppState>>,
+    Extension(schema): Extension<BankSchema>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let protocol = headers
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|protocols| protocols.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| WebSocketProtocols::from_str(protocol.trim()).ok())
+        })
+        .ok_or_else(|| {
+            ApiError::InputFailedValidation(String::from(
+                "Expected Sec-WebSocket-Protocol graphql-transport-ws or graphql-ws",
+            ))
+        })?;
+    info!(
+        "graphql websocket accepted with {}",
+        protocol.sec_websocket_protocol()
+    );
+
+    let span = Span::current();
+    Ok(ws
+        .protocols([protocol.sec_websocket_protocol()])
+        .on_upgrade(move |socket| {
+            handle_socket(socket, app_state, schema, protocol).instrument(span)
+        })
+        .into_response())
+}
+
+/// async-graphql runs the protocol; this only moves frames between it and
+/// the socket, pings the peer like the other websockets, and closes it when
+/// the server shuts down.
+async fn handle_socket(
+    socket: WebSocket,
+    app_state: Arc<AppState>,
+    schema: BankSchema,
+    protocol: WebSocketProtocols,
+) {
+    let (mut sender, receiver) = socket.split();
+    let mut close_requested = app_state.watch_for_close();
+    let websocket_config = app_state.get_websocket_config();
+    let heartbeat = Heartbeat::new();
+
+    let heartbeat_for_incoming = heartbeat.clone();
+    let incoming = receiver
+        .inspect(move |msg| {
+            if msg.is_ok() {
+                heartbeat_for_incoming.touch();
+            }
+        })
+        .take_while(|msg| {
+            future::ready(matches!(msg, Ok(msg) if !matches!(msg, Message::Close(_))))
+        })
+        .filter_map(|msg| {
+            future::ready(match msg {
+                Ok(Message::Text(text)) => Some(text.into_bytes()),
+                Ok(Message::Binary(bytes)) => Some(bytes),
+                _ => None,
+            })
+        });
+
+    let mut outgoing = GraphQLWebSocket::new(schema, incoming, protocol);
+    let mut ping_interval = ping_interval(&websocket_config);
+    loop {
+        // None on a ping tick, pinged outside the select so the close
+        // watch's guard isn't held across the send
+        let frame = tokio::select! {
+            msg = outgoing.next() => match msg {
+                Some(WsMessage::Text(text)) => Some((Message::Text(text), false)),
+                Some(WsMessage::Close(code, reason)) => Some((
+                    Message::Close(Some(CloseFrame {
+                        code,
+                        reason: reason.into(),
+                    })),
+                    true,
+                )),
+                None => break,
+            },
+            Ok(reason) = close_requested.wait_for(Option::is_some) => {
+                let reason = reason.unwrap_or(CloseReason::ServerRestarting);
+                info!("close socket recieved: {:?}", reason);
+                Some((Message::Close(Some(close_frame(reason))), true))
+            }
+            _ = ping_interval.tick() => None,
+        };
+
+        let Some((msg, closing)) = frame else {
+            if !ping_or_close(&mut sender, &heartbeat, &websocket_config).await {
+                break;
+            }
+            continue;
+        };
+
+        if !send_frame(&mut sender, msg, "Text", &websocket_config).await || closing {
+            break;
+        }
+    }
+
+    info!("graphql websocket closed");
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::{
+    appstate::AppState,
+    model::health::{BuildInfo, Readiness},
+};
+
+/// Probes for a process supervisor. They aren't part of the API, so they
+/// aren't versioned or in the OpenAPI spec.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_readiness))
+        .route("/version", get(get_version))
+}
+
+/// Answers whenever the process is serving requests at all.
+pub async fn get_health() -> Json<Value> {
+    debug!("get_health");
+
+    Json(json!({ "status": "ok" }))
+}
+
+/// 200 when the server should be sent traffic, otherwise 503 with the
+/// checks that failed.
+pub async fn get_readiness(
+    State(app_state): State<Arc<AppState>>,
+) -> (StatusCode, Json<Readiness>) {
+    debug!("get_readiness");
+
+    let readiness = app_state.check_readiness();
+    if readiness.ready {
+        (StatusCode::OK, Json(readiness))
+    } else {
+        warn!("not ready: {:?}", readiness);
+        (StatusCode::SERVICE_UNAVAILABLE, Json(readiness))
+    }
+}
+
+pub async fn get_version() -> Json<BuildInfo> {
+    debug!("get_version");
+
+    Json(BuildInfo::current())
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderValue},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use tracing::{debug, error};
+
+use crate::{appstate::AppState, middleware::metrics::metrics};
+
+/// The Prometheus scrape endpoint. Like the health probes it isn't part of
+/// the API, so it isn't versioned or in the OpenAPI spec.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/metrics", get(get_metrics))
+}
+
+pub async fn get_metrics(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    debug!("get_metrics");
+
+    let metrics = metrics();
+    metrics
+        .open_websockets
+        .set(app_state.count_open_websockets().await.total as i64);
+    match app_state.get_db().get_total_balance() {
+        Ok(total) => metrics.money_held.set(total.to_pence() as f64 / 100.0),
+        Err(e) => error!("can't read total balance for metrics: {:?}", e),
+    }
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        metrics.encode(),
+    )
+}
@@ -0,0 +1,19 @@
+use axum::Json;
+use tracing::info;
+use utoipa::OpenApi;
+
+use crate::openapi::ApiDoc;
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "openapi",
+    responses(
+        (status = 200, description = "This document", content_type = "application/json"),
+    )
+)]
+pub async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    info!("get_openapi");
+
+    Json(ApiDoc::openapi())
+}
@@ -1,117 +1,389 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
-    Extension, Json,
+    extract::State,
+    http::{HeaderMap, HeaderValue},
+    Json,
 };
 
 use chrono::Utc;
-use log::info;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+use utoipa::ToSchema;
 
 use crate::{
+    alerts::evaluate_alert_rules,
     appstate::AppState,
-    middleware::request_tracing::RequestTraceData,
-    model::{amount::Amount, error::ApiError, transaction::Transaction},
+    db::Db,
+    extract::{ApiJson, ApiPath},
+    middleware::metrics::metrics,
+    model::{
+        amount::Amount,
+        error::{check_fields, ApiError, FieldError, FieldErrorCode},
+        event::BankEvent,
+        transaction::Transaction,
+        websocket_command::ApprovalRequest,
+    },
 };
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct GiveMoney {
     pub amount: Amount,
     pub purpose: String,
 }
 
-#[derive(Debug, PartialEq)]
-enum TransactionType {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionType {
     Give,
     Spend,
 }
 
+impl TransactionType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Give => "give",
+            Self::Spend => "spend",
+        }
+    }
+}
+
+/// Lets a client retry a give or spend, e.g. one queued while offline,
+/// without it being recorded twice.
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+/// Set on a response that repeats the transaction recorded the first time
+/// its Idempotency-Key was used.
+pub const IDEMPOTENT_REPLAYED: &str = "Idempotent-Replayed";
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH => {
+            Ok(Some(String::from(key)))
+        }
+        _ => Err(ApiError::InvalidFields(vec![FieldError::new(
+            IDEMPOTENCY_KEY,
+            FieldErrorCode::InvalidFormat,
+            "Idempotency-Key must be 1 to 255 printable ASCII characters",
+        )])),
+    }
+}
+
+/// Identifies a request, so an Idempotency-Key can't be replayed for a
+/// different one.
+fn fingerprint(
+    child_name: &str,
+    give_money: &GiveMoney,
+    transaction_type: TransactionType,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{:?}\n{}\n{}\n{}",
+        transaction_type,
+        child_name,
+        give_money.amount.to_pence(),
+        give_money.purpose
+    ));
+    hex::encode(hasher.finalize())
+}
+
 fn validate_request_body(give_money: &GiveMoney) -> Result<(), ApiError> {
+    let mut errors = Vec::new();
+
     if !give_money.amount.is_positive_nonzero() {
-        return Err(ApiError::InputFailedValidation(String::from(
+        errors.push(FieldError::new(
+            "amount",
+            FieldErrorCode::NotPositive,
             "Amount must be at least 0.01",
-        )));
+        ));
     }
 
-    if give_money.purpose.len() == 0 {
-        return Err(ApiError::InputFailedValidation(String::from(
+    if give_money.purpose.is_empty() {
+        errors.push(FieldError::new(
+            "purpose",
+            FieldErrorCode::Empty,
             "Must provide a purpose",
-        )));
+        ));
     }
 
-    return Ok(());
+    check_fields(errors)
 }
 
-async fn record_transaction(
-    app_state: Arc<AppState>,
+/// Validates and records a give or spend, then notifies listeners, followed
+/// by any alerts it triggers. Shared by the HTTP handlers and websocket
+/// commands.
+pub async fn record_transaction_for_child(
+    app_state: &AppState,
     child_name: String,
-    request_trace_data: RequestTraceData,
     give_money: GiveMoney,
     transaction_type: TransactionType,
-) -> Result<Json<Transaction>, ApiError> {
-    let request_id = request_trace_data.get_id();
-    info!(
-        "[{}] {:?} called with {:?}",
-        request_id, transaction_type, give_money
-    );
+) -> Result<Transaction, ApiError> {
+    let persisted_transaction = record_transaction_quietly_for_child(
+        &app_state.get_db(),
+        child_name,
+        give_money,
+        transaction_type,
+    )?;
+
+    metrics()
+        .transactions_recorded
+        .with_label_values(&[transaction_type.name()])
+        .inc();
+    publish_transaction(app_state, &persisted_transaction, false).await;
 
+    Ok(persisted_transaction)
+}
+
+/// Validates and records a give or spend without notifying anyone or
+/// evaluating alert rules, for changes made directly to the database file
+/// while no server may be running.
+pub fn record_transaction_quietly_for_child(
+    db: &Db,
+    child_name: String,
+    give_money: GiveMoney,
+    transaction_type: TransactionType,
+) -> Result<Transaction, ApiError> {
+    validate_request_body(&give_money)?;
+
+    db.record_transaction_for_child(new_transaction(child_name, give_money, transaction_type))
+}
+
+/// As `record_transaction_for_child`, but only the first request with
+/// `idempotency_key` is recorded and published. Repeats get the same
+/// transaction back, with true. A request that failed, e.g. for
+/// insufficient funds, doesn't use up its key.
+pub async fn record_transaction_idempotently_for_child(
+    app_state: &AppState,
+    child_name: String,
+    give_money: GiveMoney,
+    transaction_type: TransactionType,
+    idempotency_key: &str,
+) -> Result<(Transaction, bool), ApiError> {
     validate_request_body(&give_money)?;
 
+    let fingerprint = fingerprint(&child_name, &give_money, transaction_type);
+    let (persisted_transaction, replayed) = app_state.get_db().record_transaction_idempotently(
+        idempotency_key,
+        &fingerprint,
+        new_transaction(child_name, give_money, transaction_type),
+    )?;
+
+    if !replayed {
+        metrics()
+            .transactions_recorded
+            .with_label_values(&[transaction_type.name()])
+            .inc();
+        publish_transaction(app_state, &persisted_transaction, false).await;
+    }
+
+    Ok((persisted_transaction, replayed))
+}
+
+fn new_transaction(
+    child_name: String,
+    give_money: GiveMoney,
+    transaction_type: TransactionType,
+) -> Transaction {
     let mut amount = give_money.amount;
     if transaction_type == TransactionType::Spend {
         amount = amount.negate();
     }
 
-    let persisted_transaction =
-        app_state
-            .get_db()
-            .record_transaction_for_child(Transaction::new(
-                0,
-                Utc::now(),
-                child_name.clone(),
-                amount,
-                give_money.purpose.clone(),
-            ))?;
+    Transaction::new(0, Utc::now(), child_name, amount, give_money.purpose)
+}
+
+/// Records a transaction cancelling out an earlier one, then notifies
+/// listeners as for any other transaction.
+pub async fn reverse_transaction_for_child(
+    app_state: &AppState,
+    child_name: String,
+    id: i64,
+) -> Result<Transaction, ApiError> {
+    let reversal = app_state
+        .get_db()
+        .reverse_transaction_for_child(&child_name, id, Utc::now())?;
+
+    metrics()
+        .transactions_recorded
+        .with_label_values(&["reversal"])
+        .inc();
+    publish_transaction(app_state, &reversal, true).await;
+
+    Ok(reversal)
+}
+
+async fn publish_transaction(app_state: &AppState, transaction: &Transaction, is_reversal: bool) {
+    app_state
+        .publish_event(BankEvent::TransactionRecorded(transaction.clone()))
+        .await;
+
+    // The transaction is committed either way, so a failure here is only logged
+    match evaluate_alert_rules(&app_state.get_db(), transaction, is_reversal) {
+        Ok(alerts) => {
+            for alert in alerts {
+                app_state
+                    .publish_event(BankEvent::AlertTriggered(alert))
+                    .await;
+            }
+        }
+        Err(e) => error!("failed to evaluate alert rules: {:?}", e),
+    }
+}
+
+/// Validates a spend a child would like a parent to approve. Nothing is
+/// recorded, the request is only passed on to the child's listeners.
+pub async fn request_approval_for_child(
+    app_state: &AppState,
+    child_name: String,
+    give_money: GiveMoney,
+) -> Result<ApprovalRequest, ApiError> {
+    validate_request_body(&give_money)?;
+
+    let approval_request = ApprovalRequest {
+        child_name: child_name.clone(),
+        amount: give_money.amount,
+        purpose: give_money.purpose,
+    };
 
     app_state
-        .queue_messages_to_active_websockets_for_child(
-            child_name.clone(),
-            crate::model::websocket_msg::WebSocketMsg::Transaction(persisted_transaction.clone()),
-        )
+        .publish_event(BankEvent::ApprovalRequested(approval_request.clone()))
         .await;
 
-    Ok(Json(persisted_transaction))
+    Ok(approval_request)
+}
+
+async fn record_transaction(
+    app_state: Arc<AppState>,
+    child_name: String,
+    headers: HeaderMap,
+    give_money: GiveMoney,
+    transaction_type: TransactionType,
+) -> Result<(HeaderMap, Json<Transaction>), ApiError> {
+    info!("{:?} called with {:?}", transaction_type, give_money);
+
+    let mut response_headers = HeaderMap::new();
+    let persisted_transaction = match idempotency_key(&headers)? {
+        Some(key) => {
+            let (persisted_transaction, replayed) = record_transaction_idempotently_for_child(
+                &app_state,
+                child_name,
+                give_money,
+                transaction_type,
+                &key,
+            )
+            .await?;
+            if replayed {
+                info!("replayed Idempotency-Key {}", key);
+                response_headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+            }
+            persisted_transaction
+        }
+        None => {
+            record_transaction_for_child(&app_state, child_name, give_money, transaction_type)
+                .await?
+        }
+    };
+
+    Ok((response_headers, Json(persisted_transaction)))
 }
 
+#[utoipa::path(
+    post,
+    path = "/child/{child_name}/give",
+    tag = "children",
+    params(
+        ("child_name" = String, Path, description = "The child's name"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Any unique string. Retrying with the same key and request within 24 hours returns the first transaction rather than recording another"),
+    ),
+    request_body = GiveMoney,
+    responses(
+        (status = 200, description = "The recorded transaction", body = Transaction,
+            headers(("Idempotent-Replayed" = String, description = "true when this repeats an earlier request with the same Idempotency-Key"))),
+        (status = 400, description = "The request failed validation", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 409, description = "The Idempotency-Key was used for a different request", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 422, description = "The body isn't a valid GiveMoney", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 415, description = "The Content-Type isn't application/json", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
 pub async fn give(
     State(app_state): State<Arc<AppState>>,
-    Path(child_name): Path<String>,
-    Extension(request_trace_data): Extension<RequestTraceData>,
-    Json(give_money): Json<GiveMoney>,
-) -> Result<Json<Transaction>, ApiError> {
+    ApiPath(child_name): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(give_money): ApiJson<GiveMoney>,
+) -> Result<(HeaderMap, Json<Transaction>), ApiError> {
     record_transaction(
         app_state,
         child_name,
-        request_trace_data,
+        headers,
         give_money,
         TransactionType::Give,
     )
     .await
 }
 
+#[utoipa::path(
+    post,
+    path = "/child/{child_name}/spend",
+    tag = "children",
+    params(
+        ("child_name" = String, Path, description = "The child's name"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Any unique string. Retrying with the same key and request within 24 hours returns the first transaction rather than recording another"),
+    ),
+    request_body = GiveMoney,
+    responses(
+        (status = 200, description = "The recorded transaction, with a negative amount", body = Transaction,
+            headers(("Idempotent-Replayed" = String, description = "true when this repeats an earlier request with the same Idempotency-Key"))),
+        (status = 400, description = "The request failed validation", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 409, description = "The Idempotency-Key was used for a different request", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 422, description = "The body isn't a valid GiveMoney", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 415, description = "The Content-Type isn't application/json", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
 pub async fn spend(
     State(app_state): State<Arc<AppState>>,
-    Path(child_name): Path<String>,
-    Extension(request_trace_data): Extension<RequestTraceData>,
-    Json(give_money): Json<GiveMoney>,
-) -> Result<Json<Transaction>, ApiError> {
+    ApiPath(child_name): ApiPath<String>,
+    headers: HeaderMap,
+    ApiJson(give_money): ApiJson<GiveMoney>,
+) -> Result<(HeaderMap, Json<Transaction>), ApiError> {
     record_transaction(
         app_state,
         child_name,
-        request_trace_data,
+        headers,
         give_money,
         TransactionType::Spend,
     )
     .await
 }
+
+#[utoipa::path(
+    post,
+    path = "/child/{child_name}/transactions/{id}/reverse",
+    tag = "children",
+    params(
+        ("child_name" = String, Path, description = "The child's name"),
+        ("id" = i64, Path, description = "The transaction to reverse"),
+    ),
+    responses(
+        (status = 200, description = "The reversal, with the opposite amount", body = Transaction),
+        (status = 400, description = "Reversing a give would take the account negative", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 404, description = "The child has no such transaction", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 409, description = "The transaction was already reversed, or is a reversal", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn reverse(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath((child_name, id)): ApiPath<(String, i64)>,
+) -> Result<Json<Transaction>, ApiError> {
+    info!("reverse {} {}", child_name, id);
+
+    let reversal = reverse_transaction_for_child(&app_state, child_name, id).await?;
+
+    Ok(Json(reversal))
+}
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{StatusCode, Uri},
+    Json,
+};
+use tracing::info;
+
+use crate::{
+    appstate::AppState,
+    extract::{ApiJson, ApiPath},
+    model::{
+        error::{check_fields, ApiError, FieldError, FieldErrorCode},
+        event::EVENT_TYPES,
+        webhook::{CreatedWebhook, NewWebhook, Webhook, WebhookDelivery},
+    },
+};
+
+fn validate_new_webhook(new_webhook: &NewWebhook) -> Result<(), ApiError> {
+    let mut errors = Vec::new();
+
+    let valid_url = new_webhook.url.parse::<Uri>().is_ok_and(|uri| {
+        matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some()
+    });
+    if !valid_url {
+        errors.push(FieldError::new(
+            "url",
+            FieldErrorCode::InvalidFormat,
+            "Must provide an http or https url",
+        ));
+    }
+
+    for (index, unknown) in new_webhook
+        .event_types
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| !EVENT_TYPES.contains(&t.as_str()))
+    {
+        errors.push(FieldError::new(
+            &format!("event_types[{}]", index),
+            FieldErrorCode::InvalidType,
+            &format!("Unknown event type '{}'", unknown),
+        ));
+    }
+
+    check_fields(errors)
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = NewWebhook,
+    responses(
+        (status = 200, description = "The webhook, with the secret deliveries are signed with", body = CreatedWebhook),
+        (status = 400, description = "The request failed validation", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 422, description = "The body isn't a valid NewWebhook", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 415, description = "The Content-Type isn't application/json", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn create_webhook(
+    State(app_state): State<Arc<AppState>>,
+    ApiJson(new_webhook): ApiJson<NewWebhook>,
+) -> Result<Json<CreatedWebhook>, ApiError> {
+    info!("create_webhook called with {:?}", new_webhook);
+
+    validate_new_webhook(&new_webhook)?;
+
+    let secret = nanoid::nanoid!(32);
+    let webhook = app_state.get_db().create_webhook(
+        new_webhook.url,
+        new_webhook.event_types,
+        secret.clone(),
+    )?;
+
+    Ok(Json(CreatedWebhook { webhook, secret }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, body = [Webhook]),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_webhooks(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    info!("list_webhooks");
+
+    Ok(Json(app_state.get_db().get_webhooks()?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i64, Path, description = "The webhook's id")),
+    responses(
+        (status = 204, description = "The webhook and its pending deliveries were deleted"),
+        (status = 404, description = "Not found", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn delete_webhook(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath(id): ApiPath<i64>,
+) -> Result<StatusCode, ApiError> {
+    info!("delete_webhook {}", id);
+
+    if app_state.get_db().delete_webhook(id)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::PathNotFound(format!("/webhooks/{}", id)))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks/dead_letters",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "Deliveries that ran out of attempts", body = [WebhookDelivery]),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn list_dead_letters(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<WebhookDelivery>>, ApiError> {
+    info!("list_dead_letters");
+
+    Ok(Json(app_state.get_db().get_dead_webhook_deliveries()?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks/dead_letters/{id}/retry",
+    tag = "webhooks",
+    params(("id" = i64, Path, description = "The delivery's id")),
+    responses(
+        (status = 202, description = "The delivery was queued again"),
+        (status = 404, description = "Not found", body = ErrorResponse, content_type = "application/problem+json"),
+        (status = 500, description = "Internal error", body = ErrorResponse, content_type = "application/problem+json"),
+    ),
+)]
+pub async fn retry_dead_letter(
+    State(app_state): State<Arc<AppState>>,
+    ApiPath(id): ApiPath<i64>,
+) -> Result<StatusCode, ApiError> {
+    info!("retry_dead_letter {}", id);
+
+    if app_state.get_db().retry_dead_webhook_delivery(id)? {
+        Ok(StatusCode::ACCEPTED)
+    } else {
+        Err(ApiError::PathNotFound(format!(
+            "/webhooks/dead_letters/{}",
+            id
+        )))
+    }
+}
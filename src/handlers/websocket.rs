@@ -1,9 +1,14 @@
-use std::{borrow::Cow, error::Error, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    error::Error,
+    sync::{Arc, Mutex},
+};
 
 use axum::{
     extract::{
         ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
-        Path, State,
+        State,
     },
     response::IntoResponse,
     Extension,
@@ -13,29 +18,95 @@ use futures::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
-use log::{info, warn};
+use serde::Serialize;
+use tokio::time::{timeout, Instant, Interval, MissedTickBehavior};
+use tracing::{debug, info, warn, Instrument, Span};
 
 use crate::{
-    appstate::AppState, middleware::request_tracing::RequestTraceData,
-    model::websocket_msg::ActiveWebsocket, model::websocket_msg::WebSocketMsg,
+    appstate::{AppState, WebsocketConfig},
+    extract::ApiPath,
+    handlers::record_transaction::{
+        record_transaction_for_child, request_approval_for_child, GiveMoney, TransactionType,
+    },
+    middleware::request_tracing::RequestTraceData,
+    model::alert::AlertNotification,
+    model::error::ApiError,
+    model::websocket_command::{
+        ApprovalRequestNotification, ChildCommand, ChildCommandOutcome, ChildCommandReply,
+        ChildCommandRequest, ChildCommandResult,
+    },
+    model::websocket_msg::{
+        ActiveWebsocket, CloseReason, HouseholdCommand, HouseholdReply, ResyncRequired,
+        Subscription, WebSocketMsg,
+    },
 };
 
+/// The most transactions a `list_transactions` command replies with, and
+/// how many it gets without a limit.
+const MAX_LISTED_TRANSACTIONS: usize = 100;
+
+#[utoipa::path(
+    get,
+    path = "/child/{child_name}/notifications",
+    tag = "notifications",
+    params(("child_name" = String, Path, description = "The child's name")),
+    responses(
+        (status = 101, description = "Upgrades to a websocket. The server sends each new Transaction, \
+AlertNotification and ApprovalRequestNotification for the child as a JSON text frame, or \
+ResyncRequired if the client fell behind. The client may send ChildCommandRequest frames and \
+gets a ChildCommandReply for each."),
+    ),
+)]
 pub async fn accept_websocket(
     ws: WebSocketUpgrade,
-    Path(child_name): Path<String>,
+    ApiPath(child_name): ApiPath<String>,
     State(app_state): State<Arc<AppState>>,
     Extension(request_trace_data): Extension<RequestTraceData>,
 ) -> impl IntoResponse {
-    let request_id = request_trace_data.get_id();
-    info!("[{request_id}] websocket accepted for child {child_name}.");
+    info!("websocket accepted");
+
+    // The socket outlives the request, so take its span along explicitly
+    let span = Span::current();
+    ws.on_upgrade(move |socket| {
+        handle_socket(
+            socket,
+            app_state,
+            request_trace_data,
+            Subscription::Child(child_name),
+        )
+        .instrument(span)
+    })
+}
 
+/// Household notifications start with no subscriptions, the client picks
+/// children with `HouseholdCommand` text frames.
+#[utoipa::path(
+    get,
+    path = "/notifications",
+    tag = "notifications",
+    responses(
+        (status = 101, description = "Upgrades to a websocket that starts with no subscriptions. \
+The client sends HouseholdCommand frames to pick children and gets a HouseholdReply for each. \
+The server then sends each Transaction, AlertNotification and ApprovalRequestNotification for \
+those children as JSON text frames, or ResyncRequired if the client fell behind."),
+    ),
+)]
+pub async fn accept_household_websocket(
+    ws: WebSocketUpgrade,
+    State(app_state): State<Arc<AppState>>,
+    Extension(request_trace_data): Extension<RequestTraceData>,
+) -> impl IntoResponse {
+    info!("household websocket accepted");
+
+    let span = Span::current();
     ws.on_upgrade(move |socket| {
         handle_socket(
             socket,
             app_state,
             request_trace_data,
-            child_name.to_string(),
+            Subscription::Children(BTreeSet::new()),
         )
+        .instrument(span)
     })
 }
 
@@ -43,133 +114,428 @@ async fn handle_socket(
     socket: WebSocket,
     app_state: Arc<AppState>,
     request_trace_data: RequestTraceData,
-    child_name: String,
+    subscription: Subscription,
 ) {
     let request_id = request_trace_data.get_id();
     let (ws_sender, ws_receiver) = socket.split();
-    let (ch_sender, ch_receiver) = tokio::sync::mpsc::channel::<WebSocketMsg>(32);
+    let (ch_sender, ch_receiver) = tokio::sync::mpsc::channel::<WebSocketMsg>(
+        app_state.get_websocket_config().channel_capacity,
+    );
 
     let websocket_details =
-        ActiveWebsocket::new(request_id.clone(), child_name.clone(), ch_sender.clone());
+        ActiveWebsocket::new(request_id.clone(), subscription, ch_sender.clone());
     app_state
         .register_open_websocket(websocket_details.clone())
         .await;
 
+    let heartbeat = Heartbeat::new();
+
     let ws_details_for_outgoing_task = websocket_details.clone();
-    let ws_outgoing_task = tokio::spawn(async move {
-        websocket_outgoing(ws_details_for_outgoing_task, ch_receiver, ws_sender).await
-    });
+    let heartbeat_for_outgoing_task = heartbeat.clone();
+    let websocket_config = app_state.get_websocket_config();
+    let ws_outgoing_task = tokio::spawn(
+        async move {
+            websocket_outgoing(
+                ws_details_for_outgoing_task,
+                heartbeat_for_outgoing_task,
+                websocket_config,
+                ch_receiver,
+                ws_sender,
+            )
+            .await
+        }
+        .in_current_span(),
+    );
 
-    let ws_incoming_task =
-        tokio::spawn(async move { websocket_incoming(websocket_details, ws_receiver).await });
+    let app_state_for_incoming_task = app_state.clone();
+    let ws_incoming_task = tokio::spawn(
+        async move {
+            websocket_incoming(
+                app_state_for_incoming_task,
+                websocket_details,
+                heartbeat,
+                websocket_config,
+                ws_receiver,
+            )
+            .await
+        }
+        .in_current_span(),
+    );
 
-    let _ = tokio::join!(ws_outgoing_task, ws_incoming_task);
+    // The outgoing task finishes once the socket is closed from either side.
+    // A dead peer never wakes the incoming task, so stop it here rather than
+    // waiting on it.
+    let _ = ws_outgoing_task.await;
+    ws_incoming_task.abort();
     app_state.deregister_open_websocket(request_id).await;
 }
 
+/// When the peer was last heard from, shared between the incoming and
+/// outgoing sides of a socket.
+#[derive(Clone)]
+pub struct Heartbeat {
+    last_seen: Arc<Mutex<Instant>>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heartbeat {
+    pub fn new() -> Heartbeat {
+        Heartbeat {
+            last_seen: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn touch(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    fn is_expired(&self, websocket_config: &WebsocketConfig) -> bool {
+        self.last_seen.lock().unwrap().elapsed()
+            > websocket_config.ping_interval + websocket_config.pong_timeout
+    }
+}
+
+/// Ticks every ping interval, starting one interval from now.
+pub fn ping_interval(websocket_config: &WebsocketConfig) -> Interval {
+    let mut ping_interval = tokio::time::interval_at(
+        Instant::now() + websocket_config.ping_interval,
+        websocket_config.ping_interval,
+    );
+    ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ping_interval
+}
+
+/// On each ping interval tick: closes the socket if the peer has sent
+/// nothing back since before the pong deadline, otherwise pings it.
+/// Returns false if the socket should be dropped.
+pub async fn ping_or_close(
+    ws_sender: &mut SplitSink<WebSocket, Message>,
+    heartbeat: &Heartbeat,
+    websocket_config: &WebsocketConfig,
+) -> bool {
+    if heartbeat.is_expired(websocket_config) {
+        warn!("no pong before deadline, closing");
+        let frame = Message::Close(Some(CloseFrame {
+            code: axum::extract::ws::close_code::AWAY,
+            reason: Cow::from("Pong timeout"),
+        }));
+        send_frame(ws_sender, frame, "Close/Timeout", websocket_config).await;
+        return false;
+    }
+
+    send_frame(
+        ws_sender,
+        Message::Ping(Vec::new()),
+        "Ping",
+        websocket_config,
+    )
+    .await
+}
+
 async fn websocket_outgoing(
     active_websocket: ActiveWebsocket,
+    heartbeat: Heartbeat,
+    websocket_config: WebsocketConfig,
     mut rcv_channel: tokio::sync::mpsc::Receiver<WebSocketMsg>,
     mut ws_sender: SplitSink<WebSocket, Message>,
 ) -> () {
-    let log_prefix = active_websocket.get_log_prefix();
-    info!("{} Starting outgoing websocket", log_prefix);
+    info!(
+        "starting outgoing websocket for {}",
+        active_websocket.get_subscription()
+    );
+
+    let mut ping_interval = ping_interval(&websocket_config);
 
     loop {
-        let msg = rcv_channel.recv().await;
-
-        match msg {
-            Some(WebSocketMsg::CloseSocket()) => {
-                info!("{} close socket recieved", log_prefix);
-                let ws_send_res: Result<(), axum::Error> = ws_sender
-                    .send(Message::Close(Some(CloseFrame {
-                        code: axum::extract::ws::close_code::NORMAL,
-                        reason: Cow::from("Goodbye"),
-                    })))
-                    .await;
-                log_on_error(&log_prefix, "Close", "ws_sender", ws_send_res);
+        let msg = tokio::select! {
+            msg = rcv_channel.recv() => msg,
+            _ = ping_interval.tick() => {
+                if !ping_or_close(&mut ws_sender, &heartbeat, &websocket_config).await {
+                    break;
+                }
+                continue;
+            }
+        };
 
-                break;
+        let (frame, msg_type, closing) = match msg {
+            Some(WebSocketMsg::CloseSocket(reason)) => {
+                info!("close socket recieved: {:?}", reason);
+                (Message::Close(Some(close_frame(reason))), "Close", true)
             }
             Some(WebSocketMsg::Transaction(t)) => {
-                info!("{} notification recieved: {:?}", log_prefix, t);
-                let ws_send_res = ws_sender
-                    .send(Message::Text(serde_json::to_string(&t).unwrap()))
-                    .await;
-                log_on_error(&log_prefix, "Text", "ws_sender", ws_send_res);
+                info!("notification recieved: {:?}", t);
+                (text_frame(&t), "Text", false)
+            }
+            Some(WebSocketMsg::HouseholdReply(reply)) => {
+                info!("household reply: {:?}", reply);
+                (text_frame(&reply), "Text", false)
+            }
+            Some(WebSocketMsg::ChildCommandReply(reply)) => {
+                info!("command reply: {:?}", reply);
+                (text_frame(&reply), "Text", false)
+            }
+            Some(WebSocketMsg::ApprovalRequest(approval_request)) => {
+                info!("approval request: {:?}", approval_request);
+                (
+                    text_frame(&ApprovalRequestNotification { approval_request }),
+                    "Text",
+                    false,
+                )
+            }
+            Some(WebSocketMsg::Alert(alert)) => {
+                info!("alert: {:?}", alert);
+                (text_frame(&AlertNotification { alert }), "Text", false)
             }
             None => {
-                info!("{} none recieved, all senders likely dropped", log_prefix);
-
-                let ws_send_res = ws_sender
-                    .send(Message::Close(Some(CloseFrame {
-                        code: axum::extract::ws::close_code::NORMAL,
-                        reason: Cow::from("Goodbye"),
-                    })))
-                    .await;
-                log_on_error(&log_prefix, "Close", "ws_sender", ws_send_res);
+                info!("none recieved, all senders likely dropped");
+                (
+                    Message::Close(Some(close_frame(CloseReason::Goodbye))),
+                    "Close",
+                    true,
+                )
+            }
+        };
+        if !send_frame(&mut ws_sender, frame, msg_type, &websocket_config).await || closing {
+            break;
+        }
 
+        if active_websocket.take_resync_required() {
+            warn!("messages were dropped, requesting resync");
+            let frame = text_frame(&ResyncRequired::default());
+            if !send_frame(&mut ws_sender, frame, "Text/Resync", &websocket_config).await {
                 break;
             }
         }
     }
 
-    info!("{} websocket_outgoing exiting", log_prefix);
+    info!("websocket_outgoing exiting");
+}
+
+fn text_frame<T: Serialize>(msg: &T) -> Message {
+    Message::Text(serde_json::to_string(msg).unwrap())
+}
+
+/// Sends a frame, giving the peer up to the pong timeout to take it.
+/// A half-open peer's TCP buffer fills up and would otherwise block the
+/// send, and the pong deadline with it, forever. Returns false if the
+/// socket should be dropped.
+pub async fn send_frame(
+    ws_sender: &mut SplitSink<WebSocket, Message>,
+    frame: Message,
+    msg_type: &str,
+    websocket_config: &WebsocketConfig,
+) -> bool {
+    match timeout(websocket_config.pong_timeout, ws_sender.send(frame)).await {
+        Ok(ws_send_res) => {
+            log_on_error(msg_type, "ws_sender", ws_send_res);
+            true
+        }
+        Err(_) => {
+            warn!("peer stopped reading, {} frame timed out", msg_type);
+            false
+        }
+    }
 }
 
 async fn websocket_incoming(
+    app_state: Arc<AppState>,
     active_websocket: ActiveWebsocket,
+    heartbeat: Heartbeat,
+    websocket_config: WebsocketConfig,
     mut websocket_reciever: SplitStream<WebSocket>,
 ) -> () {
-    let log_prefix = active_websocket.get_log_prefix();
     loop {
         let frame = websocket_reciever.next().await;
+        if let Some(Ok(_)) = frame {
+            heartbeat.touch();
+        }
         {
             match frame {
                 Some(Ok(Message::Text(msg))) => {
-                    info!("{} ok {}", log_prefix, msg);
+                    info!("ok {}", msg);
+                    let reply = match active_websocket.get_subscription() {
+                        Subscription::Children(_) => WebSocketMsg::HouseholdReply(
+                            handle_household_command(&app_state, &active_websocket, &msg).await,
+                        ),
+                        Subscription::Child(child_name) => WebSocketMsg::ChildCommandReply(
+                            handle_child_command(&app_state, child_name, &msg).await,
+                        ),
+                        Subscription::AllChildren => continue,
+                    };
+                    // Unlike fan-out, a reply is never dropped: the client
+                    // can't tell otherwise whether its command was recorded.
+                    // A channel that stays full this long means the peer
+                    // stopped reading, and the outgoing task is dropping it.
+                    match timeout(
+                        websocket_config.pong_timeout,
+                        active_websocket.send_message(reply),
+                    )
+                    .await
+                    {
+                        Ok(ws_send_res) => {
+                            log_on_error("Text/Reply", "active_websocket", ws_send_res)
+                        }
+                        Err(_) => {
+                            warn!("peer stopped reading, reply timed out");
+                            break;
+                        }
+                    }
                 }
                 Some(Ok(Message::Close(_))) => {
-                    info!("{} ok close", log_prefix);
+                    info!("ok close");
                     let ws_send_res = active_websocket
-                        .send_message(WebSocketMsg::CloseSocket())
+                        .send_message(WebSocketMsg::CloseSocket(CloseReason::Goodbye))
                         .await;
-                    log_on_error(&log_prefix, "Close/Close", "active_websocket", ws_send_res);
+                    log_on_error("Close/Close", "active_websocket", ws_send_res);
                     break;
                 }
+                Some(Ok(Message::Pong(_))) => {
+                    debug!("ok pong");
+                }
                 Some(Ok(_)) => {
-                    info!("{} ok unhandled", log_prefix);
+                    info!("ok unhandled");
                 }
                 Some(Err(_)) => {
-                    info!("{} ok error", log_prefix);
+                    info!("ok error");
                     let ws_send_res = active_websocket
-                        .send_message(WebSocketMsg::CloseSocket())
+                        .send_message(WebSocketMsg::CloseSocket(CloseReason::Goodbye))
                         .await;
-                    log_on_error(&log_prefix, "Close/Err", "active_websocket", ws_send_res);
+                    log_on_error("Close/Err", "active_websocket", ws_send_res);
                     break;
                 }
                 None => {
-                    info!("{} None", log_prefix);
+                    info!("None");
                     let ws_send_res = active_websocket
-                        .send_message(WebSocketMsg::CloseSocket())
+                        .send_message(WebSocketMsg::CloseSocket(CloseReason::Goodbye))
                         .await;
-                    log_on_error(&log_prefix, "Close/None", "active_websocket", ws_send_res);
+                    log_on_error("Close/None", "active_websocket", ws_send_res);
                     break;
                 }
             }
         }
     }
-    info!("{} websocket_incoming exiting", log_prefix);
+    info!("websocket_incoming exiting");
+}
+
+async fn handle_household_command(
+    app_state: &AppState,
+    active_websocket: &ActiveWebsocket,
+    msg: &str,
+) -> HouseholdReply {
+    let command = match serde_json::from_str::<HouseholdCommand>(msg) {
+        Ok(command) => command,
+        Err(e) => return HouseholdReply::Error(format!("Invalid command: {}", e)),
+    };
+
+    match app_state
+        .apply_household_command(active_websocket.get_id(), &command)
+        .await
+    {
+        Some(Subscription::Children(child_names)) => {
+            HouseholdReply::Subscriptions(child_names.into_iter().collect())
+        }
+        _ => HouseholdReply::Error(String::from("Subscriptions can't be changed")),
+    }
+}
+
+async fn handle_child_command(
+    app_state: &AppState,
+    child_name: String,
+    msg: &str,
+) -> ChildCommandReply {
+    let request = match serde_json::from_str::<ChildCommandRequest>(msg) {
+        Ok(request) => request,
+        Err(e) => {
+            // Echo the id back if there is one, so the client can still correlate
+            let id = serde_json::from_str::<serde_json::Value>(msg)
+                .ok()
+                .and_then(|v| v.get("id").and_then(|id| id.as_str()).map(String::from))
+                .unwrap_or_default();
+            return ChildCommandReply {
+                id,
+                outcome: ChildCommandOutcome::Error(
+                    ApiError::InputFailedValidation(format!("Invalid command: {}", e))
+                        .to_error_details(),
+                ),
+            };
+        }
+    };
+
+    let result = match request.command {
+        ChildCommand::GetBalance => app_state
+            .get_db()
+            .get_account_balance_for_child(child_name)
+            .map(|balance| ChildCommandResult::Balance { balance }),
+        ChildCommand::ListTransactions { limit } => app_state
+            .get_db()
+            .get_last_transactions_for_child(
+                child_name,
+                limit
+                    .unwrap_or(MAX_LISTED_TRANSACTIONS)
+                    .min(MAX_LISTED_TRANSACTIONS),
+            )
+            .map(|transactions| ChildCommandResult::Transactions { transactions }),
+        ChildCommand::Give { amount, purpose } => record_transaction_for_child(
+            app_state,
+            child_name,
+            GiveMoney { amount, purpose },
+            TransactionType::Give,
+        )
+        .await
+        .map(|transaction| ChildCommandResult::Transaction { transaction }),
+        ChildCommand::Spend { amount, purpose } => record_transaction_for_child(
+            app_state,
+            child_name,
+            GiveMoney { amount, purpose },
+            TransactionType::Spend,
+        )
+        .await
+        .map(|transaction| ChildCommandResult::Transaction { transaction }),
+        ChildCommand::RequestApproval { amount, purpose } => {
+            request_approval_for_child(app_state, child_name, GiveMoney { amount, purpose })
+                .await
+                .map(|approval_request| ChildCommandResult::ApprovalRequested { approval_request })
+        }
+    };
+
+    ChildCommandReply {
+        id: request.id,
+        outcome: match result {
+            Ok(result) => ChildCommandOutcome::Result(result),
+            Err(e) => {
+                warn!("command failed with public_reason={}", e.public_reason());
+                ChildCommandOutcome::Error(e.to_error_details())
+            }
+        },
+    }
+}
+
+pub fn close_frame(reason: CloseReason) -> CloseFrame<'static> {
+    match reason {
+        CloseReason::Goodbye => CloseFrame {
+            code: axum::extract::ws::close_code::NORMAL,
+            reason: Cow::from("Goodbye"),
+        },
+        CloseReason::ServerRestarting => CloseFrame {
+            code: axum::extract::ws::close_code::RESTART,
+            reason: Cow::from("Server restarting"),
+        },
+    }
 }
 
-fn log_on_error<E>(log_prefix: &str, msg_type: &str, channel_name: &str, result: Result<(), E>)
+fn log_on_error<E>(msg_type: &str, channel_name: &str, result: Result<(), E>)
 where
     E: Error,
 {
     if let Err(e) = result {
         warn!(
-            "{} Failed to add {} message to {} channel: {}",
-            log_prefix, msg_type, channel_name, e
+            "Failed to add {} message to {} channel: {}",
+            msg_type, channel_name, e
         );
     }
 }
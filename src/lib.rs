@@ -1,42 +1,295 @@
 use std::sync::Arc;
 
 use axum::{
-    routing::{get, post},
-    Router,
+    handler::Handler,
+    http::Method,
+    routing::{on, MethodFilter, MethodRouter},
+    Extension, Router,
 };
 
 use db::Db;
+use tracing::error;
 
 use tower::ServiceBuilder;
 
-use crate::appstate::AppState;
+use crate::{
+    api_version::ApiVersion,
+    appstate::AppState,
+    config::{Config, Features},
+    notifications::{
+        email::EmailNotifier,
+        mqtt::MqttPublisher,
+        webhooks::{WebhookConfig, WebhookDispatcher},
+    },
+};
 
+pub mod alerts;
+pub mod api_version;
 pub mod appstate;
+pub mod config;
+pub mod dashboard;
 pub mod db;
+pub mod extract;
+pub mod graphql;
 pub mod handlers;
 pub mod middleware;
 pub mod model;
+pub mod notifications;
+pub mod openapi;
+pub mod shutdown;
+pub mod telemetry;
 
+/// Builds the API with the default configuration. Must be called from
+/// within a tokio runtime.
 pub fn router(db: Db) -> Router {
-    let app_state = Arc::new(AppState::new(db));
+    router_with_config(db, &Config::default())
+}
 
-    Router::new()
-        .route("/child/:child_name", get(crate::handlers::child::get_child))
-        .route(
-            "/child/:child_name/give",
-            post(crate::handlers::record_transaction::give),
+/// Builds the API as configured, with webhook delivery running if enabled
+/// and email and MQTT if configured. Must be called from within a tokio
+/// runtime.
+pub fn router_with_config(db: Db, config: &Config) -> Router {
+    router_with_state_and_config(Arc::new(app_state_with_config(db, config)), config)
+}
+
+/// The state `router_with_config` serves, for callers that need to reach
+/// it afterwards, e.g. to close websockets on shutdown.
+pub fn app_state_with_config(db: Db, config: &Config) -> AppState {
+    let mut app_state = AppState::new(db)
+        .with_websocket_config(config.websocket.websocket_config())
+        .with_features(config.features);
+
+    if config.features.webhooks {
+        let webhooks = WebhookDispatcher::start(app_state.get_db(), WebhookConfig::default());
+        app_state = app_state.with_event_listener(Arc::new(webhooks));
+    }
+
+    if let Some(email) = &config.email {
+        match EmailNotifier::start(app_state.get_db(), email.email_config()) {
+            Ok(email) => app_state = app_state.with_event_listener(Arc::new(email)),
+            Err(e) => error!("email notifications disabled: {}", e),
+        }
+    }
+
+    if let Some(mqtt) = &config.mqtt {
+        let mqtt = MqttPublisher::start(app_state.get_db(), mqtt.mqtt_config());
+        app_state = app_state.with_event_listener(Arc::new(mqtt));
+    }
+
+    app_state
+}
+
+pub fn router_with_state_and_config(app_state: Arc<AppState>, config: &Config) -> Router {
+    let app = router_with_state(app_state);
+    match config.cors_layer() {
+        Some(cors) => app.layer(cors),
+        None => app,
+    }
+}
+
+pub fn router_with_state(app_state: Arc<AppState>) -> Router {
+    let graphql_schema = graphql::build_schema(app_state.clone());
+    let features = app_state.get_features();
+
+    let mut app = Router::new()
+        .nest(
+            ApiVersion::V1.prefix(),
+            api_routes(ApiVersion::V1, api_route_table(features)),
         )
-        .route(
-            "/child/:child_name/spend",
-            post(crate::handlers::record_transaction::spend),
+        .merge(
+            api_routes(ApiVersion::V1, unversioned_route_table(features)).layer(
+                axum::middleware::from_fn(crate::middleware::deprecation::unversioned_alias),
+            ),
         )
-        .route(
-            "/child/:child_name/notifications",
-            get(crate::handlers::websocket::accept_websocket),
+        .merge(crate::handlers::health::routes())
+        .merge(crate::handlers::metrics::routes());
+    if features.dashboard {
+        app = app.merge(dashboard::routes());
+    }
+
+    app.fallback(crate::handlers::path_not_found::handler_404)
+        .layer(Extension(graphql_schema))
+        .layer(
+            ServiceBuilder::new()
+                .layer(axum::middleware::from_fn(
+                    crate::middleware::request_tracing::request_tracing,
+                ))
+                .layer(axum::middleware::from_fn(
+                    crate::middleware::metrics::track_metrics,
+                )),
         )
-        .fallback(crate::handlers::path_not_found::handler_404)
-        .layer(ServiceBuilder::new().layer(axum::middleware::from_fn(
-            crate::middleware::request_tracing::request_tracing,
-        )))
         .with_state(app_state)
-}
\ No newline at end of file
+}
+
+/// The API as one version serves it. Versions share handlers; a handler
+/// whose response shape differs between versions reads `ApiVersion` from
+/// the request extensions and serializes accordingly.
+fn api_routes(version: ApiVersion, routes: Vec<ApiRoute>) -> Router<Arc<AppState>> {
+    routes
+        .into_iter()
+        .fold(Router::new(), |routes, route| {
+            routes.route(route.path, route.method_router)
+        })
+        .layer(Extension(version))
+}
+
+/// The paths clients were using before the API was versioned. Only these
+/// keep an unprefixed, deprecated alias; routes added since are only served
+/// under a version.
+const UNVERSIONED_PATHS: &[&str] = &[
+    "/child/:child_name",
+    "/child/:child_name/give",
+    "/child/:child_name/spend",
+    "/child/:child_name/alert_rules",
+    "/child/:child_name/alert_rules/:id",
+    "/child/:child_name/notifications",
+    "/child/:child_name/events",
+    "/events",
+    "/notifications",
+    "/email_preferences",
+    "/email_preferences/:id",
+    "/diagnostics/websockets",
+];
+
+fn unversioned_route_table(features: Features) -> Vec<ApiRoute> {
+    api_route_table(features)
+        .into_iter()
+        .filter(|route| UNVERSIONED_PATHS.contains(&route.path))
+        .collect()
+}
+
+/// One method on one path of the API, relative to the version prefix.
+pub struct ApiRoute {
+    /// In axum's form, e.g. `/child/:child_name`.
+    pub path: &'static str,
+    pub method: Method,
+    method_router: MethodRouter<Arc<AppState>>,
+}
+
+impl ApiRoute {
+    fn new<H, T>(path: &'static str, method: Method, handler: H) -> ApiRoute
+    where
+        H: Handler<T, Arc<AppState>>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(method.clone()).expect("routable method");
+        ApiRoute {
+            path,
+            method,
+            method_router: on(filter, handler),
+        }
+    }
+}
+
+/// Every route the API serves with these features, which is what
+/// `api_routes` builds from, so the list can be checked against the
+/// OpenAPI spec.
+pub fn api_route_table(features: Features) -> Vec<ApiRoute> {
+    use crate::handlers::{
+        alert_rules, child, children, diagnostics, email_preferences, events, graphql, openapi,
+        record_transaction, webhooks, websocket,
+    };
+
+    let mut routes = vec![
+        ApiRoute::new("/child/:child_name", Method::GET, child::get_child),
+        ApiRoute::new(
+            "/child/:child_name/give",
+            Method::POST,
+            record_transaction::give,
+        ),
+        ApiRoute::new(
+            "/child/:child_name/spend",
+            Method::POST,
+            record_transaction::spend,
+        ),
+        ApiRoute::new(
+            "/child/:child_name/transactions/:id/reverse",
+            Method::POST,
+            record_transaction::reverse,
+        ),
+        ApiRoute::new("/children", Method::GET, children::list_children),
+        ApiRoute::new(
+            "/child/:child_name/alert_rules",
+            Method::GET,
+            alert_rules::list_alert_rules,
+        ),
+        ApiRoute::new(
+            "/child/:child_name/alert_rules",
+            Method::POST,
+            alert_rules::create_alert_rule,
+        ),
+        ApiRoute::new(
+            "/child/:child_name/alert_rules/:id",
+            Method::DELETE,
+            alert_rules::delete_alert_rule,
+        ),
+        ApiRoute::new(
+            "/child/:child_name/notifications",
+            Method::GET,
+            websocket::accept_websocket,
+        ),
+        ApiRoute::new(
+            "/child/:child_name/events",
+            Method::GET,
+            events::child_events,
+        ),
+        ApiRoute::new("/events", Method::GET, events::all_events),
+        ApiRoute::new(
+            "/notifications",
+            Method::GET,
+            websocket::accept_household_websocket,
+        ),
+        ApiRoute::new(
+            "/email_preferences",
+            Method::GET,
+            email_preferences::list_email_preferences,
+        ),
+        ApiRoute::new(
+            "/email_preferences",
+            Method::POST,
+            email_preferences::upsert_email_preferences,
+        ),
+        ApiRoute::new(
+            "/email_preferences/:id",
+            Method::DELETE,
+            email_preferences::delete_email_preferences,
+        ),
+        ApiRoute::new(
+            "/diagnostics/websockets",
+            Method::GET,
+            diagnostics::get_websocket_counts,
+        ),
+        ApiRoute::new(
+            "/diagnostics/integrity",
+            Method::GET,
+            diagnostics::get_integrity,
+        ),
+        ApiRoute::new("/openapi.json", Method::GET, openapi::get_openapi),
+    ];
+
+    if features.webhooks {
+        routes.extend([
+            ApiRoute::new("/webhooks", Method::GET, webhooks::list_webhooks),
+            ApiRoute::new("/webhooks", Method::POST, webhooks::create_webhook),
+            ApiRoute::new("/webhooks/:id", Method::DELETE, webhooks::delete_webhook),
+            ApiRoute::new(
+                "/webhooks/dead_letters",
+                Method::GET,
+                webhooks::list_dead_letters,
+            ),
+            ApiRoute::new(
+                "/webhooks/dead_letters/:id/retry",
+                Method::POST,
+                webhooks::retry_dead_letter,
+            ),
+        ]);
+    }
+    if features.graphql {
+        routes.extend([
+            ApiRoute::new("/graphql", Method::GET, graphql::accept_graphql_websocket),
+            ApiRoute::new("/graphql", Method::POST, graphql::execute_graphql),
+        ]);
+    }
+
+    routes
+}
@@ -1,19 +1,82 @@
-use std::net::{Ipv6Addr, SocketAddr};
+use std::{net::SocketAddr, process, sync::Arc};
 
-use bank_of_dad::{db::Db, router};
-use log::info;
+use bank_of_dad::{
+    app_state_with_config,
+    config::{Args, Config},
+    db::Db,
+    router_with_state_and_config, shutdown,
+    telemetry::{self, Telemetry},
+};
+use clap::Parser;
+use tracing::{error, info};
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt().with_thread_ids(true).init();
+    let config = match Config::load(Args::parse()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+    let telemetry = telemetry::init(&config);
 
     info!("started");
 
-    let db = Db::new();
-    let app = router(db);
+    // In memory unless a file is given, e.g. for bankctl to manage
+    let db = match &config.database.path {
+        Some(path) => {
+            info!("using database {}", path.display());
+            Db::open(path).unwrap_or_else(|e| {
+                error!("can't open database {}: {}", path.display(), e);
+                process::exit(1);
+            })
+        }
+        None => Db::new(),
+    };
+    let app_state = Arc::new(app_state_with_config(db, &config));
+    let app = router_with_state_and_config(app_state.clone(), &config);
 
-    axum::Server::bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, 3000)))
+    let server = axum::Server::try_bind(&config.socket_addr()).unwrap_or_else(|e| {
+        error!("can't listen on {}: {}", config.socket_addr(), e);
+        process::exit(1);
+    });
+    info!("listening on {}", config.socket_addr());
+
+    let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel::<()>();
+    let server = server
         .serve(app.into_make_service_with_connect_info::<SocketAddr>())
-        .await
-        .unwrap();
+        .with_graceful_shutdown(async {
+            let _ = stop_receiver.await;
+        });
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => server_stopped(result, telemetry),
+        _ = shutdown::signal() => {}
+    }
+
+    // Go unready but keep serving while supervisors notice, then stop
+    // accepting connections and drain what's already running
+    app_state.begin_shutdown();
+    info!("unready, serving for another {:?}", config.shutdown_grace());
+    tokio::select! {
+        result = &mut server => server_stopped(result, telemetry),
+        _ = tokio::time::sleep(config.shutdown_grace()) => {}
+    }
+
+    let _ = stop_sender.send(());
+    shutdown::drain(&app_state, server, config.shutdown_timeout()).await;
+    info!("stopped");
+    telemetry.shutdown();
+}
+
+/// The server only stops by itself when it fails.
+fn server_stopped(result: hyper::Result<()>, telemetry: Telemetry) -> ! {
+    match result {
+        Ok(()) => error!("server stopped unexpectedly"),
+        Err(e) => error!("server failed: {}", e),
+    }
+    telemetry.shutdown();
+    process::exit(1);
 }
@@ -1 +1,3 @@
+pub mod deprecation;
+pub mod metrics;
 pub mod request_tracing;
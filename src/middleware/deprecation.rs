@@ -0,0 +1,30 @@
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::IntoResponse,
+};
+use tracing::warn;
+
+use crate::api_version::ApiVersion;
+
+/// When the unprefixed aliases stop being served, as an RFC 8594 HTTP-date.
+pub const UNVERSIONED_SUNSET: &str = "Wed, 30 Jun 2027 00:00:00 GMT";
+
+/// Marks responses from the unprefixed paths as deprecated, pointing clients
+/// at the same path under `/v1`.
+pub async fn unversioned_alias<T>(req: Request<T>, next: Next<T>) -> impl IntoResponse {
+    let successor = format!("{}{}", ApiVersion::V1.prefix(), req.uri().path());
+    warn!("deprecated unversioned path, use {}", successor);
+
+    let mut response = next.run(req).await;
+
+    let headers = response.headers_mut();
+    headers.insert("Deprecation", HeaderValue::from_static("true"));
+    headers.insert("Sunset", HeaderValue::from_static(UNVERSIONED_SUNSET));
+    if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+    {
+        headers.insert("Link", link);
+    }
+
+    response
+}
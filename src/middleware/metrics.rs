@@ -0,0 +1,159 @@
+use std::{sync::OnceLock, time::Instant};
+
+use axum::{
+    extract::MatchedPath,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    core::Collector, Encoder, Gauge, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+/// Every metric the server reports at `/metrics`. There's one set per
+/// process, as `Db` records into it without access to `AppState`.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    /// Registered websockets and event streams. Set when scraped.
+    pub open_websockets: IntGauge,
+    pub fan_out_messages: IntCounter,
+    pub fan_out_dropped: IntCounter,
+    /// By `type`: give, spend or reversal.
+    pub transactions_recorded: IntCounterVec,
+    pub overdrafts_rejected: IntCounter,
+    pub db_lock_wait: Histogram,
+    /// Sum of every child's balance. Set when scraped.
+    pub money_held: Gauge,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new_custom(Some(String::from("bank_of_dad")), None).unwrap();
+
+        Metrics {
+            http_requests: registered(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new("http_requests_total", "HTTP requests by route and status"),
+                    &["method", "route", "status"],
+                ),
+            ),
+            http_request_duration: registered(
+                &registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "http_request_duration_seconds",
+                        "Time to respond to HTTP requests by route and status",
+                    ),
+                    &["method", "route", "status"],
+                ),
+            ),
+            open_websockets: registered(
+                &registry,
+                IntGauge::new(
+                    "open_websockets",
+                    "Websockets, event streams and subscriptions receiving fan-out",
+                ),
+            ),
+            fan_out_messages: registered(
+                &registry,
+                IntCounter::new(
+                    "fan_out_messages_total",
+                    "Messages queued to websockets, event streams and subscriptions",
+                ),
+            ),
+            fan_out_dropped: registered(
+                &registry,
+                IntCounter::new(
+                    "fan_out_dropped_total",
+                    "Messages dropped for lagging listeners, which are told to resync",
+                ),
+            ),
+            transactions_recorded: registered(
+                &registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        "transactions_recorded_total",
+                        "Transactions recorded by type",
+                    ),
+                    &["type"],
+                ),
+            ),
+            overdrafts_rejected: registered(
+                &registry,
+                IntCounter::new(
+                    "overdrafts_rejected_total",
+                    "Transactions refused for taking a balance negative",
+                ),
+            ),
+            db_lock_wait: registered(
+                &registry,
+                Histogram::with_opts(
+                    HistogramOpts::new(
+                        "db_lock_wait_seconds",
+                        "Time spent waiting for the database connection",
+                    )
+                    .buckets(vec![
+                        0.000_01, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0,
+                    ]),
+                ),
+            ),
+            money_held: registered(
+                &registry,
+                Gauge::new("money_held_pounds", "Sum of every child's balance"),
+            ),
+            registry,
+        }
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+/// The names and labels are fixed, so failing here is a bug.
+fn registered<C>(registry: &Registry, collector: prometheus::Result<C>) -> C
+where
+    C: Collector + Clone + 'static,
+{
+    let collector = collector.unwrap();
+    registry.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+/// Counts and times each request by the route it matched, rather than its
+/// path, so a label is never a child's name.
+pub async fn track_metrics<T>(req: Request<T>, next: Next<T>) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched_path| matched_path.as_str().to_owned())
+        .unwrap_or_else(|| String::from("unmatched"));
+    let method = req.method().clone();
+    let started = Instant::now();
+
+    let response = next.run(req).await.into_response();
+
+    let status = response.status().as_u16().to_string();
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    metrics().http_requests.with_label_values(&labels).inc();
+    metrics()
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+
+    response
+}
@@ -1,22 +1,108 @@
-use std::net::SocketAddr;
+use std::{collections::HashMap, net::SocketAddr};
 
 use axum::{
-    extract::ConnectInfo,
-    http::{HeaderValue, Request},
+    extract::{ConnectInfo, Path},
+    http::{HeaderMap, HeaderValue, Request},
     middleware::Next,
     response::IntoResponse,
 };
-use log::info;
+use tracing::{field, info, info_span, warn, Instrument, Span};
+
+const TRACEPARENT_HEADER: &str = "traceparent";
+const HEX: [char; 16] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f',
+];
+
+/// A W3C Trace Context `traceparent`: the trace a request is part of and
+/// the span that made it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParent {
+    /// 32 lowercase hex digits.
+    pub trace_id: String,
+    /// 16 lowercase hex digits.
+    pub parent_id: String,
+    pub sampled: bool,
+}
+
+impl TraceParent {
+    /// Parses a `traceparent` header, or None if it's invalid, in which case
+    /// the request starts a new trace.
+    pub fn parse(value: &str) -> Option<TraceParent> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+
+        // Later versions may add fields, but must keep these
+        if !is_hex(version, 2) || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        if !is_hex(trace_id, 32) || !is_hex(parent_id, 16) || !is_hex(flags, 2) {
+            return None;
+        }
+        if is_zero(trace_id) || is_zero(parent_id) {
+            return None;
+        }
+
+        Some(TraceParent {
+            trace_id: String::from(trace_id),
+            parent_id: String::from(parent_id),
+            sampled: u8::from_str_radix(flags, 16).ok()? & 1 == 1,
+        })
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Option<TraceParent> {
+        TraceParent::parse(headers.get(TRACEPARENT_HEADER)?.to_str().ok()?)
+    }
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn is_zero(s: &str) -> bool {
+    s.bytes().all(|b| b == b'0')
+}
+
+fn random_hex(len: usize) -> String {
+    nanoid::nanoid!(len, &HEX)
+}
 
 #[derive(Clone)]
 pub struct RequestTraceData {
+    /// The request's span id, which is also its `X-Request-Id`.
     id: String,
+    trace_id: String,
 }
 
 impl RequestTraceData {
+    /// Joins the caller's trace if it sent one, otherwise starts a new one.
+    /// When spans are exported the ids are the exported span's, so the
+    /// request id can be looked up in the collector.
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    fn new(span: &Span, trace_parent: Option<TraceParent>) -> RequestTraceData {
+        #[cfg(feature = "otel")]
+        if let Some((trace_id, id)) = crate::telemetry::link(span, trace_parent.as_ref()) {
+            return RequestTraceData { id, trace_id };
+        }
+
+        RequestTraceData {
+            id: random_hex(16),
+            trace_id: match trace_parent {
+                Some(trace_parent) => trace_parent.trace_id,
+                None => random_hex(32),
+            },
+        }
+    }
+
     pub fn get_id(&self) -> String {
         return self.id.clone();
     }
+
+    pub fn get_trace_id(&self) -> String {
+        self.trace_id.clone()
+    }
 }
 
 fn get_remote_ip_addr<T>(req: &Request<T>) -> String {
@@ -28,33 +114,124 @@ fn get_remote_ip_addr<T>(req: &Request<T>) -> String {
     }
 }
 
+/// The header's value, or `not-set` if it's missing or isn't visible ASCII.
 fn get_header_or<T>(req: &Request<T>, key: String) -> String {
-    match req.headers().get(key) {
-        Some(header) => header.to_str().unwrap().to_string(),
-        None => String::from("not-set"),
+    match req.headers().get(key).map(|header| header.to_str()) {
+        Some(Ok(header)) => header.to_string(),
+        _ => String::from("not-set"),
     }
 }
 
-pub async fn request_tracing<T>(mut req: Request<T>, next: Next<T>) -> impl IntoResponse {
-    let request_id = nanoid::nanoid!(10);
+/// Runs each request in a `request` span, so everything logged while
+/// handling it, including by websocket and event stream tasks it starts,
+/// carries the request id and the child it's for.
+pub async fn request_tracing<T>(
+    path_params: Option<Path<HashMap<String, String>>>,
+    mut req: Request<T>,
+    next: Next<T>,
+) -> impl IntoResponse {
+    let child_name = path_params.and_then(|Path(mut params)| params.remove("child_name"));
 
-    info!(
-        "[{}] {} '{}' {} {}",
-        request_id,
-        get_remote_ip_addr(&req),
-        get_header_or(&req, String::from("user-agent")),
-        req.method().as_str(),
-        req.uri().to_string(),
+    let span = info_span!(
+        "request",
+        request_id = field::Empty,
+        trace_id = field::Empty,
+        method = %req.method(),
+        path = %req.uri().path(),
+        remote_ip = %get_remote_ip_addr(&req),
+        child_name = child_name.as_deref(),
     );
-
-    req.extensions_mut().insert(RequestTraceData {
-        id: request_id.clone(),
+    let request_trace_data = RequestTraceData::new(&span, TraceParent::from_headers(req.headers()));
+    span.record("request_id", request_trace_data.id.as_str());
+    span.record("trace_id", request_trace_data.trace_id.as_str());
+    span.in_scope(|| {
+        info!(
+            user_agent = %get_header_or(&req, String::from("user-agent")),
+            "started"
+        )
     });
-    let mut response = next.run(req).await;
 
-    response
-        .headers_mut()
-        .insert("X-Request-Id", HeaderValue::from_str(&request_id).unwrap());
+    let request_id = request_trace_data.get_id();
+    req.extensions_mut().insert(request_trace_data);
+    let mut response = next.run(req).instrument(span).await;
+
+    // Always hex, but a bad id is no reason to fail the response
+    match HeaderValue::from_str(&request_id) {
+        Ok(request_id) => {
+            response.headers_mut().insert("X-Request-Id", request_id);
+        }
+        Err(e) => warn!("can't send X-Request-Id {:?}: {}", request_id, e),
+    }
 
     response
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderValue, Request};
+    use tracing::Span;
+
+    use super::{get_header_or, RequestTraceData, TraceParent};
+
+    #[test]
+    fn traceparent_test() {
+        assert_eq!(
+            TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            Some(TraceParent {
+                trace_id: String::from("4bf92f3577b34da6a3ce929d0e0e4736"),
+                parent_id: String::from("00f067aa0ba902b7"),
+                sampled: true,
+            })
+        );
+        // A later version may have more fields
+        assert_eq!(
+            TraceParent::parse("cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-what")
+                .map(|t| t.sampled),
+            Some(false)
+        );
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(TraceParent::parse(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn trace_ids_test() {
+        let trace_parent =
+            TraceParent::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+        let joined = RequestTraceData::new(&Span::none(), trace_parent);
+        assert_eq!(joined.get_trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(joined.get_id().len(), 16);
+        assert_ne!(joined.get_id(), "00f067aa0ba902b7");
+
+        let started = RequestTraceData::new(&Span::none(), None);
+        assert_eq!(started.get_trace_id().len(), 32);
+        assert_ne!(started.get_trace_id(), joined.get_trace_id());
+        assert_ne!(started.get_id(), joined.get_id());
+    }
+
+    #[test]
+    fn get_header_or_test() {
+        let mut req = Request::new(());
+        assert_eq!(get_header_or(&req, String::from("user-agent")), "not-set");
+
+        req.headers_mut()
+            .insert("user-agent", HeaderValue::from_static("curl/8.0"));
+        assert_eq!(get_header_or(&req, String::from("user-agent")), "curl/8.0");
+
+        req.headers_mut().insert(
+            "user-agent",
+            HeaderValue::from_bytes("café".as_bytes()).unwrap(),
+        );
+        assert_eq!(get_header_or(&req, String::from("user-agent")), "not-set");
+    }
+}
@@ -1,4 +1,11 @@
+pub mod alert;
 pub mod amount;
+pub mod email;
 pub mod error;
+pub mod event;
+pub mod health;
+pub mod integrity;
 pub mod transaction;
+pub mod webhook;
+pub mod websocket_command;
 pub mod websocket_msg;
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::amount::Amount;
+
+const DEFAULT_COOLDOWN_SECONDS: u64 = 60 * 60;
+/// A year, well within what the database stores as milliseconds.
+pub const MAX_COOLDOWN_SECONDS: u64 = 365 * 24 * 60 * 60;
+
+fn default_cooldown_seconds() -> u64 {
+    DEFAULT_COOLDOWN_SECONDS
+}
+
+/// What a rule watches for, e.g. `{"kind":"balance_below","amount":2}`.
+/// Every condition is checked after a spend.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// The balance left after a spend is below `amount`.
+    BalanceBelow { amount: Amount },
+    /// More than `count` spends on the same (UTC) day.
+    SpendsPerDayOver { count: u32 },
+    /// A single spend took more than `percent` of the balance before it.
+    SpendOverBalancePercent { percent: u32 },
+}
+
+/// The state of a child's account just after a spend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendContext {
+    pub spent: Amount,
+    pub balance_after: Amount,
+    pub spends_today: u32,
+}
+
+impl AlertCondition {
+    /// Describes why the condition holds, or None if it doesn't.
+    pub fn check(&self, child_name: &str, context: &SpendContext) -> Option<String> {
+        match self {
+            Self::BalanceBelow { amount } if context.balance_after < *amount => Some(format!(
+                "{}'s balance is down to {}, below {}",
+                child_name, context.balance_after, amount
+            )),
+            Self::SpendsPerDayOver { count } if context.spends_today > *count => Some(format!(
+                "{} has made {} spends today, more than {}",
+                child_name, context.spends_today, count
+            )),
+            Self::SpendOverBalancePercent { percent } => {
+                let balance_before = context.balance_after + context.spent;
+                // Widened so large balances can't overflow
+                if i128::from(context.spent.to_pence()) * 100
+                    > i128::from(balance_before.to_pence()) * i128::from(*percent)
+                {
+                    Some(format!(
+                        "{} spent {} of their {} balance, more than {}%",
+                        child_name, context.spent, balance_before, percent
+                    ))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct NewAlertRule {
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    /// Once triggered, the rule stays quiet for this long, at most a year.
+    #[serde(default = "default_cooldown_seconds")]
+    pub cooldown_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct AlertRule {
+    pub id: i64,
+    pub child_name: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    pub cooldown_seconds: u64,
+    pub last_triggered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct Alert {
+    pub rule_id: i64,
+    pub child_name: String,
+    pub condition: AlertCondition,
+    pub message: String,
+    pub transaction_id: i64,
+    pub triggered_at: DateTime<Utc>,
+}
+
+/// How an alert is sent to websocket and event stream clients, e.g.
+/// `{"alert":{...}}`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct AlertNotification {
+    pub alert: Alert,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::amount::Amount;
+
+    use super::{AlertCondition, NewAlertRule, SpendContext};
+
+    fn context(spent: i64, balance_after: i64, spends_today: u32) -> SpendContext {
+        SpendContext {
+            spent: Amount::from_pence(spent),
+            balance_after: Amount::from_pence(balance_after),
+            spends_today,
+        }
+    }
+
+    #[test]
+    fn new_alert_rule_deserialize_test() {
+        assert_eq!(
+            serde_json::from_str::<NewAlertRule>(r#"{"kind":"balance_below","amount":2}"#).unwrap(),
+            NewAlertRule {
+                condition: AlertCondition::BalanceBelow {
+                    amount: Amount::from_pence(200)
+                },
+                cooldown_seconds: 3600,
+            }
+        );
+        assert!(serde_json::from_str::<NewAlertRule>(r#"{"kind":"anything"}"#).is_err());
+    }
+
+    #[test]
+    fn check_test() {
+        let balance_below = AlertCondition::BalanceBelow {
+            amount: Amount::from_pence(200),
+        };
+        assert_eq!(balance_below.check("ava", &context(100, 200, 1)), None);
+        assert_eq!(
+            balance_below.check("ava", &context(100, 199, 1)).unwrap(),
+            "ava's balance is down to 1.99, below 2.00"
+        );
+
+        let spends_per_day = AlertCondition::SpendsPerDayOver { count: 3 };
+        assert_eq!(spends_per_day.check("ava", &context(1, 0, 3)), None);
+        assert!(spends_per_day.check("ava", &context(1, 0, 4)).is_some());
+
+        let percent = AlertCondition::SpendOverBalancePercent { percent: 50 };
+        assert_eq!(percent.check("ava", &context(500, 500, 1)), None);
+        assert_eq!(
+            percent.check("ava", &context(501, 499, 1)).unwrap(),
+            "ava spent 5.01 of their 10.00 balance, more than 50%"
+        );
+
+        let percent = AlertCondition::SpendOverBalancePercent { percent: 100 };
+        assert_eq!(percent.check("ava", &context(i64::MAX, 0, 1)), None);
+        let percent = AlertCondition::SpendOverBalancePercent { percent: 1 };
+        assert!(percent
+            .check("ava", &context(i64::MAX / 2, i64::MAX / 2, 1))
+            .is_some());
+    }
+}
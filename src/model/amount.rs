@@ -1,3 +1,4 @@
+use async_graphql::{InputValueError, InputValueResult, Scalar, ScalarType, Value};
 use regex::Regex;
 use serde::{
     de::{self},
@@ -7,10 +8,16 @@ use std::{fmt::Display, ops::Add};
 
 use serde::{Deserialize, Deserializer, Serializer};
 use serde_json::Number;
+use utoipa::openapi::{schema::Schema, ObjectBuilder, RefOr, SchemaFormat, SchemaType};
+use utoipa::ToSchema;
 
 const MIN_AMOUNT_POUNDS: i64 = i64::MIN / 100;
 const MAX_AMOUNT_POUNDS: i64 = i64::MAX / 100;
 
+/// Deserialization error messages, matched on to report `invalid_amount`.
+pub const INVALID_AMOUNT: &str = "Invalid amount";
+pub const UNPARSEABLE_AMOUNT: &str = "Failed to parse amount";
+
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Amount {
     amount: i64,
@@ -21,6 +28,10 @@ impl Amount {
         Amount { amount }
     }
 
+    pub fn to_pence(&self) -> i64 {
+        self.amount
+    }
+
     pub fn is_positive_nonzero(&self) -> bool {
         self.amount > 0
     }
@@ -82,7 +93,7 @@ impl<'de> Deserialize<'de> for Amount {
 
         if let Some(v) = n.as_i64() {
             if v <= MIN_AMOUNT_POUNDS || v >= MAX_AMOUNT_POUNDS {
-                return Err(de::Error::custom("Invalid amount"));
+                return Err(de::Error::custom(INVALID_AMOUNT));
             } else {
                 return Ok(Amount::from_pence(v * 100));
             }
@@ -90,14 +101,14 @@ impl<'de> Deserialize<'de> for Amount {
 
         if let Some(v) = n.as_u64() {
             if v >= MAX_AMOUNT_POUNDS as u64 {
-                return Err(de::Error::custom("Invalid amount"));
+                return Err(de::Error::custom(INVALID_AMOUNT));
             } else {
                 match i64::try_from(v) {
                     Ok(v) => {
                         return Ok(Amount::from_pence(v * 100));
                     }
                     Err(_) => {
-                        return Err(de::Error::custom("Failed to parse"));
+                        return Err(de::Error::custom(UNPARSEABLE_AMOUNT));
                     }
                 }
             }
@@ -108,7 +119,7 @@ impl<'de> Deserialize<'de> for Amount {
             if caps.len() == 4 {
                 if let Ok(pounds_value) = i64::from_str_radix(&caps[1], 10) {
                     if pounds_value <= MIN_AMOUNT_POUNDS || pounds_value >= MAX_AMOUNT_POUNDS {
-                        return Err(de::Error::custom("Invalid amount"));
+                        return Err(de::Error::custom(INVALID_AMOUNT));
                     } else {
                         if let Ok(mut pence_value) = i64::from_str_radix(&caps[3], 10) {
                             if caps[1].starts_with('-') {
@@ -121,7 +132,42 @@ impl<'de> Deserialize<'de> for Amount {
             }
         }
 
-        Err(de::Error::custom("Failed to parse"))
+        Err(de::Error::custom(UNPARSEABLE_AMOUNT))
+    }
+}
+
+impl<'s> ToSchema<'s> for Amount {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "Amount",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::Number)
+                .format(Some(SchemaFormat::Custom(String::from("decimal"))))
+                .multiple_of(Some(0.01))
+                .description(Some(
+                    "An amount of money in pounds, as a JSON number with exactly 2 decimal \
+                     places, e.g. 1.50. Whole numbers such as 2 are accepted as input.",
+                ))
+                .example(serde_json::from_str("1.50").ok())
+                .into(),
+        )
+    }
+}
+
+/// The same decimal number as in JSON bodies. Literals and variables keep
+/// their digits, so `1.50` stays `1.50` rather than becoming a float.
+#[Scalar(name = "Amount")]
+impl ScalarType for Amount {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        // Variables arrive as serde_json's arbitrary precision number object
+        let json = value
+            .into_json()
+            .map_err(|e| InputValueError::custom(e.to_string()))?;
+        serde_json::from_value(json).map_err(|e| InputValueError::custom(e.to_string()))
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Number(serde_json::from_str(&self.to_string()).unwrap())
     }
 }
 
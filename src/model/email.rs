@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::amount::Amount;
+
+/// A parent's email settings, keyed by address. Posting again for the same
+/// address replaces the previous settings.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewEmailPreferences {
+    pub email: String,
+    /// Children to hear about. Empty means every child.
+    #[serde(default)]
+    pub children: Vec<String>,
+    /// Email whenever a single spend is over this amount. None turns spend
+    /// alerts off.
+    #[serde(default)]
+    pub spend_threshold: Option<Amount>,
+    #[serde(default)]
+    pub weekly_digest: bool,
+    /// Email alerts from the children's alert rules.
+    #[serde(default = "default_alerts")]
+    pub alerts: bool,
+}
+
+fn default_alerts() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct EmailPreferences {
+    pub id: i64,
+    pub email: String,
+    pub children: Vec<String>,
+    pub spend_threshold: Option<Amount>,
+    pub weekly_digest: bool,
+    pub alerts: bool,
+}
+
+impl EmailPreferences {
+    pub fn includes_child(&self, child_name: &str) -> bool {
+        self.children.is_empty() || self.children.iter().any(|c| c == child_name)
+    }
+}
@@ -1,22 +1,231 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use log::{error, warn};
 use rusqlite::Error;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+use utoipa::ToSchema;
+
+const PROBLEM_JSON: &str = "application/problem+json";
 
 #[derive(Debug)]
 pub enum ApiError {
     InternalError(String),
     InputFailedValidation(String),
+    /// One or more fields of an otherwise well-formed request are invalid.
+    InvalidFields(Vec<FieldError>),
+    InsufficientFunds(String),
+    /// The request can't be applied to the resource as it is now, e.g.
+    /// reversing a transaction twice.
+    Conflict(String),
+    /// The body isn't JSON at all.
+    MalformedJson(String),
+    /// The body is JSON but doesn't have the expected shape.
+    InvalidBody(Vec<FieldError>),
+    UnsupportedMediaType(String),
     PathNotFound(String),
 }
 
-#[derive(Debug, Serialize)]
-struct ErrorResponse {
-    reason: String,
+/// Stable, machine-readable error codes. Unlike the human readable reasons
+/// these never change, so clients can match on them.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InternalError,
+    ValidationFailed,
+    InsufficientFunds,
+    Conflict,
+    MalformedJson,
+    InvalidBody,
+    UnsupportedMediaType,
+    NotFound,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InternalError => "internal_error",
+            Self::ValidationFailed => "validation_failed",
+            Self::InsufficientFunds => "insufficient_funds",
+            Self::Conflict => "conflict",
+            Self::MalformedJson => "malformed_json",
+            Self::InvalidBody => "invalid_body",
+            Self::UnsupportedMediaType => "unsupported_media_type",
+            Self::NotFound => "not_found",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::InternalError => "Internal error",
+            Self::ValidationFailed => "Validation failed",
+            Self::InsufficientFunds => "Insufficient funds",
+            Self::Conflict => "Conflict",
+            Self::MalformedJson => "Malformed JSON",
+            Self::InvalidBody => "Invalid request body",
+            Self::UnsupportedMediaType => "Unsupported media type",
+            Self::NotFound => "Not found",
+        }
+    }
+
+    /// The RFC 7807 problem type, e.g. `urn:bank-of-dad:problem:not_found`.
+    pub fn problem_type(&self) -> String {
+        format!("urn:bank-of-dad:problem:{}", self.as_str())
+    }
+}
+
+/// Why a single field was rejected.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldErrorCode {
+    /// A required field is missing.
+    Missing,
+    /// The value has the wrong JSON type, or isn't one of the allowed values.
+    InvalidType,
+    /// Not a decimal with at most 2 decimal places, or out of range.
+    InvalidAmount,
+    /// Must be more than zero.
+    NotPositive,
+    Empty,
+    OutOfRange,
+    /// Not a valid url, email address, etc.
+    InvalidFormat,
+}
+
+/// e.g. `{"field":"purpose","code":"empty","message":"Must provide a purpose"}`.
+/// Nested fields are paths, e.g. `children[0]`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub code: FieldErrorCode,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: FieldErrorCode, message: &str) -> FieldError {
+        FieldError {
+            field: String::from(field),
+            code,
+            message: String::from(message),
+        }
+    }
+}
+
+/// Turns the errors a validator collected into its result.
+pub fn check_fields(errors: Vec<FieldError>) -> Result<(), ApiError> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidFields(errors))
+    }
+}
+
+/// The body of every error response, an RFC 7807 problem details object
+/// served as `application/problem+json`.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: ErrorCode,
+    /// Only present when individual fields were rejected.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    /// The same as `detail`, kept for clients written before problem details.
+    pub reason: String,
+}
+
+/// An `ApiError` carried outside of an HTTP response, e.g. in a websocket
+/// frame, with the status the HTTP API would have used.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct ErrorDetails {
+    pub status: u16,
+    pub code: ErrorCode,
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InputFailedValidation(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            Self::InsufficientFunds(_) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::MalformedJson(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidBody(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::PathNotFound(_) => StatusCode::NOT_FOUND,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InternalError(_) => ErrorCode::InternalError,
+            Self::InputFailedValidation(_) => ErrorCode::ValidationFailed,
+            Self::InvalidFields(_) => ErrorCode::ValidationFailed,
+            Self::InsufficientFunds(_) => ErrorCode::InsufficientFunds,
+            Self::Conflict(_) => ErrorCode::Conflict,
+            Self::MalformedJson(_) => ErrorCode::MalformedJson,
+            Self::InvalidBody(_) => ErrorCode::InvalidBody,
+            Self::UnsupportedMediaType(_) => ErrorCode::UnsupportedMediaType,
+            Self::PathNotFound(_) => ErrorCode::NotFound,
+        }
+    }
+
+    pub fn public_reason(&self) -> String {
+        match self {
+            Self::InternalError(public_reason) => public_reason.clone(),
+            Self::InputFailedValidation(public_reason) => public_reason.clone(),
+            Self::InvalidFields(errors) | Self::InvalidBody(errors) => errors
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<&str>>()
+                .join("; "),
+            Self::InsufficientFunds(public_reason) => public_reason.clone(),
+            Self::Conflict(public_reason) => public_reason.clone(),
+            Self::MalformedJson(public_reason) => public_reason.clone(),
+            Self::UnsupportedMediaType(public_reason) => public_reason.clone(),
+            Self::PathNotFound(path) => format!("Requested path '{}' not found", path),
+        }
+    }
+
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        match self {
+            Self::InvalidFields(errors) | Self::InvalidBody(errors) => errors.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn to_error_details(&self) -> ErrorDetails {
+        ErrorDetails {
+            status: self.status_code().as_u16(),
+            code: self.code(),
+            reason: self.public_reason(),
+            errors: self.field_errors(),
+        }
+    }
+
+    pub fn to_error_response(&self) -> ErrorResponse {
+        let code = self.code();
+        let public_reason = self.public_reason();
+
+        ErrorResponse {
+            problem_type: code.problem_type(),
+            title: String::from(code.title()),
+            status: self.status_code().as_u16(),
+            detail: public_reason.clone(),
+            code,
+            errors: self.field_errors(),
+            reason: public_reason,
+        }
+    }
 }
 
 impl From<rusqlite::Error> for ApiError {
@@ -28,39 +237,64 @@ impl From<rusqlite::Error> for ApiError {
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        match self {
-            Self::InternalError(public_reason) => {
-                warn!(
-                    "INTERNAL_SERVER_ERROR response with public_reason={}",
-                    public_reason
-                );
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        reason: public_reason,
-                    }),
-                )
-            }
-            Self::InputFailedValidation(public_reason) => {
-                warn!("BAD_REQUEST response with public_reason={}", public_reason);
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(ErrorResponse {
-                        reason: public_reason,
-                    }),
-                )
-            }
-            Self::PathNotFound(path) => {
-                let public_reason = format!("Requested path '{}' not found", path);
-                warn!("NOT_FOUND response with public_reason={}", public_reason);
-                (
-                    StatusCode::NOT_FOUND,
-                    Json(ErrorResponse {
-                        reason: public_reason,
-                    }),
-                )
-            }
-        }
-        .into_response()
+        let status_code = self.status_code();
+        let error_response = self.to_error_response();
+        warn!(
+            "{} response with code={} public_reason={}",
+            status_code
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_uppercase()
+                .replace(' ', "_"),
+            error_response.code.as_str(),
+            error_response.detail
+        );
+
+        let mut response = (status_code, Json(error_response)).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{ApiError, FieldError, FieldErrorCode};
+
+    #[test]
+    fn error_response_test() {
+        let error = ApiError::InvalidFields(vec![
+            FieldError::new(
+                "amount",
+                FieldErrorCode::NotPositive,
+                "Amount must be at least 0.01",
+            ),
+            FieldError::new("purpose", FieldErrorCode::Empty, "Must provide a purpose"),
+        ]);
+
+        assert_eq!(
+            serde_json::to_value(error.to_error_response()).unwrap(),
+            json!({
+                "type": "urn:bank-of-dad:problem:validation_failed",
+                "title": "Validation failed",
+                "status": 400,
+                "detail": "Amount must be at least 0.01; Must provide a purpose",
+                "code": "validation_failed",
+                "errors": [
+                    {"field": "amount", "code": "not_positive", "message": "Amount must be at least 0.01"},
+                    {"field": "purpose", "code": "empty", "message": "Must provide a purpose"},
+                ],
+                "reason": "Amount must be at least 0.01; Must provide a purpose",
+            })
+        );
+
+        let error = ApiError::PathNotFound(String::from("/v1/nope"));
+        let error_response = serde_json::to_value(error.to_error_response()).unwrap();
+        assert_eq!(error_response["code"], "not_found");
+        assert_eq!(error_response["status"], 404);
+        assert!(error_response.get("errors").is_none());
     }
 }
@@ -0,0 +1,77 @@
+use serde::Serialize;
+
+use super::{
+    alert::Alert, transaction::Transaction, websocket_command::ApprovalRequest,
+    websocket_msg::WebSocketMsg,
+};
+
+/// Every event type a notification channel can filter on.
+pub const EVENT_TYPES: &[&str] = &[
+    "transaction.give",
+    "transaction.spend",
+    "approval.requested",
+    "alert.triggered",
+];
+
+/// Something that happened to a child's account. Published once by
+/// `AppState::publish_event` to websockets and every notification channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BankEvent {
+    TransactionRecorded(Transaction),
+    ApprovalRequested(ApprovalRequest),
+    AlertTriggered(Alert),
+}
+
+/// The JSON body notification channels send, e.g.
+/// `{"event_type":"transaction.give","data":{...}}`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventPayload<'a> {
+    pub event_type: &'static str,
+    pub data: EventData<'a>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum EventData<'a> {
+    Transaction(&'a Transaction),
+    ApprovalRequest(&'a ApprovalRequest),
+    Alert(&'a Alert),
+}
+
+impl BankEvent {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Self::TransactionRecorded(t) if t.amount.is_negative() => "transaction.spend",
+            Self::TransactionRecorded(_) => "transaction.give",
+            Self::ApprovalRequested(_) => "approval.requested",
+            Self::AlertTriggered(_) => "alert.triggered",
+        }
+    }
+
+    pub fn child_name(&self) -> &str {
+        match self {
+            Self::TransactionRecorded(t) => &t.child_name,
+            Self::ApprovalRequested(r) => &r.child_name,
+            Self::AlertTriggered(a) => &a.child_name,
+        }
+    }
+
+    pub fn to_payload(&self) -> EventPayload<'_> {
+        EventPayload {
+            event_type: self.event_type(),
+            data: match self {
+                Self::TransactionRecorded(t) => EventData::Transaction(t),
+                Self::ApprovalRequested(r) => EventData::ApprovalRequest(r),
+                Self::AlertTriggered(a) => EventData::Alert(a),
+            },
+        }
+    }
+
+    pub fn to_websocket_msg(&self) -> WebSocketMsg {
+        match self {
+            Self::TransactionRecorded(t) => WebSocketMsg::Transaction(t.clone()),
+            Self::ApprovalRequested(r) => WebSocketMsg::ApprovalRequest(r.clone()),
+            Self::AlertTriggered(a) => WebSocketMsg::Alert(a.clone()),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// One thing the server needs before it should be sent traffic. It passed
+/// if it found no problem.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReadinessCheck {
+    pub name: String,
+    pub problem: Option<String>,
+}
+
+impl ReadinessCheck {
+    pub fn new(name: &str, problem: Option<String>) -> ReadinessCheck {
+        ReadinessCheck {
+            name: String::from(name),
+            problem,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+impl Readiness {
+    pub fn new(checks: Vec<ReadinessCheck>) -> Readiness {
+        Readiness {
+            ready: checks.iter().all(ReadinessCheck::passed),
+            checks,
+        }
+    }
+}
+
+/// What was built, as recorded by `build.rs`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BuildInfo {
+    pub version: String,
+    /// `unknown` if built outside a git checkout without
+    /// `BANK_OF_DAD_GIT_SHA` set.
+    pub git_sha: String,
+    pub build_time: DateTime<Utc>,
+}
+
+impl BuildInfo {
+    pub fn current() -> BuildInfo {
+        let build_timestamp: i64 = env!("BANK_OF_DAD_BUILD_TIMESTAMP").parse().unwrap();
+
+        BuildInfo {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            git_sha: String::from(env!("BANK_OF_DAD_GIT_SHA")),
+            build_time: DateTime::from_timestamp(build_timestamp, 0).unwrap(),
+        }
+    }
+}
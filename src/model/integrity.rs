@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// One consistency check over the database. It passed if it found no
+/// problems.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct IntegrityCheck {
+    pub name: String,
+    pub problems: Vec<String>,
+}
+
+impl IntegrityCheck {
+    pub fn new(name: &str, problems: Vec<String>) -> IntegrityCheck {
+        IntegrityCheck {
+            name: String::from(name),
+            problems,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct IntegrityReport {
+    pub checks: Vec<IntegrityCheck>,
+}
+
+impl IntegrityReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(IntegrityCheck::passed)
+    }
+}
@@ -1,20 +1,48 @@
+use async_graphql::SimpleObject;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use super::amount::Amount;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema, SimpleObject)]
 pub struct Transaction {
-    pub id: u8,
+    pub id: i64,
     pub timestamp: DateTime<Utc>,
     pub child_name: String,
     pub amount: Amount,
     pub purpose: String,
 }
 
+/// One child's balance, for listing every child at once.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+pub struct ChildBalance {
+    pub child_name: String,
+    pub balance: Amount,
+}
+
+/// The state of one child's ledger. Every transaction recorded for the
+/// child changes it, nothing else does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LedgerVersion {
+    pub latest_transaction_id: Option<i64>,
+    pub balance: Amount,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl LedgerVersion {
+    pub fn of(balance: Amount, transactions: &[Transaction]) -> LedgerVersion {
+        LedgerVersion {
+            latest_transaction_id: transactions.iter().map(|t| t.id).max(),
+            balance,
+            last_modified: transactions.iter().map(|t| t.timestamp).max(),
+        }
+    }
+}
+
 impl Transaction {
     pub fn new(
-        id: u8,
+        id: i64,
         timestamp: DateTime<Utc>,
         child_name: String,
         amount: Amount,
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{amount::Amount, error::ErrorDetails, transaction::Transaction};
+
+/// A command sent as a JSON text frame on a child's notifications socket,
+/// e.g. `{"id":"1","command":"spend","amount":1.50,"purpose":"sweets"}`.
+/// The client picks `id`, it is echoed back on the reply.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct ChildCommandRequest {
+    pub id: String,
+    #[serde(flatten)]
+    pub command: ChildCommand,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ChildCommand {
+    GetBalance,
+    /// The latest `limit` transactions, oldest first, at most 100.
+    ListTransactions {
+        limit: Option<usize>,
+    },
+    Give {
+        amount: Amount,
+        purpose: String,
+    },
+    Spend {
+        amount: Amount,
+        purpose: String,
+    },
+    RequestApproval {
+        amount: Amount,
+        purpose: String,
+    },
+}
+
+/// Reply to a `ChildCommandRequest`, serialized as
+/// `{"id":"1","result":{...}}` or `{"id":"1","error":{"status":400,"reason":"..."}}`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct ChildCommandReply {
+    pub id: String,
+    #[serde(flatten)]
+    pub outcome: ChildCommandOutcome,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChildCommandOutcome {
+    Result(ChildCommandResult),
+    Error(ErrorDetails),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(untagged)]
+pub enum ChildCommandResult {
+    Balance { balance: Amount },
+    Transactions { transactions: Vec<Transaction> },
+    Transaction { transaction: Transaction },
+    ApprovalRequested { approval_request: ApprovalRequest },
+}
+
+/// A spend a child has asked a parent to approve. Fanned out to the child's
+/// listeners as `{"approval_request":{...}}`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct ApprovalRequest {
+    pub child_name: String,
+    pub amount: Amount,
+    pub purpose: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct ApprovalRequestNotification {
+    pub approval_request: ApprovalRequest,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::amount::Amount;
+
+    use super::{ChildCommand, ChildCommandRequest};
+
+    #[test]
+    fn deserialize_command_test() {
+        assert_eq!(
+            serde_json::from_str::<ChildCommandRequest>(r#"{"id":"1","command":"get_balance"}"#)
+                .unwrap(),
+            ChildCommandRequest {
+                id: String::from("1"),
+                command: ChildCommand::GetBalance
+            }
+        );
+
+        assert_eq!(
+            serde_json::from_str::<ChildCommandRequest>(
+                r#"{"id":"2","command":"list_transactions","limit":5}"#
+            )
+            .unwrap(),
+            ChildCommandRequest {
+                id: String::from("2"),
+                command: ChildCommand::ListTransactions { limit: Some(5) }
+            }
+        );
+
+        assert_eq!(
+            serde_json::from_str::<ChildCommandRequest>(
+                r#"{"id":"3","command":"spend","amount":1.50,"purpose":"sweets"}"#
+            )
+            .unwrap(),
+            ChildCommandRequest {
+                id: String::from("3"),
+                command: ChildCommand::Spend {
+                    amount: Amount::from_pence(150),
+                    purpose: String::from("sweets")
+                }
+            }
+        );
+
+        assert_eq!(
+            serde_json::from_str::<ChildCommandRequest>(
+                r#"{"id":"5","command":"give","amount":2,"purpose":"pocket money"}"#
+            )
+            .unwrap(),
+            ChildCommandRequest {
+                id: String::from("5"),
+                command: ChildCommand::Give {
+                    amount: Amount::from_pence(200),
+                    purpose: String::from("pocket money")
+                }
+            }
+        );
+
+        assert!(serde_json::from_str::<ChildCommandRequest>(
+            r#"{"id":"4","command":"spend","amount":1.5,"purpose":"sweets"}"#
+        )
+        .is_err());
+
+        assert!(
+            serde_json::from_str::<ChildCommandRequest>(r#"{"command":"get_balance"}"#).is_err()
+        );
+    }
+}
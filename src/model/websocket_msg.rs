@@ -1,46 +1,226 @@
-use tokio::sync::mpsc::error::SendError;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use super::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use utoipa::ToSchema;
+
+use super::{
+    alert::Alert,
+    transaction::Transaction,
+    websocket_command::{ApprovalRequest, ChildCommandReply},
+};
 
 #[derive(Debug, Clone)]
 pub enum WebSocketMsg {
-    CloseSocket(),
+    CloseSocket(CloseReason),
     Transaction(Transaction),
+    HouseholdReply(HouseholdReply),
+    ChildCommandReply(ChildCommandReply),
+    ApprovalRequest(ApprovalRequest),
+    Alert(Alert),
+}
+
+/// Why a listener is being closed. Sockets send it in the close frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The peer closed the socket, or it failed.
+    Goodbye,
+    /// The server is shutting down. Clients should reconnect shortly.
+    ServerRestarting,
+}
+
+/// Commands a household notifications client sends as JSON text frames, e.g.
+/// `{"action":"subscribe","children":["a","b"]}`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum HouseholdCommand {
+    Subscribe { children: Vec<String> },
+    Unsubscribe { children: Vec<String> },
+}
+
+/// Replies to a `HouseholdCommand`, serialized as `{"subscriptions":[...]}`
+/// or `{"error":"..."}`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HouseholdReply {
+    Subscriptions(Vec<String>),
+    Error(String),
+}
+
+/// Which children's events a registered listener wants to receive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subscription {
+    Child(String),
+    Children(BTreeSet<String>),
+    AllChildren,
+}
+
+impl Subscription {
+    pub fn matches(&self, child_name: &str) -> bool {
+        match self {
+            Self::Child(subscribed_child_name) => subscribed_child_name == child_name,
+            Self::Children(child_names) => child_names.contains(child_name),
+            Self::AllChildren => true,
+        }
+    }
+
+    /// Applies a household command. Only `Children` subscriptions can change,
+    /// returns false for the fixed kinds.
+    pub fn apply(&mut self, command: &HouseholdCommand) -> bool {
+        let Self::Children(child_names) = self else {
+            return false;
+        };
+
+        match command {
+            HouseholdCommand::Subscribe { children } => {
+                child_names.extend(children.iter().cloned());
+            }
+            HouseholdCommand::Unsubscribe { children } => {
+                children.iter().for_each(|child_name| {
+                    child_names.remove(child_name);
+                });
+            }
+        }
+
+        true
+    }
+}
+
+impl Display for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Child(child_name) => write!(f, "{}", child_name),
+            Self::Children(_) => write!(f, "household"),
+            Self::AllChildren => write!(f, "*"),
+        }
+    }
+}
+
+/// Sent in place of messages a listener was too slow to receive. The client
+/// should re-read the account rather than trust its local state,
+/// serialized as `{"resync_required":true}`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct ResyncRequired {
+    pub resync_required: bool,
+}
+
+impl Default for ResyncRequired {
+    fn default() -> Self {
+        ResyncRequired {
+            resync_required: true,
+        }
+    }
+}
+
+/// Registered listeners, for diagnostics. A household socket counts once
+/// for each child it's subscribed to, so `by_child` can sum past `total`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct WebsocketCounts {
+    pub total: usize,
+    pub all_children: usize,
+    pub by_child: BTreeMap<String, usize>,
 }
 
+/// A listener registered for fan-out in `AppState`. Server-sent event streams
+/// register here too, they just never read from a socket.
 #[derive(Debug, Clone)]
 pub struct ActiveWebsocket {
     id: String,
-    child_name: String,
+    subscription: Subscription,
     send_channel: tokio::sync::mpsc::Sender<WebSocketMsg>,
+    resync_required: Arc<AtomicBool>,
 }
 
 impl ActiveWebsocket {
     pub fn new(
         id: String,
-        child_name: String,
+        subscription: Subscription,
         send_channel: tokio::sync::mpsc::Sender<WebSocketMsg>,
     ) -> ActiveWebsocket {
         ActiveWebsocket {
-            id: id,
-            child_name: child_name,
-            send_channel: send_channel,
+            id,
+            subscription,
+            send_channel,
+            resync_required: Arc::new(AtomicBool::new(false)),
         }
     }
 
     pub fn get_id(&self) -> String {
-        return self.id.clone();
+        self.id.clone()
+    }
+
+    pub fn get_subscription(&self) -> Subscription {
+        self.subscription.clone()
     }
 
-    pub fn get_child_name(&self) -> String {
-        return self.child_name.clone();
+    pub fn apply_household_command(&mut self, command: &HouseholdCommand) -> bool {
+        self.subscription.apply(command)
     }
 
     pub async fn send_message(&self, msg: WebSocketMsg) -> Result<(), SendError<WebSocketMsg>> {
         return self.send_channel.send(msg).await;
     }
 
-    pub fn get_log_prefix(&self) -> String {
-        return format!("[{}::{}]", self.id, self.child_name);
+    /// Queues a fan-out message without waiting. If the listener's channel is
+    /// full the message is dropped and the listener is flagged for a resync.
+    pub fn queue_message(&self, msg: WebSocketMsg) -> Result<(), TrySendError<WebSocketMsg>> {
+        let result = self.send_channel.try_send(msg);
+        if let Err(TrySendError::Full(_)) = result {
+            self.resync_required.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    /// Returns true once after messages were dropped for this listener.
+    pub fn take_resync_required(&self) -> bool {
+        self.resync_required.swap(false, Ordering::AcqRel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{HouseholdCommand, Subscription};
+
+    #[test]
+    fn household_subscription_test() {
+        let mut subscription = Subscription::Children(BTreeSet::new());
+        assert!(!subscription.matches("a"));
+
+        let command: HouseholdCommand =
+            serde_json::from_str(r#"{"action":"subscribe","children":["a","b"]}"#).unwrap();
+        assert!(subscription.apply(&command));
+        assert!(subscription.matches("a"));
+        assert!(subscription.matches("b"));
+        assert!(!subscription.matches("c"));
+
+        let command: HouseholdCommand =
+            serde_json::from_str(r#"{"action":"unsubscribe","children":["a","c"]}"#).unwrap();
+        assert!(subscription.apply(&command));
+        assert!(!subscription.matches("a"));
+        assert!(subscription.matches("b"));
+    }
+
+    #[test]
+    fn fixed_subscription_test() {
+        let command = HouseholdCommand::Subscribe {
+            children: vec![String::from("b")],
+        };
+
+        let mut subscription = Subscription::Child(String::from("a"));
+        assert!(!subscription.apply(&command));
+        assert!(!subscription.matches("b"));
+
+        let mut subscription = Subscription::AllChildren;
+        assert!(!subscription.apply(&command));
+        assert!(subscription.matches("b"));
     }
 }
@@ -0,0 +1,18 @@
+use crate::model::event::BankEvent;
+
+pub mod email;
+pub mod mqtt;
+pub mod webhooks;
+
+/// A notification channel fed by `AppState::publish_event`. Called on the
+/// request path after the transaction is committed, so implementations
+/// should only queue work and return.
+pub trait EventListener: Send + Sync {
+    fn on_event(&self, event: &BankEvent);
+
+    /// Names the channel in readiness checks.
+    fn name(&self) -> &'static str;
+
+    /// False once a background task the channel relies on has stopped.
+    fn is_running(&self) -> bool;
+}
@@ -0,0 +1,536 @@
+use std::sync::Arc;
+
+use askama::Template;
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::{
+    db::Db,
+    model::{
+        alert::Alert, amount::Amount, email::EmailPreferences, error::ApiError, event::BankEvent,
+        transaction::Transaction,
+    },
+};
+
+use super::EventListener;
+
+/// Where and when to send email. Spend alerts go out as soon as the spend is
+/// recorded; the digest goes out weekly on `digest_weekday` at `digest_hour`
+/// UTC.
+#[derive(Debug, Clone)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_credentials: Option<(String, String)>,
+    pub starttls: bool,
+    pub from: Mailbox,
+    pub digest_weekday: Weekday,
+    pub digest_hour: u32,
+}
+
+impl EmailConfig {
+    pub fn new(smtp_host: &str, smtp_port: u16) -> EmailConfig {
+        EmailConfig {
+            smtp_host: String::from(smtp_host),
+            smtp_port,
+            smtp_credentials: None,
+            starttls: false,
+            from: "Bank of Dad <bank@localhost>".parse().unwrap(),
+            digest_weekday: Weekday::Sun,
+            digest_hour: 18,
+        }
+    }
+}
+
+/// The first digest time strictly after `now`.
+pub fn next_digest_at(now: DateTime<Utc>, weekday: Weekday, hour: u32) -> DateTime<Utc> {
+    let days_ahead =
+        (7 + weekday.num_days_from_monday() - now.weekday().num_days_from_monday()) % 7;
+    let next = now.date_naive().and_hms_opt(hour, 0, 0).unwrap().and_utc()
+        + Duration::days(days_ahead.into());
+
+    if next <= now {
+        next + Duration::days(7)
+    } else {
+        next
+    }
+}
+
+/// The digest time the scheduler missed while it wasn't running, if the
+/// last one it sent was before the most recent digest time.
+pub fn missed_digest_at(
+    now: DateTime<Utc>,
+    last_digest_at: DateTime<Utc>,
+    weekday: Weekday,
+    hour: u32,
+) -> Option<DateTime<Utc>> {
+    let previous = next_digest_at(now, weekday, hour) - Duration::days(7);
+    (last_digest_at < previous).then_some(previous)
+}
+
+mod filters {
+    use std::borrow::Borrow;
+
+    use crate::model::amount::Amount;
+
+    pub fn money(amount: impl Borrow<Amount>) -> askama::Result<String> {
+        let amount = amount.borrow();
+        if amount.is_negative() {
+            Ok(format!("-£{}", amount.negate()))
+        } else {
+            Ok(format!("£{}", amount))
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email/spend_alert.txt")]
+struct SpendAlertText<'a> {
+    transaction: &'a Transaction,
+    threshold: Amount,
+    balance: Amount,
+}
+
+#[derive(Template)]
+#[template(path = "email/spend_alert.html")]
+struct SpendAlertHtml<'a> {
+    transaction: &'a Transaction,
+    threshold: Amount,
+    balance: Amount,
+}
+
+#[derive(Template)]
+#[template(path = "email/alert.txt")]
+struct AlertText<'a> {
+    alert: &'a Alert,
+}
+
+#[derive(Template)]
+#[template(path = "email/alert.html")]
+struct AlertHtml<'a> {
+    alert: &'a Alert,
+}
+
+#[derive(Debug, Clone)]
+pub struct DigestChild {
+    pub child_name: String,
+    pub balance: Amount,
+    pub given: Amount,
+    pub spent: Amount,
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(Template)]
+#[template(path = "email/weekly_digest.txt")]
+struct WeeklyDigestText<'a> {
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    children: &'a [DigestChild],
+}
+
+#[derive(Template)]
+#[template(path = "email/weekly_digest.html")]
+struct WeeklyDigestHtml<'a> {
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    children: &'a [DigestChild],
+}
+
+/// Emails parents according to their `EmailPreferences`: an alert for each
+/// spend over their threshold or triggered alert rule, and a weekly digest of
+/// every child's account.
+pub struct EmailNotifier {
+    mailer: Arc<Mailer>,
+    events: mpsc::UnboundedSender<BankEvent>,
+    workers: [JoinHandle<()>; 2],
+}
+
+struct Mailer {
+    db: Arc<Db>,
+    config: EmailConfig,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl EmailNotifier {
+    /// Starts the alert and digest tasks. Must be called from within a tokio
+    /// runtime.
+    pub fn start(
+        db: Arc<Db>,
+        config: EmailConfig,
+    ) -> Result<EmailNotifier, lettre::transport::smtp::Error> {
+        let mut transport = if config.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+        }
+        .port(config.smtp_port);
+        if let Some((username, password)) = &config.smtp_credentials {
+            transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let mailer = Arc::new(Mailer {
+            db,
+            config,
+            transport: transport.build(),
+        });
+        let (events, receiver) = mpsc::unbounded_channel();
+
+        let workers = [
+            tokio::spawn(run_alert_worker(mailer.clone(), receiver)),
+            tokio::spawn(run_digest_scheduler(mailer.clone())),
+        ];
+
+        Ok(EmailNotifier {
+            mailer,
+            events,
+            workers,
+        })
+    }
+
+    /// Sends the digest covering the week up to `until` to every parent who
+    /// wants one. Returns how many were sent.
+    pub async fn send_weekly_digest(&self, until: DateTime<Utc>) -> usize {
+        self.mailer.send_weekly_digest(until).await
+    }
+}
+
+impl EventListener for EmailNotifier {
+    fn on_event(&self, event: &BankEvent) {
+        if self.events.send(event.clone()).is_err() {
+            error!("email alert worker has stopped");
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn is_running(&self) -> bool {
+        self.workers.iter().all(|worker| !worker.is_finished())
+    }
+}
+
+async fn run_alert_worker(mailer: Arc<Mailer>, mut events: mpsc::UnboundedReceiver<BankEvent>) {
+    while let Some(event) = events.recv().await {
+        match event {
+            BankEvent::TransactionRecorded(transaction) if transaction.amount.is_negative() => {
+                mailer.send_spend_alerts(&transaction).await
+            }
+            BankEvent::AlertTriggered(alert) => mailer.send_alert(&alert).await,
+            _ => {}
+        }
+    }
+}
+
+/// Records each digest sent, so a restart around the digest time neither
+/// skips nor repeats it.
+async fn run_digest_scheduler(mailer: Arc<Mailer>) {
+    let weekday = mailer.config.digest_weekday;
+    let hour = mailer.config.digest_hour;
+
+    let now = Utc::now();
+    match mailer.db.get_last_digest_at() {
+        Ok(Some(last_digest_at)) => {
+            if let Some(missed) = missed_digest_at(now, last_digest_at, weekday, hour) {
+                info!("sending the weekly digest missed at {}", missed);
+                mailer.send_scheduled_digest(missed).await;
+            }
+        }
+        // Nothing was owed before the scheduler first ran
+        Ok(None) => mailer.record_digest(next_digest_at(now, weekday, hour) - Duration::days(7)),
+        Err(e) => error!("failed to read the last weekly digest time: {:?}", e),
+    }
+
+    loop {
+        let now = Utc::now();
+        let next = next_digest_at(now, weekday, hour);
+        info!("next weekly digest at {}", next);
+
+        tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+        mailer.send_scheduled_digest(next).await;
+    }
+}
+
+impl Mailer {
+    async fn send_spend_alerts(&self, transaction: &Transaction) {
+        let preferences = match self.db.get_email_preferences() {
+            Ok(preferences) => preferences,
+            Err(e) => {
+                error!("failed to read email preferences: {:?}", e);
+                return;
+            }
+        };
+        let spent = transaction.amount.negate();
+        let balance = match self
+            .db
+            .get_account_balance_for_child(transaction.child_name.clone())
+        {
+            Ok(balance) => balance,
+            Err(e) => {
+                error!("failed to read balance for spend alert: {:?}", e);
+                return;
+            }
+        };
+
+        for p in preferences
+            .iter()
+            .filter(|p| p.includes_child(&transaction.child_name))
+        {
+            let Some(threshold) = p.spend_threshold.filter(|t| spent > *t) else {
+                continue;
+            };
+
+            let text = SpendAlertText {
+                transaction,
+                threshold,
+                balance,
+            };
+            let html = SpendAlertHtml {
+                transaction,
+                threshold,
+                balance,
+            };
+
+            self.send(
+                p,
+                format!(
+                    "{} spent {}",
+                    transaction.child_name,
+                    filters::money(spent).unwrap()
+                ),
+                text.render(),
+                html.render(),
+            )
+            .await;
+        }
+    }
+
+    async fn send_alert(&self, alert: &Alert) {
+        let preferences = match self.db.get_email_preferences() {
+            Ok(preferences) => preferences,
+            Err(e) => {
+                error!("failed to read email preferences: {:?}", e);
+                return;
+            }
+        };
+
+        for p in preferences
+            .iter()
+            .filter(|p| p.alerts && p.includes_child(&alert.child_name))
+        {
+            self.send(
+                p,
+                format!("Alert for {}", alert.child_name),
+                AlertText { alert }.render(),
+                AlertHtml { alert }.render(),
+            )
+            .await;
+        }
+    }
+
+    async fn send_scheduled_digest(&self, until: DateTime<Utc>) {
+        self.send_weekly_digest(until).await;
+        self.record_digest(until);
+    }
+
+    fn record_digest(&self, until: DateTime<Utc>) {
+        if let Err(e) = self.db.set_last_digest_at(until) {
+            error!("failed to record the weekly digest time: {:?}", e);
+        }
+    }
+
+    async fn send_weekly_digest(&self, until: DateTime<Utc>) -> usize {
+        let since = until - Duration::days(7);
+
+        let digest = match self.build_digest(since) {
+            Ok(digest) => digest,
+            Err(e) => {
+                error!("failed to build weekly digest: {:?}", e);
+                return 0;
+            }
+        };
+
+        let mut sent = 0;
+        for p in self.db.get_email_preferences().unwrap_or_default() {
+            if !p.weekly_digest {
+                continue;
+            }
+
+            let children = digest
+                .iter()
+                .filter(|c| p.includes_child(&c.child_name))
+                .cloned()
+                .collect::<Vec<DigestChild>>();
+            if children.is_empty() {
+                continue;
+            }
+
+            let text = WeeklyDigestText {
+                since,
+                until,
+                children: &children,
+            };
+            let html = WeeklyDigestHtml {
+                since,
+                until,
+                children: &children,
+            };
+
+            if self
+                .send(
+                    &p,
+                    String::from("Your weekly Bank of Dad digest"),
+                    text.render(),
+                    html.render(),
+                )
+                .await
+            {
+                sent += 1;
+            }
+        }
+
+        info!("sent {} weekly digests", sent);
+        sent
+    }
+
+    fn build_digest(&self, since: DateTime<Utc>) -> Result<Vec<DigestChild>, ApiError> {
+        let transactions = self.db.get_transactions_since(since)?;
+
+        self.db
+            .get_child_names()?
+            .into_iter()
+            .map(|child_name| {
+                let transactions = transactions
+                    .iter()
+                    .filter(|t| t.child_name == child_name)
+                    .cloned()
+                    .collect::<Vec<Transaction>>();
+                let zero = Amount::from_pence(0);
+
+                Ok(DigestChild {
+                    balance: self.db.get_account_balance_for_child(child_name.clone())?,
+                    given: transactions
+                        .iter()
+                        .filter(|t| !t.amount.is_negative())
+                        .fold(zero, |total, t| total + t.amount),
+                    spent: transactions
+                        .iter()
+                        .filter(|t| t.amount.is_negative())
+                        .fold(zero, |total, t| total + t.amount.negate()),
+                    child_name,
+                    transactions,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns whether the email was accepted by the SMTP server.
+    async fn send(
+        &self,
+        preferences: &EmailPreferences,
+        subject: String,
+        text: askama::Result<String>,
+        html: askama::Result<String>,
+    ) -> bool {
+        let message = text
+            .and_then(|text| html.map(|html| (text, html)))
+            .map_err(|e| e.to_string())
+            .and_then(|(text, html)| {
+                let to = preferences
+                    .email
+                    .parse::<Mailbox>()
+                    .map_err(|e| e.to_string())?;
+
+                Message::builder()
+                    .from(self.config.from.clone())
+                    .to(to)
+                    .subject(subject)
+                    .multipart(MultiPart::alternative_plain_html(text, html))
+                    .map_err(|e| e.to_string())
+            });
+
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                error!("failed to build email to {}: {}", preferences.email, e);
+                return false;
+            }
+        };
+
+        match self.transport.send(message).await {
+            Ok(_) => {
+                info!("sent email to {}", preferences.email);
+                true
+            }
+            Err(e) => {
+                warn!("failed to send email to {}: {}", preferences.email, e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc, Weekday};
+
+    use super::{filters::money, missed_digest_at, next_digest_at};
+    use crate::model::amount::Amount;
+
+    #[test]
+    fn next_digest_at_test() {
+        // Wednesday
+        let now = Utc.with_ymd_and_hms(2023, 10, 18, 9, 30, 0).unwrap();
+        assert_eq!(
+            next_digest_at(now, Weekday::Sun, 18),
+            Utc.with_ymd_and_hms(2023, 10, 22, 18, 0, 0).unwrap()
+        );
+
+        // Sunday, before and after the digest hour
+        let now = Utc.with_ymd_and_hms(2023, 10, 22, 17, 59, 59).unwrap();
+        assert_eq!(
+            next_digest_at(now, Weekday::Sun, 18),
+            Utc.with_ymd_and_hms(2023, 10, 22, 18, 0, 0).unwrap()
+        );
+        let now = Utc.with_ymd_and_hms(2023, 10, 22, 18, 0, 0).unwrap();
+        assert_eq!(
+            next_digest_at(now, Weekday::Sun, 18),
+            Utc.with_ymd_and_hms(2023, 10, 29, 18, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn missed_digest_at_test() {
+        let sent = Utc.with_ymd_and_hms(2023, 10, 22, 18, 0, 0).unwrap();
+
+        // Restarted just before and just after the digest that was sent
+        let now = Utc.with_ymd_and_hms(2023, 10, 22, 18, 0, 5).unwrap();
+        assert_eq!(missed_digest_at(now, sent, Weekday::Sun, 18), None);
+        let now = Utc.with_ymd_and_hms(2023, 10, 29, 17, 59, 0).unwrap();
+        assert_eq!(missed_digest_at(now, sent, Weekday::Sun, 18), None);
+
+        // Down over the next digest, then over several
+        let now = Utc.with_ymd_and_hms(2023, 10, 29, 18, 0, 5).unwrap();
+        assert_eq!(
+            missed_digest_at(now, sent, Weekday::Sun, 18),
+            Some(Utc.with_ymd_and_hms(2023, 10, 29, 18, 0, 0).unwrap())
+        );
+        let now = Utc.with_ymd_and_hms(2023, 11, 15, 9, 0, 0).unwrap();
+        assert_eq!(
+            missed_digest_at(now, sent, Weekday::Sun, 18),
+            Some(Utc.with_ymd_and_hms(2023, 11, 12, 18, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn money_test() {
+        assert_eq!(money(Amount::from_pence(1050)).unwrap(), "£10.50");
+        assert_eq!(money(Amount::from_pence(-5)).unwrap(), "-£0.05");
+    }
+}
@@ -0,0 +1,322 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::{db::Db, model::event::BankEvent};
+
+use super::EventListener;
+
+/// Where to publish. Balances go to a retained `<topic_prefix>/<child>/balance`
+/// and every event to `<topic_prefix>/<child>/events`. With a
+/// `discovery_prefix` each child also gets a Home Assistant sensor.
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub credentials: Option<(String, String)>,
+    pub topic_prefix: String,
+    pub discovery_prefix: Option<String>,
+    pub keep_alive: Duration,
+    pub reconnect_delay: Duration,
+}
+
+impl MqttConfig {
+    pub fn new(host: &str, port: u16) -> MqttConfig {
+        MqttConfig {
+            host: String::from(host),
+            port,
+            client_id: String::from("bank_of_dad"),
+            credentials: None,
+            topic_prefix: String::from("bank_of_dad"),
+            discovery_prefix: Some(String::from("homeassistant")),
+            keep_alive: Duration::from_secs(30),
+            reconnect_delay: Duration::from_secs(5),
+        }
+    }
+
+    pub fn balance_topic(&self, child_name: &str) -> String {
+        format!(
+            "{}/{}/balance",
+            self.topic_prefix,
+            topic_segment(child_name)
+        )
+    }
+
+    pub fn events_topic(&self, child_name: &str) -> String {
+        format!("{}/{}/events", self.topic_prefix, topic_segment(child_name))
+    }
+
+    pub fn discovery_topic(&self, child_name: &str) -> Option<String> {
+        self.discovery_prefix.as_ref().map(|discovery_prefix| {
+            format!(
+                "{}/sensor/{}/balance/config",
+                discovery_prefix,
+                self.device_id(child_name)
+            )
+        })
+    }
+
+    fn device_id(&self, child_name: &str) -> String {
+        format!(
+            "{}_{}",
+            object_id(&self.topic_prefix),
+            child_object_id(child_name)
+        )
+    }
+}
+
+/// Child names come straight from the URL, so percent-encode MQTT wildcards
+/// and level separators, and `%` itself so distinct names stay distinct.
+fn topic_segment(child_name: &str) -> String {
+    let mut segment = String::with_capacity(child_name.len());
+    for c in child_name.chars() {
+        match c {
+            '%' | '/' | '+' | '#' => segment.push_str(&format!("%{:02X}", c as u8)),
+            c => segment.push(c),
+        }
+    }
+    segment
+}
+
+/// Home Assistant object ids only allow `[a-zA-Z0-9_-]`.
+fn object_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// As `object_id`, but names that had to change get a short hash of the
+/// original, so "a/b" and "a_b" don't share an id.
+fn child_object_id(child_name: &str) -> String {
+    let id = object_id(child_name);
+    if child_name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        id
+    } else {
+        let hash = Sha256::digest(child_name.as_bytes());
+        format!("{}_{}", id, hex::encode(&hash[..4]))
+    }
+}
+
+enum MqttJob {
+    Connected,
+    Event(BankEvent),
+}
+
+/// Publishes balances and events to an MQTT broker, reconnecting as needed.
+/// Retained balances and discovery configs are republished on every
+/// connect so the broker's view survives restarts on either side.
+pub struct MqttPublisher {
+    jobs: mpsc::UnboundedSender<MqttJob>,
+    workers: [JoinHandle<()>; 2],
+}
+
+impl MqttPublisher {
+    /// Starts the connection and publishing tasks. Must be called from
+    /// within a tokio runtime.
+    pub fn start(db: Arc<Db>, config: MqttConfig) -> MqttPublisher {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(config.keep_alive);
+        if let Some((username, password)) = &config.credentials {
+            options.set_credentials(username, password);
+        }
+
+        let (client, event_loop) = AsyncClient::new(options, 64);
+        let (jobs, receiver) = mpsc::unbounded_channel();
+
+        let workers = [
+            tokio::spawn(run_event_loop(
+                event_loop,
+                jobs.clone(),
+                config.reconnect_delay,
+            )),
+            tokio::spawn(run_publisher(db, config, client, receiver)),
+        ];
+
+        MqttPublisher { jobs, workers }
+    }
+}
+
+impl EventListener for MqttPublisher {
+    fn on_event(&self, event: &BankEvent) {
+        if self.jobs.send(MqttJob::Event(event.clone())).is_err() {
+            error!("mqtt publisher has stopped");
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn is_running(&self) -> bool {
+        self.workers.iter().all(|worker| !worker.is_finished())
+    }
+}
+
+async fn run_event_loop(
+    mut event_loop: EventLoop,
+    jobs: mpsc::UnboundedSender<MqttJob>,
+    reconnect_delay: Duration,
+) {
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("connected to mqtt broker");
+                if jobs.send(MqttJob::Connected).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("mqtt connection error: {}", e);
+                tokio::time::sleep(reconnect_delay).await;
+            }
+        }
+    }
+}
+
+async fn run_publisher(
+    db: Arc<Db>,
+    config: MqttConfig,
+    client: AsyncClient,
+    mut jobs: mpsc::UnboundedReceiver<MqttJob>,
+) {
+    let publisher = Publisher { db, config, client };
+    let mut announced: HashSet<String> = HashSet::new();
+
+    while let Some(job) = jobs.recv().await {
+        match job {
+            MqttJob::Connected => {
+                announced.clear();
+                for child_name in publisher.db.get_child_names().unwrap_or_default() {
+                    publisher.announce(&child_name).await;
+                    publisher.publish_balance(&child_name).await;
+                    announced.insert(child_name);
+                }
+            }
+            MqttJob::Event(event) => {
+                let child_name = event.child_name().to_string();
+                if !announced.contains(&child_name) {
+                    publisher.announce(&child_name).await;
+                    announced.insert(child_name.clone());
+                }
+                if let BankEvent::TransactionRecorded(_) = event {
+                    publisher.publish_balance(&child_name).await;
+                }
+                publisher
+                    .publish(
+                        publisher.config.events_topic(&child_name),
+                        false,
+                        serde_json::to_vec(&event.to_payload()).unwrap(),
+                    )
+                    .await;
+            }
+        }
+    }
+}
+
+struct Publisher {
+    db: Arc<Db>,
+    config: MqttConfig,
+    client: AsyncClient,
+}
+
+impl Publisher {
+    async fn publish_balance(&self, child_name: &str) {
+        match self
+            .db
+            .get_account_balance_for_child(child_name.to_string())
+        {
+            Ok(balance) => {
+                self.publish(
+                    self.config.balance_topic(child_name),
+                    true,
+                    balance.to_string().into_bytes(),
+                )
+                .await
+            }
+            Err(e) => error!("failed to read balance for {}: {:?}", child_name, e),
+        }
+    }
+
+    async fn announce(&self, child_name: &str) {
+        let Some(topic) = self.config.discovery_topic(child_name) else {
+            return;
+        };
+        let device_id = self.config.device_id(child_name);
+        let discovery = json!({
+            "name": "Balance",
+            "unique_id": format!("{}_balance", device_id),
+            "state_topic": self.config.balance_topic(child_name),
+            "unit_of_measurement": "GBP",
+            "device_class": "monetary",
+            "icon": "mdi:piggy-bank",
+            "device": {
+                "identifiers": [device_id],
+                "name": format!("Bank of Dad {}", child_name),
+                "manufacturer": "Bank of Dad",
+            },
+        });
+
+        self.publish(topic, true, serde_json::to_vec(&discovery).unwrap())
+            .await;
+    }
+
+    async fn publish(&self, topic: String, retain: bool, payload: Vec<u8>) {
+        if let Err(e) = self
+            .client
+            .publish(&topic, QoS::AtLeastOnce, retain, payload)
+            .await
+        {
+            error!("failed to publish to {}: {}", topic, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MqttConfig;
+
+    #[test]
+    fn topics_test() {
+        let config = MqttConfig::new("localhost", 1883);
+
+        assert_eq!(config.balance_topic("a"), "bank_of_dad/a/balance");
+        assert_eq!(
+            config.events_topic("a/b+#%"),
+            "bank_of_dad/a%2Fb%2B%23%25/events"
+        );
+        assert_ne!(config.balance_topic("a/b"), config.balance_topic("a_b"));
+        assert_eq!(
+            config.discovery_topic("Mary-Ann").unwrap(),
+            "homeassistant/sensor/bank_of_dad_Mary-Ann/balance/config"
+        );
+        assert_eq!(
+            config.discovery_topic("Mary Ann").unwrap(),
+            "homeassistant/sensor/bank_of_dad_Mary_Ann_1374ceec/balance/config"
+        );
+        assert_ne!(
+            config.discovery_topic("a/b").unwrap(),
+            config.discovery_topic("a_b").unwrap()
+        );
+
+        let config = MqttConfig {
+            discovery_prefix: None,
+            ..config
+        };
+        assert_eq!(config.discovery_topic("a"), None);
+    }
+}
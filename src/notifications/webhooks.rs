@@ -0,0 +1,279 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use hyper::{client::HttpConnector, Body, Client, Request};
+use hyper_rustls::HttpsConnector;
+use sha2::Sha256;
+use tokio::{
+    sync::Notify,
+    task::{self, JoinHandle, JoinSet},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    db::Db,
+    model::{event::BankEvent, webhook::PendingWebhookDelivery},
+};
+
+use super::EventListener;
+
+pub const SIGNATURE_HEADER: &str = "X-Bank-Of-Dad-Signature";
+pub const EVENT_HEADER: &str = "X-Bank-Of-Dad-Event";
+pub const DELIVERY_HEADER: &str = "X-Bank-Of-Dad-Delivery";
+
+/// A failed delivery is retried after `initial_backoff`, doubling each time
+/// up to `max_backoff`, until `max_attempts` have been made. Up to
+/// `max_in_flight` webhooks are sent to at once, one delivery each.
+#[derive(Debug, Clone, Copy)]
+pub struct WebhookConfig {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub request_timeout: Duration,
+    pub poll_interval: Duration,
+    pub max_in_flight: usize,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            max_attempts: 8,
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(60 * 60),
+            request_timeout: Duration::from_secs(10),
+            poll_interval: Duration::from_secs(5),
+            max_in_flight: 8,
+        }
+    }
+}
+
+impl WebhookConfig {
+    fn backoff_after(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// `sha256=<hex>` HMAC of the exact request body, keyed by the webhook's
+/// secret, as sent in `X-Bank-Of-Dad-Signature`.
+pub fn sign_payload(secret: &str, payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Queues deliveries in the database for each event and wakes a background
+/// task to send them, so a slow receiver never holds up a transaction.
+pub struct WebhookDispatcher {
+    db: Arc<Db>,
+    wake: Arc<Notify>,
+    worker: JoinHandle<()>,
+}
+
+impl WebhookDispatcher {
+    /// Starts the delivery task. Must be called from within a tokio runtime.
+    pub fn start(db: Arc<Db>, config: WebhookConfig) -> WebhookDispatcher {
+        let wake = Arc::new(Notify::new());
+
+        let worker_db = db.clone();
+        let worker_wake = wake.clone();
+        let worker =
+            tokio::spawn(async move { run_delivery_worker(worker_db, config, worker_wake).await });
+
+        WebhookDispatcher { db, wake, worker }
+    }
+}
+
+impl EventListener for WebhookDispatcher {
+    fn on_event(&self, event: &BankEvent) {
+        let payload = serde_json::to_string(&event.to_payload()).unwrap();
+
+        match self
+            .db
+            .enqueue_webhook_deliveries(event.event_type(), &payload)
+        {
+            Ok(0) => {}
+            Ok(queued) => {
+                info!(
+                    "queued {} webhook deliveries for {}",
+                    queued,
+                    event.event_type()
+                );
+                self.wake.notify_one();
+            }
+            Err(e) => error!("failed to queue webhook deliveries: {:?}", e),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "webhooks"
+    }
+
+    fn is_running(&self) -> bool {
+        !self.worker.is_finished()
+    }
+}
+
+/// Each webhook's deliveries go one at a time, so a slow or unreachable
+/// receiver only holds up its own queue.
+async fn run_delivery_worker(db: Arc<Db>, config: WebhookConfig, wake: Arc<Notify>) {
+    let client: Client<HttpsConnector<HttpConnector>> = Client::builder().build(
+        hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build(),
+    );
+
+    let mut in_flight = JoinSet::new();
+    let mut busy_webhooks: HashMap<task::Id, i64> = HashMap::new();
+
+    loop {
+        let busy_webhook_ids: HashSet<i64> = busy_webhooks.values().copied().collect();
+        let free = config.max_in_flight.saturating_sub(busy_webhook_ids.len());
+        let deliveries = if free > 0 {
+            match db.get_due_webhook_deliveries(Utc::now(), &busy_webhook_ids, free) {
+                Ok(deliveries) => deliveries,
+                Err(e) => {
+                    error!("failed to read webhook deliveries: {:?}", e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        for delivery in deliveries {
+            let webhook_id = delivery.webhook_id;
+            let db = db.clone();
+            let client = client.clone();
+            let handle = in_flight
+                .spawn(async move { attempt_delivery(&db, &config, &client, &delivery).await });
+            busy_webhooks.insert(handle.id(), webhook_id);
+        }
+
+        let busy_webhook_ids: HashSet<i64> = busy_webhooks.values().copied().collect();
+        let sleep_for = match db.get_next_webhook_delivery_due_at(&busy_webhook_ids) {
+            Ok(Some(due_at)) => (due_at - Utc::now())
+                .to_std()
+                .unwrap_or(Duration::ZERO)
+                .min(config.poll_interval),
+            _ => config.poll_interval,
+        };
+        // Every slot is taken, so nothing can start until one frees up
+        let sleep_for = if busy_webhook_ids.len() >= config.max_in_flight {
+            config.poll_interval
+        } else {
+            sleep_for
+        };
+
+        tokio::select! {
+            Some(finished) = in_flight.join_next_with_id() => {
+                let id = match finished {
+                    Ok((id, ())) => id,
+                    Err(e) => {
+                        error!("webhook delivery task failed: {:?}", e);
+                        e.id()
+                    }
+                };
+                busy_webhooks.remove(&id);
+            }
+            _ = wake.notified() => {}
+            _ = tokio::time::sleep(sleep_for) => {}
+        }
+    }
+}
+
+async fn attempt_delivery(
+    db: &Db,
+    config: &WebhookConfig,
+    client: &Client<HttpsConnector<HttpConnector>>,
+    delivery: &PendingWebhookDelivery,
+) {
+    let request = Request::post(&delivery.url)
+        .header(hyper::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(
+            SIGNATURE_HEADER,
+            sign_payload(&delivery.secret, delivery.payload.as_bytes()),
+        )
+        .body(Body::from(delivery.payload.clone()));
+
+    let result = match request {
+        Ok(request) => {
+            match tokio::time::timeout(config.request_timeout, client.request(request)).await {
+                Ok(Ok(response)) if response.status().is_success() => Ok(()),
+                Ok(Ok(response)) => Err(format!("receiver responded {}", response.status())),
+                Ok(Err(e)) => Err(format!("request failed: {}", e)),
+                Err(_) => Err(String::from("request timed out")),
+            }
+        }
+        Err(e) => Err(format!("invalid request: {}", e)),
+    };
+
+    let recorded = match result {
+        Ok(()) => {
+            info!("webhook delivery {} sent to {}", delivery.id, delivery.url);
+            db.complete_webhook_delivery(delivery.id)
+        }
+        Err(reason) => {
+            let attempts = delivery.attempts + 1;
+            let next_attempt_at = if attempts < config.max_attempts {
+                Some(
+                    Utc::now()
+                        + chrono::Duration::from_std(config.backoff_after(attempts)).unwrap(),
+                )
+            } else {
+                None
+            };
+            warn!(
+                "webhook delivery {} to {} failed on attempt {}: {}",
+                delivery.id, delivery.url, attempts, reason
+            );
+            db.fail_webhook_delivery(delivery.id, &reason, next_attempt_at)
+        }
+    };
+
+    if let Err(e) = recorded {
+        error!("failed to record webhook delivery {}: {:?}", delivery.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{sign_payload, WebhookConfig};
+
+    #[test]
+    fn backoff_test() {
+        let config = WebhookConfig {
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(60),
+            ..WebhookConfig::default()
+        };
+
+        assert_eq!(config.backoff_after(1), Duration::from_secs(5));
+        assert_eq!(config.backoff_after(2), Duration::from_secs(10));
+        assert_eq!(config.backoff_after(4), Duration::from_secs(40));
+        assert_eq!(config.backoff_after(5), Duration::from_secs(60));
+        assert_eq!(config.backoff_after(40), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn sign_payload_test() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_payload("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use utoipa::OpenApi;
+
+use crate::{
+    handlers,
+    model::{
+        alert, amount, email, error, integrity, transaction, webhook, websocket_command,
+        websocket_msg,
+    },
+};
+
+/// The OpenAPI 3 description of the v1 API, served at `/v1/openapi.json`.
+/// Every route needs its handler listed in `paths` here.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Bank of Dad",
+        description = "Pocket money accounts for children. Amounts are in pounds, always with \
+                       2 decimal places."
+    ),
+    servers((url = "/v1")),
+    paths(
+        handlers::child::get_child,
+        handlers::record_transaction::give,
+        handlers::record_transaction::spend,
+        handlers::record_transaction::reverse,
+        handlers::children::list_children,
+        handlers::alert_rules::list_alert_rules,
+        handlers::alert_rules::create_alert_rule,
+        handlers::alert_rules::delete_alert_rule,
+        handlers::websocket::accept_websocket,
+        handlers::events::child_events,
+        handlers::events::all_events,
+        handlers::websocket::accept_household_websocket,
+        handlers::webhooks::list_webhooks,
+        handlers::webhooks::create_webhook,
+        handlers::webhooks::delete_webhook,
+        handlers::webhooks::list_dead_letters,
+        handlers::webhooks::retry_dead_letter,
+        handlers::email_preferences::list_email_preferences,
+        handlers::email_preferences::upsert_email_preferences,
+        handlers::email_preferences::delete_email_preferences,
+        handlers::diagnostics::get_websocket_counts,
+        handlers::diagnostics::get_integrity,
+        handlers::graphql::execute_graphql,
+        handlers::graphql::accept_graphql_websocket,
+        handlers::openapi::get_openapi,
+    ),
+    components(schemas(
+        amount::Amount,
+        error::ErrorResponse,
+        error::ErrorDetails,
+        error::ErrorCode,
+        error::FieldError,
+        error::FieldErrorCode,
+        handlers::record_transaction::GiveMoney,
+        transaction::Transaction,
+        transaction::ChildBalance,
+        integrity::IntegrityCheck,
+        integrity::IntegrityReport,
+        handlers::child::ChildAccountResponse,
+        alert::AlertCondition,
+        alert::NewAlertRule,
+        alert::AlertRule,
+        alert::Alert,
+        alert::AlertNotification,
+        webhook::NewWebhook,
+        webhook::Webhook,
+        webhook::CreatedWebhook,
+        webhook::WebhookDelivery,
+        email::NewEmailPreferences,
+        email::EmailPreferences,
+        websocket_command::ChildCommandRequest,
+        websocket_command::ChildCommand,
+        websocket_command::ChildCommandReply,
+        websocket_command::ChildCommandOutcome,
+        websocket_command::ChildCommandResult,
+        websocket_command::ApprovalRequest,
+        websocket_command::ApprovalRequestNotification,
+        websocket_msg::HouseholdCommand,
+        websocket_msg::HouseholdReply,
+        websocket_msg::ResyncRequired,
+        websocket_msg::WebsocketCounts,
+    ))
+)]
+pub struct ApiDoc;
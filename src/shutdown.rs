@@ -0,0 +1,78 @@
+use std::{future::Future, time::Duration};
+
+use tokio::time::{timeout_at, Instant};
+use tracing::{error, info, warn};
+
+use crate::{appstate::AppState, model::websocket_msg::CloseReason};
+
+/// Resolves on SIGTERM or Ctrl-C.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("can't listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("can't listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Ctrl-C received, shutting down"),
+        _ = terminate => info!("SIGTERM received, shutting down"),
+    }
+}
+
+/// Finishes up once `server` has gone unready and been told to shut down
+/// gracefully: asks every websocket and event stream to close, lets
+/// in-flight requests finish, then checkpoints the database. Anything still
+/// running at `timeout` is abandoned.
+pub async fn drain<S>(app_state: &AppState, server: S, timeout: Duration)
+where
+    S: Future<Output = hyper::Result<()>>,
+{
+    let deadline = Instant::now() + timeout;
+
+    // Streaming responses never finish on their own, so close them before
+    // waiting on the server.
+    if timeout_at(
+        deadline,
+        app_state.close_open_websockets(CloseReason::ServerRestarting),
+    )
+    .await
+    .is_err()
+    {
+        warn!("websockets still being closed after {:?}", timeout);
+    }
+
+    match timeout_at(deadline, server).await {
+        Ok(Ok(())) => info!("in-flight requests finished"),
+        Ok(Err(e)) => error!("server failed while shutting down: {}", e),
+        Err(_) => warn!(
+            "requests still running after {:?}, shutting down anyway",
+            timeout
+        ),
+    }
+
+    let open = app_state.wait_for_websockets_closed(deadline).await;
+    if open > 0 {
+        warn!("{} websockets still open, shutting down anyway", open);
+    }
+
+    match app_state.get_db().checkpoint() {
+        Ok(()) => info!("database checkpointed"),
+        Err(e) => error!("can't checkpoint database: {}", e),
+    }
+}
@@ -0,0 +1,138 @@
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::config::{Config, LogFormat};
+
+/// Flushes exported spans when shut down. Does nothing without the `otel`
+/// feature or a `[telemetry]` section.
+#[must_use = "spans still buffered are lost unless this is shut down"]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    exporting: bool,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if self.exporting {
+            opentelemetry::global::shutdown_tracer_provider();
+        }
+    }
+}
+
+/// Installs the global subscriber, logging as configured and, with the
+/// `otel` feature, exporting spans to the `[telemetry]` collector. Call
+/// once, after validation, from within the runtime.
+pub fn init(config: &Config) -> Telemetry {
+    let log_layer = tracing_subscriber::fmt::layer().with_thread_ids(true);
+    let log_layer = match config.log.format {
+        LogFormat::Text => log_layer.boxed(),
+        LogFormat::Json => log_layer.json().boxed(),
+    }
+    .with_filter(EnvFilter::new(&config.log.level));
+    let subscriber = tracing_subscriber::registry().with(log_layer);
+
+    #[cfg(feature = "otel")]
+    {
+        let (export_layer, export_error) = match &config.telemetry {
+            Some(telemetry) => match otlp::layer(telemetry) {
+                Ok(layer) => (Some(layer), None),
+                Err(e) => (None, Some(e)),
+            },
+            None => (None, None),
+        };
+        let exporting = export_layer.is_some();
+        subscriber.with(export_layer).init();
+
+        if let Some(e) = export_error {
+            tracing::error!("can't export spans: {}", e);
+        }
+        Telemetry { exporting }
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        subscriber.init();
+        Telemetry {}
+    }
+}
+
+#[cfg(feature = "otel")]
+pub use otlp::link;
+
+#[cfg(feature = "otel")]
+mod otlp {
+    use opentelemetry::{
+        trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+        Context, KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+    use tracing::{Level, Span, Subscriber};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{filter, registry::LookupSpan, Layer};
+
+    use crate::{config::TelemetrySection, middleware::request_tracing::TraceParent};
+
+    pub fn layer<S>(
+        telemetry: &TelemetrySection,
+    ) -> Result<Box<dyn Layer<S> + Send + Sync>, opentelemetry::trace::TraceError>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+    {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&telemetry.otlp_endpoint),
+            )
+            .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+                "service.name",
+                telemetry.service_name().to_owned(),
+            )])))
+            .install_batch(runtime::Tokio)?;
+
+        // Only our own spans, and database queries only as part of something
+        // else, rather than a trace for every poll by the webhook sender
+        let own_spans = filter::dynamic_filter_fn(|metadata, cx| {
+            let target = metadata.target();
+            *metadata.level() <= Level::INFO
+                && target.starts_with("bank_of_dad")
+                && (!target.starts_with("bank_of_dad::db") || cx.lookup_current().is_some())
+        });
+
+        Ok(tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(own_spans)
+            .boxed())
+    }
+
+    /// Makes `span` a child of the caller's span, if there is one, and
+    /// returns its trace and span ids as hex. None when spans aren't being
+    /// exported.
+    pub fn link(span: &Span, trace_parent: Option<&TraceParent>) -> Option<(String, String)> {
+        if let Some(trace_parent) = trace_parent {
+            let remote = SpanContext::new(
+                TraceId::from_hex(&trace_parent.trace_id).ok()?,
+                SpanId::from_hex(&trace_parent.parent_id).ok()?,
+                if trace_parent.sampled {
+                    TraceFlags::SAMPLED
+                } else {
+                    TraceFlags::default()
+                },
+                true,
+                TraceState::default(),
+            );
+            span.set_parent(Context::new().with_remote_span_context(remote));
+        }
+
+        let context = span.context();
+        let span_context = context.span().span_context().clone();
+        span_context.is_valid().then(|| {
+            (
+                span_context.trace_id().to_string(),
+                span_context.span_id().to_string(),
+            )
+        })
+    }
+}
@@ -0,0 +1,346 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::model::alert::{AlertCondition, AlertNotification, AlertRule};
+use bank_of_dad::model::amount::Amount;
+use bank_of_dad::model::transaction::Transaction;
+use bank_of_dad::{db::Db, router};
+use futures::StreamExt;
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn get_next_text_frame_from_socket(socket: &mut Socket) -> String {
+    match timeout(Duration::from_secs(1), socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+    {
+        tungstenite::Message::Text(msg) => msg,
+        other => panic!("unexpected websocket message {other:?}"),
+    }
+}
+
+async fn request(
+    client: &Client<HttpConnector>,
+    method: http::Method,
+    uri: String,
+    request_body: Option<&str>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(String::from(request_body.unwrap_or_default())))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+
+    let status_code = response.status();
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    (status_code, body)
+}
+
+/// Records the transaction and returns the alerts that followed it on the
+/// child's websocket, as (rule id, message) pairs.
+async fn record(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    socket: &mut Socket,
+    uri: &str,
+    request_body: &str,
+    expected_alerts: usize,
+) -> Vec<(i64, String)> {
+    let (status_code, _body) = request(
+        client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/ava/{uri}"),
+        Some(request_body),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    serde_json::from_str::<Transaction>(&get_next_text_frame_from_socket(socket).await).unwrap();
+
+    let mut alerts = Vec::new();
+    for _ in 0..expected_alerts {
+        let alert = serde_json::from_str::<AlertNotification>(
+            &get_next_text_frame_from_socket(socket).await,
+        )
+        .unwrap()
+        .alert;
+        assert_eq!(alert.child_name, "ava");
+        alerts.push((alert.rule_id, alert.message));
+    }
+
+    alerts
+}
+
+#[tokio::test]
+async fn alert_rules_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("alert_rules_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // Rules are validated
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/ava/alert_rules"),
+        Some(r#"{"kind":"spend_over_balance_percent","percent":0}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/ava/alert_rules"),
+        Some(r#"{"kind":"balance_below","amount":-1}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/ava/alert_rules"),
+        Some(r#"{"kind":"balance_below","amount":1,"cooldown_seconds":9223372036854775808}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/ava/alert_rules"),
+        Some(r#"{"kind":"balance_above","amount":1}"#),
+    )
+    .await;
+    assert!(status_code.is_client_error());
+
+    //
+    // One rule of each kind; the spends rule has no cooldown
+    //
+    let mut rule_ids = Vec::new();
+    for body in [
+        r#"{"kind":"balance_below","amount":2}"#,
+        r#"{"kind":"spends_per_day_over","count":2,"cooldown_seconds":0}"#,
+        r#"{"kind":"spend_over_balance_percent","percent":50}"#,
+    ] {
+        let (status_code, body) = request(
+            &client,
+            http::Method::POST,
+            format!("http://{addr}/v1/child/ava/alert_rules"),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::OK);
+        rule_ids.push(serde_json::from_value::<AlertRule>(body).unwrap().id);
+    }
+    let (balance_rule, spends_rule, percent_rule) = (rule_ids[0], rule_ids[1], rule_ids[2]);
+
+    let (mut socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/child/ava/notifications"))
+            .await
+            .unwrap();
+
+    //
+    // Alerts follow the transaction that triggered them
+    //
+    assert!(record(
+        &client,
+        addr,
+        &mut socket,
+        "give",
+        r#"{"amount":10,"purpose":"birthday"}"#,
+        0
+    )
+    .await
+    .is_empty());
+
+    assert_eq!(
+        record(
+            &client,
+            addr,
+            &mut socket,
+            "spend",
+            r#"{"amount":6,"purpose":"game"}"#,
+            1
+        )
+        .await,
+        vec![(
+            percent_rule,
+            String::from("ava spent 6.00 of their 10.00 balance, more than 50%")
+        )]
+    );
+
+    assert!(record(
+        &client,
+        addr,
+        &mut socket,
+        "spend",
+        r#"{"amount":1,"purpose":"sweets"}"#,
+        0
+    )
+    .await
+    .is_empty());
+
+    assert_eq!(
+        record(
+            &client,
+            addr,
+            &mut socket,
+            "spend",
+            r#"{"amount":1.50,"purpose":"comic"}"#,
+            2
+        )
+        .await,
+        vec![
+            (
+                balance_rule,
+                String::from("ava's balance is down to 1.50, below 2.00")
+            ),
+            (
+                spends_rule,
+                String::from("ava has made 3 spends today, more than 2")
+            )
+        ]
+    );
+
+    //
+    // The balance rule is cooling down, the spends rule isn't
+    //
+    assert_eq!(
+        record(
+            &client,
+            addr,
+            &mut socket,
+            "spend",
+            r#"{"amount":0.50,"purpose":"sticker"}"#,
+            1
+        )
+        .await,
+        vec![(
+            spends_rule,
+            String::from("ava has made 4 spends today, more than 2")
+        )]
+    );
+
+    //
+    // Reversing a give takes money back, but isn't a spend
+    //
+    assert!(record(
+        &client,
+        addr,
+        &mut socket,
+        "give",
+        r#"{"amount":5,"purpose":"mistake"}"#,
+        0
+    )
+    .await
+    .is_empty());
+
+    assert!(
+        record(&client, addr, &mut socket, "transactions/6/reverse", "", 0)
+            .await
+            .is_empty()
+    );
+
+    assert_eq!(
+        record(
+            &client,
+            addr,
+            &mut socket,
+            "spend",
+            r#"{"amount":0.10,"purpose":"chew"}"#,
+            1
+        )
+        .await,
+        vec![(
+            spends_rule,
+            String::from("ava has made 5 spends today, more than 2")
+        )]
+    );
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/child/ava/alert_rules"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    let rules = serde_json::from_value::<Vec<AlertRule>>(body).unwrap();
+    assert_eq!(rules.len(), 3);
+    assert_eq!(
+        rules[0].condition,
+        AlertCondition::BalanceBelow {
+            amount: Amount::from_pence(200)
+        }
+    );
+    assert_eq!(rules[0].cooldown_seconds, 3600);
+    assert!(rules[0].last_triggered_at.is_some());
+
+    //
+    // Rules belong to a child
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::DELETE,
+        format!("http://{addr}/v1/child/bob/alert_rules/{balance_rule}"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_FOUND);
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::DELETE,
+        format!("http://{addr}/v1/child/ava/alert_rules/{balance_rule}"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NO_CONTENT);
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/child/ava/alert_rules"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_value::<Vec<AlertRule>>(body)
+            .unwrap()
+            .iter()
+            .map(|r| r.id)
+            .collect::<Vec<i64>>(),
+        vec![spends_rule, percent_rule]
+    );
+}
@@ -0,0 +1,132 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::http;
+use bank_of_dad::handlers::child::ChildAccountResponse;
+use bank_of_dad::{db::Db, router};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::HeaderMap;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use serde_json::Value;
+
+async fn request(
+    client: &Client<HttpConnector>,
+    method: http::Method,
+    uri: String,
+    request_body: Option<&str>,
+) -> (StatusCode, HeaderMap, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(String::from(request_body.unwrap_or_default())))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+
+    let status_code = response.status();
+    let headers = response.headers().clone();
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    (status_code, headers, body)
+}
+
+fn assert_deprecated(headers: &HeaderMap, successor: &str) {
+    assert_eq!(headers["deprecation"], "true");
+    assert_eq!(headers["sunset"], "Wed, 30 Jun 2027 00:00:00 GMT");
+    assert_eq!(
+        headers["link"],
+        format!("<{successor}>; rel=\"successor-version\"").as_str()
+    );
+}
+
+#[tokio::test]
+async fn api_versions_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("api_versions_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // The unprefixed paths still work, but are marked deprecated
+    //
+    let (status_code, headers, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/child/a/give"),
+        Some(r#"{"amount":2,"purpose":"chores"}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_deprecated(&headers, "/v1/child/a/give");
+
+    let (status_code, headers, body) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/child/a"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert!(!headers.contains_key("deprecation"));
+    let v1 = serde_json::from_value::<ChildAccountResponse>(body).unwrap();
+    assert_eq!(v1.transactions.len(), 1);
+
+    let (status_code, headers, body) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/child/a"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_deprecated(&headers, "/v1/child/a");
+    assert_eq!(
+        serde_json::from_value::<ChildAccountResponse>(body).unwrap(),
+        v1
+    );
+
+    let (_socket, response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/child/a/notifications"))
+            .await
+            .unwrap();
+    assert_deprecated(response.headers(), "/v1/child/a/notifications");
+
+    //
+    // Unknown paths are a plain 404 either way, as are routes added since
+    // versioning without their prefix
+    //
+    for path in [
+        "/v1/nope",
+        "/nope",
+        "/v2/child/a",
+        "/children",
+        "/diagnostics/integrity",
+    ] {
+        let (status_code, headers, body) = request(
+            &client,
+            http::Method::GET,
+            format!("http://{addr}{path}"),
+            None,
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::NOT_FOUND);
+        assert!(!headers.contains_key("deprecation"));
+        assert_eq!(
+            body["reason"],
+            format!("Requested path '{path}' not found").as_str()
+        );
+    }
+}
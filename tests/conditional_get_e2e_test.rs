@@ -0,0 +1,167 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::http;
+use bank_of_dad::{db::Db, router};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::HeaderMap;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+
+async fn get_child(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    child_name: &str,
+    conditions: &[(http::HeaderName, &str)],
+) -> (StatusCode, HeaderMap, String) {
+    let mut request = Request::builder().uri(format!("http://{addr}/v1/child/{child_name}"));
+    for (name, value) in conditions {
+        request = request.header(name, *value);
+    }
+
+    let response = client
+        .request(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status_code = response.status();
+    let headers = response.headers().clone();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+    (
+        status_code,
+        headers,
+        String::from_utf8(body.to_vec()).unwrap(),
+    )
+}
+
+async fn give(client: &Client<HttpConnector>, addr: SocketAddr, child_name: &str) {
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/{child_name}/give"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(r#"{"amount":1.25,"purpose":"chores"}"#))
+        .unwrap();
+
+    assert_eq!(
+        client.request(request).await.unwrap().status(),
+        StatusCode::OK
+    );
+}
+
+fn header(headers: &HeaderMap, name: http::HeaderName) -> &str {
+    headers[name].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn conditional_get_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("conditional_get_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // A new child has an ETag but no Last-Modified
+    //
+    let (status_code, headers, _body) = get_child(&client, addr, "a", &[]).await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(header(&headers, http::header::ETAG), "\"v1-0-0\"");
+    assert_eq!(header(&headers, http::header::CACHE_CONTROL), "no-cache");
+    assert!(!headers.contains_key(http::header::LAST_MODIFIED));
+
+    let (status_code, _headers, body) = get_child(
+        &client,
+        addr,
+        "a",
+        &[(http::header::IF_NONE_MATCH, "\"v1-0-0\"")],
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_MODIFIED);
+    assert!(body.is_empty());
+
+    //
+    // A transaction changes the ETag
+    //
+    give(&client, addr, "a").await;
+
+    let (status_code, headers, _body) = get_child(
+        &client,
+        addr,
+        "a",
+        &[(http::header::IF_NONE_MATCH, "\"v1-0-0\"")],
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    let etag = header(&headers, http::header::ETAG).to_string();
+    assert_eq!(etag, "\"v1-1-125\"");
+    let last_modified = header(&headers, http::header::LAST_MODIFIED).to_string();
+
+    let (status_code, headers, body) = get_child(
+        &client,
+        addr,
+        "a",
+        &[(http::header::IF_NONE_MATCH, etag.as_str())],
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_MODIFIED);
+    assert_eq!(header(&headers, http::header::ETAG), etag);
+    assert_eq!(header(&headers, http::header::LAST_MODIFIED), last_modified);
+    assert!(body.is_empty());
+
+    // Any of several tags, weak or not, will do
+    let (status_code, _headers, _body) = get_child(
+        &client,
+        addr,
+        "a",
+        &[(
+            http::header::IF_NONE_MATCH,
+            format!("\"v1-0-0\", W/{etag}").as_str(),
+        )],
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_MODIFIED);
+
+    let (status_code, _headers, _body) = get_child(
+        &client,
+        addr,
+        "a",
+        &[(http::header::IF_MODIFIED_SINCE, last_modified.as_str())],
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_MODIFIED);
+
+    //
+    // Another child's transactions don't
+    //
+    give(&client, addr, "b").await;
+
+    let (status_code, _headers, _body) = get_child(
+        &client,
+        addr,
+        "a",
+        &[(http::header::IF_NONE_MATCH, etag.as_str())],
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_MODIFIED);
+
+    give(&client, addr, "a").await;
+
+    let (status_code, headers, _body) = get_child(
+        &client,
+        addr,
+        "a",
+        &[(http::header::IF_NONE_MATCH, etag.as_str())],
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(header(&headers, http::header::ETAG), "\"v1-3-250\"");
+}
@@ -0,0 +1,146 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::http;
+use bank_of_dad::{config::Config, db::Db, router_with_config};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::HeaderMap;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+
+async fn request(
+    client: &Client<HttpConnector>,
+    method: http::Method,
+    uri: String,
+    origin: &str,
+) -> (StatusCode, HeaderMap) {
+    let mut request = Request::builder()
+        .method(method.clone())
+        .uri(uri)
+        .header(http::header::ORIGIN, origin);
+    if method == http::Method::OPTIONS {
+        request = request
+            .header(http::header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(
+                http::header::ACCESS_CONTROL_REQUEST_HEADERS,
+                "content-type,idempotency-key",
+            );
+    }
+
+    let response = client
+        .request(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    (response.status(), response.headers().clone())
+}
+
+#[tokio::test]
+async fn config_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let config: Config = toml::from_str(
+        r#"
+        [server]
+        allowed_origins = ["https://app.example"]
+
+        [features]
+        dashboard = false
+        graphql = false
+        "#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+
+    let db = Db::new();
+    let app = router_with_config(db, &config);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("config_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // Allowed origins may call the API from a browser
+    //
+    let (status_code, headers) = request(
+        &client,
+        http::Method::OPTIONS,
+        format!("http://{addr}/v1/child/a/spend"),
+        "https://app.example",
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        headers[http::header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example"
+    );
+    let allowed_headers = headers[http::header::ACCESS_CONTROL_ALLOW_HEADERS]
+        .to_str()
+        .unwrap();
+    assert!(
+        allowed_headers.contains("idempotency-key"),
+        "{allowed_headers}"
+    );
+
+    let (status_code, headers) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/children"),
+        "https://app.example",
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        headers[http::header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://app.example"
+    );
+    assert!(headers[http::header::ACCESS_CONTROL_EXPOSE_HEADERS]
+        .to_str()
+        .unwrap()
+        .contains("x-request-id"));
+
+    //
+    // Other origins aren't
+    //
+    let (_, headers) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/children"),
+        "https://elsewhere.example",
+    )
+    .await;
+    assert!(!headers.contains_key(http::header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    //
+    // Features that are off have no routes
+    //
+    for (method, path) in [
+        (http::Method::GET, "/"),
+        (http::Method::GET, "/static/app.js"),
+        (http::Method::POST, "/v1/graphql"),
+        (http::Method::GET, "/graphql"),
+    ] {
+        let (status_code, _) = request(
+            &client,
+            method,
+            format!("http://{addr}{path}"),
+            "https://app.example",
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    let (status_code, _) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/webhooks"),
+        "https://app.example",
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+}
@@ -0,0 +1,151 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::http;
+use bank_of_dad::{db::Db, router};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::HeaderMap;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+
+async fn get(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    path: &str,
+    if_none_match: Option<&str>,
+) -> (StatusCode, HeaderMap, String) {
+    let mut request = Request::builder().uri(format!("http://{addr}{path}"));
+    if let Some(etag) = if_none_match {
+        request = request.header(http::header::IF_NONE_MATCH, etag);
+    }
+
+    let response = client
+        .request(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status_code = response.status();
+    let headers = response.headers().clone();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+    // Icons aren't text, but only their headers are checked
+    (
+        status_code,
+        headers,
+        String::from_utf8_lossy(&body).into_owned(),
+    )
+}
+
+fn header(headers: &HeaderMap, name: http::HeaderName) -> &str {
+    headers[name].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn dashboard_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("dashboard_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // The dashboard is served from /
+    //
+    let (status_code, headers, body) = get(&client, addr, "/", None).await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert!(header(&headers, http::header::CONTENT_TYPE).starts_with("text/html"));
+    assert_eq!(header(&headers, http::header::CACHE_CONTROL), "no-cache");
+    assert!(headers.contains_key("X-Request-Id"));
+    assert!(body.contains("<title>Bank of Dad</title>"));
+    assert!(body.contains("/static/app.js"));
+
+    for (path, content_type) in [
+        ("/static/app.js", "javascript"),
+        ("/static/style.css", "text/css"),
+    ] {
+        let (status_code, headers, body) = get(&client, addr, path, None).await;
+        assert_eq!(status_code, StatusCode::OK, "{}", path);
+        assert!(header(&headers, http::header::CONTENT_TYPE).contains(content_type));
+        assert!(!body.is_empty());
+    }
+
+    //
+    // The web app manifest, service worker and icons make it installable
+    //
+    let (status_code, headers, body) = get(&client, addr, "/manifest.webmanifest", None).await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        header(&headers, http::header::CONTENT_TYPE),
+        "application/manifest+json"
+    );
+    assert_eq!(header(&headers, http::header::CACHE_CONTROL), "no-cache");
+    let manifest: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(manifest["display"], "standalone");
+    let icons = manifest["icons"].as_array().unwrap();
+    assert!(icons.iter().any(|icon| icon["sizes"] == "512x512"));
+
+    for icon in icons {
+        let path = icon["src"].as_str().unwrap();
+        let (status_code, headers, _) = get(&client, addr, path, None).await;
+        assert_eq!(status_code, StatusCode::OK, "{}", path);
+        assert_eq!(header(&headers, http::header::CONTENT_TYPE), "image/png");
+        assert_eq!(
+            header(&headers, http::header::CACHE_CONTROL),
+            "public, max-age=604800"
+        );
+    }
+
+    let (status_code, headers, body) = get(&client, addr, "/sw.js", None).await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert!(header(&headers, http::header::CONTENT_TYPE).contains("javascript"));
+    assert_eq!(header(&headers, http::header::CACHE_CONTROL), "no-cache");
+    assert!(body.contains("addEventListener(\"fetch\""));
+
+    //
+    // Files are revalidated with their ETags
+    //
+    let (_, headers, _) = get(&client, addr, "/static/app.js", None).await;
+    let etag = header(&headers, http::header::ETAG).to_string();
+    assert!(etag.starts_with('"') && etag.len() == 66, "{}", etag);
+
+    let (status_code, headers, body) = get(&client, addr, "/static/app.js", Some(&etag)).await;
+    assert_eq!(status_code, StatusCode::NOT_MODIFIED);
+    assert_eq!(header(&headers, http::header::ETAG), etag);
+    assert!(body.is_empty());
+
+    let (status_code, _, _) = get(&client, addr, "/", Some(&etag)).await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    //
+    // Unknown files are a plain 404, unknown API paths still get problem
+    // details
+    //
+    let (status_code, headers, _) = get(&client, addr, "/static/missing.js", None).await;
+    assert_eq!(status_code, StatusCode::NOT_FOUND);
+    assert!(!header(&headers, http::header::CONTENT_TYPE).contains("json"));
+
+    for path in ["/nope", "/v1/nope", "/v1/"] {
+        let (status_code, headers, body) = get(&client, addr, path, None).await;
+        assert_eq!(status_code, StatusCode::NOT_FOUND, "{}", path);
+        assert_eq!(
+            header(&headers, http::header::CONTENT_TYPE),
+            "application/problem+json"
+        );
+        assert!(body.contains("\"code\":\"not_found\""), "{}", body);
+    }
+
+    //
+    // The API is unchanged
+    //
+    let (status_code, _, body) = get(&client, addr, "/v1/children", None).await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(body, "[]");
+}
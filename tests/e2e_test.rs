@@ -74,7 +74,7 @@ fn to_normalised_child_account_response(json: Value) -> ChildAccountResponse {
     )
 }
 
-fn transaction(id: u8, child_name: &str, amount: i64, purpose: &str) -> Transaction {
+fn transaction(id: i64, child_name: &str, amount: i64, purpose: &str) -> Transaction {
     Transaction {
         id: id,
         timestamp: DateTime::UNIX_EPOCH,
@@ -132,7 +132,14 @@ async fn e2e_test() {
     assert_eq!(status_code, StatusCode::BAD_REQUEST);
     assert_eq!(
         body,
-        json!({"reason": "Transaction will take account a negative"})
+        json!({
+            "type": "urn:bank-of-dad:problem:insufficient_funds",
+            "title": "Insufficient funds",
+            "status": 400,
+            "detail": "Transaction will take account a negative",
+            "code": "insufficient_funds",
+            "reason": "Transaction will take account a negative"
+        })
     );
 
     //
@@ -169,7 +176,14 @@ async fn e2e_test() {
     assert_eq!(status_code, StatusCode::BAD_REQUEST);
     assert_eq!(
         body,
-        json!({"reason": "Transaction will take account a negative"})
+        json!({
+            "type": "urn:bank-of-dad:problem:insufficient_funds",
+            "title": "Insufficient funds",
+            "status": 400,
+            "detail": "Transaction will take account a negative",
+            "code": "insufficient_funds",
+            "reason": "Transaction will take account a negative"
+        })
     );
 
     //
@@ -0,0 +1,328 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::appstate::AppState;
+use bank_of_dad::model::email::EmailPreferences;
+use bank_of_dad::notifications::email::{EmailConfig, EmailNotifier};
+use bank_of_dad::{db::Db, router_with_state};
+use chrono::Utc;
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use mail_parser::MessageParser;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+#[derive(Debug)]
+struct ReceivedEmail {
+    recipients: Vec<String>,
+    subject: String,
+    text: String,
+    html: String,
+}
+
+/// Accepts every message over plain SMTP and hands it to the test.
+async fn start_smtp_sink() -> (u16, mpsc::UnboundedReceiver<ReceivedEmail>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                let mut recipients = Vec::new();
+
+                writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let command = line.to_ascii_uppercase();
+                    if command.starts_with("RCPT TO:") {
+                        recipients
+                            .push(line[8..].trim_matches(|c| c == '<' || c == '>').to_string());
+                    } else if command == "DATA" {
+                        writer.write_all(b"354 go ahead\r\n").await.unwrap();
+                        let mut data = String::new();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            data.push_str(&line);
+                            data.push_str("\r\n");
+                        }
+
+                        let message = MessageParser::default().parse(data.as_bytes()).unwrap();
+                        sender
+                            .send(ReceivedEmail {
+                                recipients: std::mem::take(&mut recipients),
+                                subject: message.subject().unwrap().to_string(),
+                                text: message.body_text(0).unwrap().to_string(),
+                                html: message.body_html(0).unwrap().to_string(),
+                            })
+                            .unwrap();
+                    } else if command == "QUIT" {
+                        writer.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+
+                    writer.write_all(b"250 OK\r\n").await.unwrap();
+                }
+            });
+        }
+    });
+
+    (port, receiver)
+}
+
+async fn next_email(emails: &mut mpsc::UnboundedReceiver<ReceivedEmail>) -> ReceivedEmail {
+    timeout(Duration::from_secs(5), emails.recv())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+async fn request(
+    client: &Client<HttpConnector>,
+    method: http::Method,
+    uri: String,
+    request_body: Option<&str>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(String::from(request_body.unwrap_or_default())))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+
+    let status_code = response.status();
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    (status_code, body)
+}
+
+#[tokio::test]
+async fn email_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let (smtp_port, mut emails) = start_smtp_sink().await;
+
+    let app_state = AppState::new(Db::new());
+    let email = Arc::new(
+        EmailNotifier::start(app_state.get_db(), EmailConfig::new("127.0.0.1", smtp_port)).unwrap(),
+    );
+    let app = router_with_state(Arc::new(app_state.with_event_listener(email.clone())));
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("email_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // Preferences are validated
+    //
+    for invalid in [
+        r#"{"email":"not an address"}"#,
+        r#"{"email":"mum@example.com","spend_threshold":0}"#,
+        r#"{"email":"mum@example.com","children":[""]}"#,
+    ] {
+        let (status_code, _body) = request(
+            &client,
+            http::Method::POST,
+            format!("http://{addr}/v1/email_preferences"),
+            Some(invalid),
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+    }
+
+    //
+    // Mum hears about every child; dad only about b, and updates his
+    // preferences to get the digest too
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/email_preferences"),
+        Some(r#"{"email":"mum@example.com","spend_threshold":5,"weekly_digest":true}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/email_preferences"),
+        Some(r#"{"email":"dad@example.com","children":["b"],"spend_threshold":1}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    let dad = serde_json::from_value::<EmailPreferences>(body).unwrap();
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/email_preferences"),
+        Some(
+            r#"{"email":"dad@example.com","children":["b"],"spend_threshold":1,"weekly_digest":true}"#,
+        ),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_value::<EmailPreferences>(body).unwrap(),
+        EmailPreferences {
+            weekly_digest: true,
+            ..dad.clone()
+        }
+    );
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/email_preferences"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        serde_json::from_value::<Vec<EmailPreferences>>(body)
+            .unwrap()
+            .iter()
+            .map(|p| p.email.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["mum@example.com", "dad@example.com"]
+    );
+
+    //
+    // Only spends over a parent's threshold send an alert
+    //
+    for (uri, body) in [
+        ("a/give", r#"{"amount":20,"purpose":"birthday"}"#),
+        ("a/spend", r#"{"amount":3,"purpose":"sweets"}"#),
+        ("a/spend", r#"{"amount":6,"purpose":"sweets & comics"}"#),
+    ] {
+        let (status_code, _body) = request(
+            &client,
+            http::Method::POST,
+            format!("http://{addr}/v1/child/{uri}"),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::OK);
+    }
+
+    let alert = next_email(&mut emails).await;
+    assert_eq!(alert.recipients, vec!["mum@example.com"]);
+    assert_eq!(alert.subject, "a spent £6.00");
+    assert!(alert
+        .text
+        .contains(r#"a just spent £6.00 on "sweets & comics""#));
+    assert!(alert.text.contains("over the £5.00"));
+    assert!(alert.html.contains("sweets &amp; comics"));
+    assert!(alert.html.contains("<strong>£11.00</strong>"));
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/b/alert_rules"),
+        Some(r#"{"kind":"balance_below","amount":1}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    for (uri, body) in [
+        ("b/give", r#"{"amount":2,"purpose":"chores"}"#),
+        ("b/spend", r#"{"amount":1.50,"purpose":"stickers"}"#),
+    ] {
+        let (status_code, _body) = request(
+            &client,
+            http::Method::POST,
+            format!("http://{addr}/v1/child/{uri}"),
+            Some(body),
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::OK);
+    }
+
+    let alert = next_email(&mut emails).await;
+    assert_eq!(alert.recipients, vec!["dad@example.com"]);
+    assert_eq!(alert.subject, "b spent £1.50");
+
+    //
+    // Alert rules email everyone watching the child
+    //
+    for recipient in ["mum@example.com", "dad@example.com"] {
+        let alert = next_email(&mut emails).await;
+        assert_eq!(alert.recipients, vec![recipient]);
+        assert_eq!(alert.subject, "Alert for b");
+        assert!(alert
+            .text
+            .contains("Alert for b: b's balance is down to 0.50, below 1.00."));
+    }
+
+    //
+    // The digest covers each parent's children
+    //
+    assert_eq!(email.send_weekly_digest(Utc::now()).await, 2);
+
+    let digest = next_email(&mut emails).await;
+    assert_eq!(digest.recipients, vec!["mum@example.com"]);
+    assert_eq!(digest.subject, "Your weekly Bank of Dad digest");
+    assert!(digest
+        .text
+        .contains("a: balance £11.00, £20.00 in, £9.00 out"));
+    assert!(digest
+        .text
+        .contains("b: balance £0.50, £2.00 in, £1.50 out"));
+    assert!(digest.html.contains("<h2>a</h2>"));
+
+    let digest = next_email(&mut emails).await;
+    assert_eq!(digest.recipients, vec!["dad@example.com"]);
+    assert!(!digest.text.contains("a: balance"));
+    assert!(digest.text.contains("-£1.50  stickers"));
+
+    //
+    // Deleting preferences stops the emails
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::DELETE,
+        format!("http://{addr}/v1/email_preferences/{}", dad.id),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NO_CONTENT);
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::DELETE,
+        format!("http://{addr}/v1/email_preferences/{}", dad.id),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_FOUND);
+
+    assert_eq!(email.send_weekly_digest(Utc::now()).await, 1);
+    assert_eq!(
+        next_email(&mut emails).await.recipients,
+        vec!["mum@example.com"]
+    );
+    assert!(emails.try_recv().is_err());
+}
@@ -0,0 +1,209 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::http;
+use bank_of_dad::model::error::{ErrorCode, ErrorResponse, FieldError, FieldErrorCode};
+use bank_of_dad::{db::Db, router};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+
+async fn request(
+    client: &Client<HttpConnector>,
+    method: http::Method,
+    uri: String,
+    content_type: &str,
+    request_body: &str,
+) -> (StatusCode, ErrorResponse) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, content_type)
+        .body(Body::from(String::from(request_body)))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+
+    let status_code = response.status();
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = serde_json::from_slice::<ErrorResponse>(&body).unwrap();
+    assert_eq!(body.status, status_code.as_u16());
+    assert_eq!(body.reason, body.detail);
+
+    (status_code, body)
+}
+
+#[tokio::test]
+async fn errors_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("errors_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let give = format!("http://{addr}/v1/child/a/give");
+    let json = mime::APPLICATION_JSON.as_ref();
+
+    //
+    // Bodies that aren't JSON
+    //
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        give.clone(),
+        json,
+        r#"{"amount":1.50,"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::MalformedJson);
+    assert_eq!(body.problem_type, "urn:bank-of-dad:problem:malformed_json");
+    assert!(body.errors.is_empty());
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        give.clone(),
+        "text/plain",
+        r#"{"amount":1.50,"purpose":"sweets"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(body.code, ErrorCode::UnsupportedMediaType);
+
+    //
+    // JSON of the wrong shape names the field
+    //
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        give.clone(),
+        json,
+        r#"{"amount":1.5,"purpose":"sweets"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body.code, ErrorCode::InvalidBody);
+    assert_eq!(body.errors.len(), 1);
+    assert_eq!(body.errors[0].field, "amount");
+    assert_eq!(body.errors[0].code, FieldErrorCode::InvalidAmount);
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        give.clone(),
+        json,
+        r#"{"amount":1}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body.errors,
+        vec![FieldError::new(
+            "purpose",
+            FieldErrorCode::Missing,
+            "missing field `purpose`"
+        )]
+    );
+
+    //
+    // Validation reports every invalid field at once
+    //
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        give.clone(),
+        json,
+        r#"{"amount":0,"purpose":""}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::ValidationFailed);
+    assert_eq!(body.title, "Validation failed");
+    assert_eq!(
+        body.errors,
+        vec![
+            FieldError::new(
+                "amount",
+                FieldErrorCode::NotPositive,
+                "Amount must be at least 0.01"
+            ),
+            FieldError::new("purpose", FieldErrorCode::Empty, "Must provide a purpose"),
+        ]
+    );
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/webhooks"),
+        json,
+        r#"{"url":"ftp://example.com","event_types":["transaction.give","transaction.steal"]}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body.errors
+            .iter()
+            .map(|e| (e.field.as_str(), e.code))
+            .collect::<Vec<(&str, FieldErrorCode)>>(),
+        vec![
+            ("url", FieldErrorCode::InvalidFormat),
+            ("event_types[1]", FieldErrorCode::InvalidType)
+        ]
+    );
+
+    //
+    // Path parameters that don't parse
+    //
+    for (method, uri) in [
+        (
+            http::Method::POST,
+            format!("http://{addr}/v1/child/a/transactions/abc/reverse"),
+        ),
+        (http::Method::DELETE, format!("http://{addr}/v1/webhooks/x")),
+    ] {
+        let (status_code, body) = request(&client, method, uri, json, "").await;
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(body.code, ErrorCode::ValidationFailed);
+        assert!(body.detail.contains("i64"), "{}", body.detail);
+    }
+
+    //
+    // Everything else gets a code too
+    //
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/a/spend"),
+        json,
+        r#"{"amount":1,"purpose":"sweets"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::InsufficientFunds);
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/nope"),
+        json,
+        "",
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::NotFound);
+    assert_eq!(body.detail, "Requested path '/v1/nope' not found");
+}
@@ -0,0 +1,267 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::{db::Db, router};
+use futures::{SinkExt, StreamExt};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Parsed from text, since with arbitrary precision `json!(1.50)` is `1.5`
+fn parse(json: &str) -> Value {
+    serde_json::from_str(json).unwrap()
+}
+
+async fn graphql(client: &Client<HttpConnector>, addr: SocketAddr, body: Value) -> Value {
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/graphql"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn get_next_message(socket: &mut Socket) -> Value {
+    match timeout(Duration::from_secs(1), socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+    {
+        tungstenite::Message::Text(msg) => serde_json::from_str(&msg).unwrap(),
+        other => panic!("unexpected websocket message {other:?}"),
+    }
+}
+
+async fn send_message(socket: &mut Socket, msg: Value) {
+    socket
+        .send(tungstenite::Message::Text(msg.to_string()))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn graphql_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("graphql_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // Subscribe before anything happens
+    //
+    let mut request = format!("ws://{addr}/v1/graphql")
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        http::header::SEC_WEBSOCKET_PROTOCOL,
+        http::HeaderValue::from_static("graphql-transport-ws"),
+    );
+    let (mut socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+    assert_eq!(
+        response.headers()[http::header::SEC_WEBSOCKET_PROTOCOL],
+        "graphql-transport-ws"
+    );
+
+    send_message(&mut socket, json!({"type": "connection_init"})).await;
+    assert_eq!(
+        get_next_message(&mut socket).await,
+        json!({"type": "connection_ack"})
+    );
+    send_message(
+        &mut socket,
+        json!({
+            "id": "1",
+            "type": "subscribe",
+            "payload": {"query": r#"subscription { transactions(childName: "a") { id childName amount purpose } }"#},
+        }),
+    )
+    .await;
+
+    //
+    // Mutations record through the same path as the HTTP API
+    //
+    let response = graphql(
+        &client,
+        addr,
+        json!({
+            "query": r#"mutation Give($amount: Amount!) { give(childName: "a", amount: $amount, purpose: "chores") { id amount } }"#,
+            "operationName": "Give",
+            "variables": {"amount": parse("2.50")},
+        }),
+    )
+    .await;
+    assert_eq!(
+        response,
+        parse(r#"{"data": {"give": {"id": 1, "amount": 2.50}}}"#)
+    );
+
+    let response = graphql(
+        &client,
+        addr,
+        json!({"query": r#"mutation { spend(childName: "a", amount: 1, purpose: "sweets") { id amount } }"#}),
+    )
+    .await;
+    assert_eq!(
+        response,
+        parse(r#"{"data": {"spend": {"id": 2, "amount": -1.00}}}"#)
+    );
+
+    graphql(
+        &client,
+        addr,
+        json!({"query": r#"mutation { give(childName: "b", amount: 5, purpose: "birthday") { id } }"#}),
+    )
+    .await;
+
+    // Only child a's transactions reach the subscription
+    for (id, amount, purpose) in [(1, parse("2.50"), "chores"), (2, parse("-1.00"), "sweets")] {
+        assert_eq!(
+            get_next_message(&mut socket).await,
+            json!({
+                "id": "1",
+                "type": "next",
+                "payload": {"data": {"transactions": {"id": id, "childName": "a", "amount": amount, "purpose": purpose}}},
+            })
+        );
+    }
+
+    //
+    // Errors carry the same codes as problem details
+    //
+    let response = graphql(
+        &client,
+        addr,
+        json!({"query": r#"mutation { spend(childName: "a", amount: 10, purpose: "bike") { id } }"#}),
+    )
+    .await;
+    assert_eq!(response["data"], Value::Null);
+    assert_eq!(
+        response["errors"][0]["extensions"]["code"],
+        "insufficient_funds"
+    );
+    assert_eq!(response["errors"][0]["extensions"]["status"], 400);
+
+    let response = graphql(
+        &client,
+        addr,
+        json!({"query": r#"mutation { give(childName: "a", amount: 0, purpose: "") { id } }"#}),
+    )
+    .await;
+    assert_eq!(
+        response["errors"][0]["extensions"]["code"],
+        "validation_failed"
+    );
+    assert_eq!(
+        response["errors"][0]["extensions"]["errors"],
+        json!([
+            {"field": "amount", "code": "not_positive", "message": "Amount must be at least 0.01"},
+            {"field": "purpose", "code": "empty", "message": "Must provide a purpose"},
+        ])
+    );
+
+    //
+    // One query for the whole household
+    //
+    let response = graphql(
+        &client,
+        addr,
+        json!({"query": "{ children { name balance transactions(last: 1) { id purpose } } }"}),
+    )
+    .await;
+    assert_eq!(
+        response,
+        parse(
+            r#"{"data": {"children": [
+                {"name": "a", "balance": 1.50, "transactions": [{"id": 2, "purpose": "sweets"}]},
+                {"name": "b", "balance": 5.00, "transactions": [{"id": 3, "purpose": "birthday"}]}
+            ]}}"#
+        )
+    );
+
+    // The dashboard's query, with the default page of children
+    let response = graphql(
+        &client,
+        addr,
+        json!({"query": "{ children { name balance transactions(last: 5) { id timestamp amount purpose } } }"}),
+    )
+    .await;
+    assert!(response.get("errors").is_none(), "{response}");
+    assert_eq!(
+        response["data"]["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|child| child["transactions"].as_array().unwrap().len())
+            .collect::<Vec<usize>>(),
+        vec![2, 1]
+    );
+
+    let response = graphql(
+        &client,
+        addr,
+        json!({"query": r#"{ child(name: "c") { balance transactions { id } } transactions(afterId: 1) { id } }"#}),
+    )
+    .await;
+    assert_eq!(
+        response,
+        parse(
+            r#"{"data": {
+                "child": {"balance": 0.00, "transactions": []},
+                "transactions": [{"id": 2}, {"id": 3}]
+            }}"#
+        )
+    );
+
+    //
+    // Completing the subscription deregisters it from fan-out
+    //
+    send_message(&mut socket, json!({"id": "1", "type": "complete"})).await;
+    socket.close(None).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = client
+        .get(
+            format!("http://{addr}/v1/diagnostics/websockets")
+                .parse()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let counts: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(counts["total"], 0);
+
+    //
+    // A websocket has to speak a GraphQL protocol
+    //
+    assert!(
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/graphql"))
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,82 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use bank_of_dad::config::Config;
+use bank_of_dad::model::health::{BuildInfo, Readiness, ReadinessCheck};
+use bank_of_dad::{app_state_with_config, db::Db, router_with_state_and_config};
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use serde::de::DeserializeOwned;
+
+async fn get<T: DeserializeOwned>(addr: SocketAddr, path: &str) -> (StatusCode, T) {
+    let request = Request::builder()
+        .uri(format!("http://{addr}{path}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    let status = response.status();
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice::<T>(&body).unwrap())
+}
+
+#[tokio::test]
+async fn health_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let config = Config::default();
+    let app_state = Arc::new(app_state_with_config(Db::new(), &config));
+    let app = router_with_state_and_config(app_state.clone(), &config);
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("health_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // Alive, ready and built from this crate
+    //
+    let (status_code, health) = get::<serde_json::Value>(addr, "/healthz").await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(health, serde_json::json!({ "status": "ok" }));
+
+    let (status_code, readiness) = get::<Readiness>(addr, "/readyz").await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        readiness,
+        Readiness {
+            ready: true,
+            checks: vec![
+                ReadinessCheck::new("shutdown", None),
+                ReadinessCheck::new("database", None),
+                ReadinessCheck::new("webhooks", None),
+            ],
+        }
+    );
+
+    let (status_code, build_info) = get::<BuildInfo>(addr, "/version").await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(build_info, BuildInfo::current());
+    assert_eq!(build_info.version, env!("CARGO_PKG_VERSION"));
+    assert!(!build_info.git_sha.is_empty());
+
+    //
+    // Unready once shutdown starts, though still alive
+    //
+    app_state.begin_shutdown();
+
+    let (status_code, readiness) = get::<Readiness>(addr, "/readyz").await;
+    assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(!readiness.ready);
+    assert_eq!(
+        readiness.checks[0],
+        ReadinessCheck::new("shutdown", Some(String::from("Shutting down")))
+    );
+
+    let (status_code, _) = get::<serde_json::Value>(addr, "/healthz").await;
+    assert_eq!(status_code, StatusCode::OK);
+}
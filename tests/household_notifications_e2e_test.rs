@@ -0,0 +1,129 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::model::transaction::Transaction;
+use bank_of_dad::model::websocket_msg::HouseholdReply;
+use bank_of_dad::{db::Db, router};
+use futures::{SinkExt, StreamExt};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn post_give_to_child(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    child_name: &str,
+    msg: &str,
+) {
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/{child_name}/give"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(String::from(msg)))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn get_next_text_frame_from_socket(socket: &mut Socket) -> String {
+    match timeout(Duration::from_secs(1), socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+    {
+        tungstenite::Message::Text(msg) => msg,
+        other => panic!("unexpected websocket message {other:?}"),
+    }
+}
+
+async fn send_command(socket: &mut Socket, command: &str) -> HouseholdReply {
+    socket
+        .send(tungstenite::Message::Text(String::from(command)))
+        .await
+        .unwrap();
+    serde_json::from_str::<HouseholdReply>(&get_next_text_frame_from_socket(socket).await).unwrap()
+}
+
+#[tokio::test]
+async fn household_notifications_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("household_notifications_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let (mut socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/notifications"))
+            .await
+            .unwrap();
+
+    //
+    // Subscribe to A and B, a payment to C must not arrive
+    //
+    assert_eq!(
+        send_command(
+            &mut socket,
+            r#"{"action":"subscribe","children":["b","a"]}"#
+        )
+        .await,
+        HouseholdReply::Subscriptions(vec![String::from("a"), String::from("b")])
+    );
+
+    post_give_to_child(&client, addr, "c", r#"{"amount":1,"purpose":"for c"}"#).await;
+    post_give_to_child(&client, addr, "a", r#"{"amount":2,"purpose":"for a"}"#).await;
+    post_give_to_child(&client, addr, "b", r#"{"amount":3,"purpose":"for b"}"#).await;
+
+    let trx =
+        serde_json::from_str::<Transaction>(&get_next_text_frame_from_socket(&mut socket).await)
+            .unwrap();
+    assert_eq!((trx.id, trx.child_name.as_str()), (2, "a"));
+    let trx =
+        serde_json::from_str::<Transaction>(&get_next_text_frame_from_socket(&mut socket).await)
+            .unwrap();
+    assert_eq!((trx.id, trx.child_name.as_str()), (3, "b"));
+
+    //
+    // Unsubscribe from A, only B's payment arrives
+    //
+    assert_eq!(
+        send_command(&mut socket, r#"{"action":"unsubscribe","children":["a"]}"#).await,
+        HouseholdReply::Subscriptions(vec![String::from("b")])
+    );
+
+    post_give_to_child(&client, addr, "a", r#"{"amount":4,"purpose":"for a"}"#).await;
+    post_give_to_child(&client, addr, "b", r#"{"amount":5,"purpose":"for b"}"#).await;
+
+    let trx =
+        serde_json::from_str::<Transaction>(&get_next_text_frame_from_socket(&mut socket).await)
+            .unwrap();
+    assert_eq!((trx.id, trx.child_name.as_str()), (5, "b"));
+
+    //
+    // Unknown commands are rejected without closing the socket
+    //
+    assert!(matches!(
+        send_command(&mut socket, r#"{"action":"dance"}"#).await,
+        HouseholdReply::Error(_)
+    ));
+    assert_eq!(
+        send_command(&mut socket, r#"{"action":"subscribe","children":[]}"#).await,
+        HouseholdReply::Subscriptions(vec![String::from("b")])
+    );
+}
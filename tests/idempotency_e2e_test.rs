@@ -0,0 +1,193 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::{db::Db, router};
+use futures::StreamExt;
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::HeaderMap;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use serde_json::Value;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite;
+
+async fn post(
+    client: &Client<HttpConnector>,
+    uri: String,
+    idempotency_key: Option<&str>,
+    request_body: &str,
+) -> (StatusCode, HeaderMap, Value) {
+    let mut request = Request::builder()
+        .method(http::Method::POST)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref());
+    if let Some(key) = idempotency_key {
+        request = request.header("Idempotency-Key", key);
+    }
+
+    let response = client
+        .request(request.body(Body::from(request_body.to_string())).unwrap())
+        .await
+        .unwrap();
+
+    let status_code = response.status();
+    let headers = response.headers().clone();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+
+    (status_code, headers, body)
+}
+
+async fn get_balance(client: &Client<HttpConnector>, addr: SocketAddr, child_name: &str) -> String {
+    let response = client
+        .get(
+            format!("http://{addr}/v1/child/{child_name}")
+                .parse()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    body["balance"].to_string()
+}
+
+#[tokio::test]
+async fn idempotency_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("idempotency_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let give = format!("http://{addr}/v1/child/a/give");
+    let spend = format!("http://{addr}/v1/child/a/spend");
+
+    let (socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/child/a/notifications"))
+            .await
+            .unwrap();
+    let (_sender, mut notifications) = socket.split();
+
+    //
+    // The first request with a key is recorded
+    //
+    let (status_code, headers, given) = post(
+        &client,
+        give.clone(),
+        Some("give-1"),
+        r#"{"amount":5,"purpose":"pocket money"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert!(!headers.contains_key("Idempotent-Replayed"));
+
+    let (status_code, _, spent) = post(
+        &client,
+        spend.clone(),
+        Some("spend-1"),
+        r#"{"amount":1.50,"purpose":"sweets"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(spent["amount"].to_string(), "-1.50");
+
+    //
+    // Repeats get the same transaction back without recording it again
+    //
+    let (status_code, headers, replayed) = post(
+        &client,
+        spend.clone(),
+        Some("spend-1"),
+        r#"{"amount":1.50,"purpose":"sweets"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(headers["Idempotent-Replayed"], "true");
+    assert_eq!(replayed["id"], spent["id"]);
+    assert_eq!(replayed["amount"].to_string(), "-1.50");
+    assert_eq!(get_balance(&client, addr, "a").await, "3.50");
+
+    // Only the two recorded transactions are notified
+    for expected in [&given, &spent] {
+        let msg = timeout(Duration::from_secs(1), notifications.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let tungstenite::Message::Text(text) = msg else {
+            panic!("expected a text frame, got {:?}", msg);
+        };
+        let notified: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(notified["id"], expected["id"]);
+    }
+    assert!(
+        timeout(Duration::from_millis(200), notifications.next())
+            .await
+            .is_err(),
+        "the replay was notified"
+    );
+
+    //
+    // A key can't be reused for a different request
+    //
+    for (uri, body) in [
+        (spend.clone(), r#"{"amount":2.00,"purpose":"sweets"}"#),
+        (give.clone(), r#"{"amount":1.50,"purpose":"sweets"}"#),
+        (
+            format!("http://{addr}/v1/child/b/spend"),
+            r#"{"amount":1.50,"purpose":"sweets"}"#,
+        ),
+    ] {
+        let (status_code, _, problem) = post(&client, uri, Some("spend-1"), body).await;
+        assert_eq!(status_code, StatusCode::CONFLICT);
+        assert_eq!(problem["code"], "conflict");
+    }
+
+    //
+    // A refused request doesn't use up its key
+    //
+    let bike = r#"{"amount":10,"purpose":"bike"}"#;
+    let (status_code, _, problem) = post(&client, spend.clone(), Some("bike-1"), bike).await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(problem["code"], "insufficient_funds");
+
+    post(
+        &client,
+        give.clone(),
+        None,
+        r#"{"amount":10,"purpose":"birthday"}"#,
+    )
+    .await;
+    let (status_code, headers, _) = post(&client, spend.clone(), Some("bike-1"), bike).await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert!(!headers.contains_key("Idempotent-Replayed"));
+    assert_eq!(get_balance(&client, addr, "a").await, "3.50");
+
+    //
+    // Keys must be usable
+    //
+    let too_long = "k".repeat(256);
+    for key in ["", too_long.as_str()] {
+        let (status_code, _, problem) = post(
+            &client,
+            give.clone(),
+            Some(key),
+            r#"{"amount":1,"purpose":"chores"}"#,
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(problem["code"], "validation_failed");
+        assert_eq!(problem["errors"][0]["field"], "Idempotency-Key");
+    }
+}
@@ -0,0 +1,149 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::http;
+use bank_of_dad::{db::Db, router};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+
+async fn post(client: &Client<HttpConnector>, uri: String, msg: &str) -> StatusCode {
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(String::from(msg)))
+        .unwrap();
+
+    client.request(request).await.unwrap().status()
+}
+
+async fn get_metrics(client: &Client<HttpConnector>, addr: SocketAddr) -> String {
+    let request = Request::builder()
+        .uri(format!("http://{addr}/metrics"))
+        .body(Body::empty())
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "text/plain; version=0.0.4"
+    );
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// The value of the sample with exactly this name and labels.
+fn sample(metrics: &str, series: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no {series} in\n{metrics}"))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn metrics_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("metrics_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let (_socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/child/a/notifications"))
+            .await
+            .unwrap();
+
+    for (child_name, msg) in [
+        ("a", r#"{"amount":10,"purpose":"pocket money"}"#),
+        ("b", r#"{"amount":2.50,"purpose":"pocket money"}"#),
+    ] {
+        let status_code = post(
+            &client,
+            format!("http://{addr}/v1/child/{child_name}/give"),
+            msg,
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::OK);
+    }
+    let status_code = post(
+        &client,
+        format!("http://{addr}/v1/child/a/spend"),
+        r#"{"amount":3,"purpose":"sweets"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    let status_code = post(
+        &client,
+        format!("http://{addr}/v1/child/b/spend"),
+        r#"{"amount":100,"purpose":"bike"}"#,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+
+    let metrics = get_metrics(&client, addr).await;
+
+    //
+    // Requests are labelled by route, not path
+    //
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"bank_of_dad_http_requests_total{method="POST",route="/v1/child/:child_name/give",status="200"}"#
+        ),
+        2.0
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"bank_of_dad_http_requests_total{method="POST",route="/v1/child/:child_name/spend",status="400"}"#
+        ),
+        1.0
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"bank_of_dad_http_request_duration_seconds_count{method="POST",route="/v1/child/:child_name/spend",status="200"}"#
+        ),
+        1.0
+    );
+    assert!(!metrics.contains("/v1/child/a/"));
+
+    //
+    // Ledger, fan-out and database
+    //
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"bank_of_dad_transactions_recorded_total{type="give"}"#
+        ),
+        2.0
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            r#"bank_of_dad_transactions_recorded_total{type="spend"}"#
+        ),
+        1.0
+    );
+    assert_eq!(
+        sample(&metrics, "bank_of_dad_overdrafts_rejected_total"),
+        1.0
+    );
+    assert_eq!(sample(&metrics, "bank_of_dad_money_held_pounds"), 9.5);
+    assert_eq!(sample(&metrics, "bank_of_dad_open_websockets"), 1.0);
+    assert_eq!(sample(&metrics, "bank_of_dad_fan_out_messages_total"), 2.0);
+    assert_eq!(sample(&metrics, "bank_of_dad_fan_out_dropped_total"), 0.0);
+    assert!(sample(&metrics, "bank_of_dad_db_lock_wait_seconds_count") > 0.0);
+}
@@ -0,0 +1,215 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::appstate::AppState;
+use bank_of_dad::notifications::mqtt::{MqttConfig, MqttPublisher};
+use bank_of_dad::{db::Db, router_with_state};
+use bytes::BytesMut;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use rumqttc::mqttbytes::v4::{read, ConnAck, ConnectReturnCode, Packet, PingResp, PubAck};
+use rumqttc::mqttbytes::{Error, QoS};
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::timeout;
+
+#[derive(Debug, PartialEq)]
+enum BrokerEvent {
+    Connected,
+    Published {
+        topic: String,
+        payload: String,
+        retain: bool,
+    },
+}
+
+/// Just enough of an MQTT 3.1.1 broker to accept a client and report what
+/// it publishes. Notifying `kick` drops the current connection.
+async fn start_broker() -> (u16, mpsc::UnboundedReceiver<BrokerEvent>, Arc<Notify>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let kick = Arc::new(Notify::new());
+
+    let broker_kick = kick.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut incoming = BytesMut::new();
+
+            loop {
+                tokio::select! {
+                    read_bytes = stream.read_buf(&mut incoming) => {
+                        if read_bytes.unwrap_or(0) == 0 {
+                            break;
+                        }
+                    }
+                    _ = broker_kick.notified() => break,
+                }
+
+                let mut outgoing = BytesMut::new();
+                loop {
+                    match read(&mut incoming, 1 << 20) {
+                        Ok(Packet::Connect(_)) => {
+                            ConnAck::new(ConnectReturnCode::Success, false)
+                                .write(&mut outgoing)
+                                .unwrap();
+                            sender.send(BrokerEvent::Connected).unwrap();
+                        }
+                        Ok(Packet::Publish(publish)) => {
+                            if publish.qos == QoS::AtLeastOnce {
+                                PubAck::new(publish.pkid).write(&mut outgoing).unwrap();
+                            }
+                            sender
+                                .send(BrokerEvent::Published {
+                                    topic: publish.topic,
+                                    payload: String::from_utf8(publish.payload.to_vec()).unwrap(),
+                                    retain: publish.retain,
+                                })
+                                .unwrap();
+                        }
+                        Ok(Packet::PingReq) => {
+                            PingResp.write(&mut outgoing).unwrap();
+                        }
+                        Ok(_) => {}
+                        Err(Error::InsufficientBytes(_)) => break,
+                        Err(e) => panic!("bad mqtt packet {e:?}"),
+                    }
+                }
+                stream.write_all(&outgoing).await.unwrap();
+            }
+        }
+    });
+
+    (port, receiver, kick)
+}
+
+async fn next_broker_event(events: &mut mpsc::UnboundedReceiver<BrokerEvent>) -> BrokerEvent {
+    timeout(Duration::from_secs(5), events.recv())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+fn published(topic: &str, payload: &str, retain: bool) -> BrokerEvent {
+    BrokerEvent::Published {
+        topic: topic.to_string(),
+        payload: payload.to_string(),
+        retain,
+    }
+}
+
+async fn next_published_json(
+    events: &mut mpsc::UnboundedReceiver<BrokerEvent>,
+) -> (String, Value, bool) {
+    match next_broker_event(events).await {
+        BrokerEvent::Published {
+            topic,
+            payload,
+            retain,
+        } => (topic, serde_json::from_str(&payload).unwrap(), retain),
+        other => panic!("unexpected broker event {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn mqtt_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let (broker_port, mut broker_events, kick) = start_broker().await;
+
+    let app_state = AppState::new(Db::new());
+    let mqtt = MqttPublisher::start(
+        app_state.get_db(),
+        MqttConfig {
+            reconnect_delay: Duration::from_millis(50),
+            ..MqttConfig::new("127.0.0.1", broker_port)
+        },
+    );
+    let app = router_with_state(Arc::new(app_state.with_event_listener(Arc::new(mqtt))));
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("mqtt_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    assert_eq!(
+        next_broker_event(&mut broker_events).await,
+        BrokerEvent::Connected
+    );
+
+    //
+    // The first transaction for a child announces it to Home Assistant,
+    // then publishes the retained balance and the event
+    //
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/a/give"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(r#"{"amount":5,"purpose":"pocket money"}"#))
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (topic, discovery, retain) = next_published_json(&mut broker_events).await;
+    assert_eq!(topic, "homeassistant/sensor/bank_of_dad_a/balance/config");
+    assert!(retain);
+    assert_eq!(discovery["state_topic"], "bank_of_dad/a/balance");
+    assert_eq!(discovery["device_class"], "monetary");
+    assert_eq!(discovery["unique_id"], "bank_of_dad_a_balance");
+
+    assert_eq!(
+        next_broker_event(&mut broker_events).await,
+        published("bank_of_dad/a/balance", "5.00", true)
+    );
+
+    let (topic, event, retain) = next_published_json(&mut broker_events).await;
+    assert_eq!(topic, "bank_of_dad/a/events");
+    assert!(!retain);
+    assert_eq!(event["event_type"], "transaction.give");
+    assert_eq!(event["data"]["purpose"], "pocket money");
+
+    //
+    // Later transactions only update the balance and publish the event
+    //
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/a/spend"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(r#"{"amount":1.50,"purpose":"sweets"}"#))
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(
+        next_broker_event(&mut broker_events).await,
+        published("bank_of_dad/a/balance", "3.50", true)
+    );
+    let (topic, event, _retain) = next_published_json(&mut broker_events).await;
+    assert_eq!(topic, "bank_of_dad/a/events");
+    assert_eq!(event["event_type"], "transaction.spend");
+
+    //
+    // After a reconnect the discovery config and balance are republished
+    //
+    kick.notify_one();
+    assert_eq!(
+        next_broker_event(&mut broker_events).await,
+        BrokerEvent::Connected
+    );
+    let (topic, _discovery, retain) = next_published_json(&mut broker_events).await;
+    assert_eq!(topic, "homeassistant/sensor/bank_of_dad_a/balance/config");
+    assert!(retain);
+    assert_eq!(
+        next_broker_event(&mut broker_events).await,
+        published("bank_of_dad/a/balance", "3.50", true)
+    );
+}
@@ -0,0 +1,90 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use bank_of_dad::{api_route_table, config::Features, db::Db, router};
+use hyper::Client;
+use hyper::StatusCode;
+use log::info;
+use regex::Regex;
+use serde_json::Value;
+
+/// Every `(path, method)` the router serves, in OpenAPI's `{param}` path
+/// form, so a new route can't be added without documenting it.
+fn routes_in_router() -> Vec<(String, String)> {
+    let param = Regex::new(r":(\w+)").unwrap();
+
+    api_route_table(Features::default())
+        .into_iter()
+        .map(|route| {
+            (
+                param.replace_all(route.path, "{$1}").to_string(),
+                route.method.as_str().to_lowercase(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn openapi_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("openapi_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let response = client
+        .get(format!("http://{addr}/v1/openapi.json").parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let spec: Value = serde_json::from_slice(&body).unwrap();
+
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    assert_eq!(spec["servers"][0]["url"], "/v1");
+
+    //
+    // Every route is documented
+    //
+    let routes = routes_in_router();
+    assert!(routes.len() >= 20);
+    for (path, method) in routes {
+        assert!(
+            spec["paths"][&path][&method].is_object(),
+            "{} {} is missing from the OpenAPI spec",
+            method.to_uppercase(),
+            path
+        );
+    }
+
+    //
+    // Amounts are decimals with 2 decimal places
+    //
+    let schemas = &spec["components"]["schemas"];
+    assert_eq!(schemas["Amount"]["type"], "number");
+    assert_eq!(schemas["Amount"]["multipleOf"].as_f64(), Some(0.01));
+    assert_eq!(
+        schemas["GiveMoney"]["properties"]["amount"]["$ref"],
+        "#/components/schemas/Amount"
+    );
+    for schema in ["Transaction", "ChildAccountResponse", "ErrorResponse"] {
+        assert!(schemas[schema].is_object(), "{} is missing", schema);
+    }
+    assert_eq!(
+        spec["paths"]["/child/{child_name}/give"]["post"]["responses"]["400"]["content"]
+            ["application/problem+json"]["schema"]["$ref"],
+        "#/components/schemas/ErrorResponse"
+    );
+
+    //
+    // The websocket endpoints are documented as upgrades
+    //
+    for path in ["/child/{child_name}/notifications", "/notifications"] {
+        assert!(spec["paths"][path]["get"]["responses"]["101"].is_object());
+    }
+}
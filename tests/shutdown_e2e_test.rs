@@ -0,0 +1,136 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::http;
+use bank_of_dad::appstate::AppState;
+use bank_of_dad::{db::Db, router_with_state, shutdown};
+use futures::StreamExt;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+async fn shutdown_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db_path = std::env::temp_dir().join(format!("shutdown_e2e_test_{}.db", nanoid::nanoid!(8)));
+    let mut wal_path = db_path.clone().into_os_string();
+    wal_path.push("-wal");
+
+    let app_state = Arc::new(AppState::new(Db::open(&db_path).unwrap()));
+    let app = router_with_state(app_state.clone());
+    let client = Client::new();
+
+    let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel::<()>();
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("shutdown_e2e_test running on port {}", addr);
+    let server = tokio::spawn(server.with_graceful_shutdown(async {
+        let _ = stop_receiver.await;
+    }));
+
+    //
+    // A websocket, an event stream and a committed transaction in the WAL
+    //
+    let (mut socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/child/a/notifications"))
+            .await
+            .unwrap();
+
+    let mut request = format!("ws://{addr}/v1/graphql")
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        http::header::SEC_WEBSOCKET_PROTOCOL,
+        http::HeaderValue::from_static("graphql-transport-ws"),
+    );
+    let (mut graphql_socket, _response) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+    let request = Request::builder()
+        .uri(format!("http://{addr}/v1/child/a/events"))
+        .header(http::header::ACCEPT, mime::TEXT_EVENT_STREAM.as_ref())
+        .body(Body::empty())
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut events = response.into_body();
+
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/a/give"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(r#"{"amount":5,"purpose":"pocket money"}"#))
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(std::fs::metadata(&wal_path).unwrap().len() > 0);
+
+    //
+    // Going unready comes first, while requests are still served
+    //
+    app_state.begin_shutdown();
+    let request = Request::builder()
+        .uri(format!("http://{addr}/readyz"))
+        .body(Body::empty())
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    //
+    // Draining closes every stream well before the deadline and empties
+    // the WAL into the database file
+    //
+    stop_sender.send(()).unwrap();
+    let started = Instant::now();
+    shutdown::drain(
+        &app_state,
+        async { server.await.unwrap() },
+        Duration::from_secs(5),
+    )
+    .await;
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+
+    for socket in [&mut socket, &mut graphql_socket] {
+        let close_frame = loop {
+            match timeout(Duration::from_secs(1), socket.next())
+                .await
+                .unwrap()
+            {
+                Some(Ok(Message::Close(close_frame))) => break close_frame.unwrap(),
+                Some(Ok(_)) => continue,
+                other => panic!("expected close frame, got {other:?}"),
+            }
+        };
+        assert_eq!(close_frame.code, CloseCode::Restart);
+        assert_eq!(close_frame.reason, "Server restarting");
+    }
+
+    // The event stream ends once any events still buffered are read
+    while let Some(chunk) = timeout(Duration::from_secs(1), events.next())
+        .await
+        .unwrap()
+    {
+        chunk.unwrap();
+    }
+
+    //
+    // Nothing is listening any more
+    //
+    let request = Request::builder()
+        .uri(format!("http://{addr}/v1/children"))
+        .body(Body::empty())
+        .unwrap();
+    assert!(Client::new().request(request).await.is_err());
+
+    let _ = std::fs::remove_file(&db_path);
+    let _ = std::fs::remove_file(&wal_path);
+}
@@ -0,0 +1,303 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::model::amount::Amount;
+use bank_of_dad::model::transaction::Transaction;
+use bank_of_dad::{db::Db, router};
+use chrono::DateTime;
+use futures::StreamExt;
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use tokio::time::timeout;
+
+struct SseEvent {
+    id: String,
+    event: String,
+    data: String,
+}
+
+struct SseReader {
+    body: Body,
+    buffer: String,
+}
+
+impl SseReader {
+    async fn next_event(&mut self) -> SseEvent {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let raw_event: String = self.buffer.drain(..end + 2).collect();
+                let mut event = SseEvent {
+                    id: String::new(),
+                    event: String::new(),
+                    data: String::new(),
+                };
+                for line in raw_event.lines() {
+                    if let Some(v) = line.strip_prefix("id:") {
+                        event.id = v.trim().to_string();
+                    } else if let Some(v) = line.strip_prefix("event:") {
+                        event.event = v.trim().to_string();
+                    } else if let Some(v) = line.strip_prefix("data:") {
+                        event.data.push_str(v.trim());
+                    }
+                }
+                // comment-only frames are keep-alives
+                if event.data.is_empty() {
+                    continue;
+                }
+                return event;
+            }
+
+            let chunk = timeout(Duration::from_secs(1), self.body.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+
+    async fn next_transaction(&mut self) -> (String, Transaction) {
+        let event = self.next_event().await;
+        assert_eq!(event.event, "transaction");
+        let transaction = serde_json::from_str::<Transaction>(&event.data).unwrap();
+        (
+            event.id,
+            Transaction {
+                timestamp: DateTime::UNIX_EPOCH,
+                ..transaction
+            },
+        )
+    }
+}
+
+async fn open_event_stream(
+    client: &Client<HttpConnector>,
+    uri: String,
+    last_event_id: Option<&str>,
+) -> SseReader {
+    let mut request = Request::builder()
+        .uri(uri)
+        .header(http::header::ACCEPT, mime::TEXT_EVENT_STREAM.as_ref());
+    if let Some(last_event_id) = last_event_id {
+        request = request.header("Last-Event-ID", last_event_id);
+    }
+
+    let response = client
+        .request(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        mime::TEXT_EVENT_STREAM.as_ref()
+    );
+
+    SseReader {
+        body: response.into_body(),
+        buffer: String::new(),
+    }
+}
+
+async fn post_give_to_child(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    child_name: &str,
+    msg: &str,
+) {
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/{child_name}/give"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(String::from(msg)))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+fn transaction(id: i64, child_name: &str, amount: i64, purpose: &str) -> Transaction {
+    Transaction::new(
+        id,
+        DateTime::UNIX_EPOCH,
+        child_name.to_string(),
+        Amount::from_pence(amount),
+        purpose.to_string(),
+    )
+}
+
+#[tokio::test]
+async fn sse_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("sse_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let mut stream_a =
+        open_event_stream(&client, format!("http://{addr}/v1/child/a/events"), None).await;
+    let mut stream_all = open_event_stream(&client, format!("http://{addr}/v1/events"), None).await;
+
+    //
+    // A payment to A reaches both the child stream and the all-children stream
+    //
+    post_give_to_child(
+        &client,
+        addr,
+        "a",
+        r#"{"amount":5.99,"purpose":"pocket money 1"}"#,
+    )
+    .await;
+
+    let expected = transaction(1, "a", 599, "pocket money 1");
+    assert_eq!(
+        stream_a.next_transaction().await,
+        (String::from("1"), expected.clone())
+    );
+    assert_eq!(
+        stream_all.next_transaction().await,
+        (String::from("1"), expected)
+    );
+
+    //
+    // A payment to B only reaches the all-children stream, the next event on
+    // A's stream must be A's second payment.
+    //
+    post_give_to_child(
+        &client,
+        addr,
+        "b",
+        r#"{"amount":10,"purpose":"pocket money 2"}"#,
+    )
+    .await;
+    post_give_to_child(
+        &client,
+        addr,
+        "a",
+        r#"{"amount":1,"purpose":"pocket money 3"}"#,
+    )
+    .await;
+
+    assert_eq!(
+        stream_all.next_transaction().await,
+        (
+            String::from("2"),
+            transaction(2, "b", 1000, "pocket money 2")
+        )
+    );
+    assert_eq!(
+        stream_all.next_transaction().await,
+        (
+            String::from("3"),
+            transaction(3, "a", 100, "pocket money 3")
+        )
+    );
+    assert_eq!(
+        stream_a.next_transaction().await,
+        (
+            String::from("3"),
+            transaction(3, "a", 100, "pocket money 3")
+        )
+    );
+
+    //
+    // Resuming with Last-Event-ID replays only what was missed, then continues live
+    //
+    let mut resumed_a = open_event_stream(
+        &client,
+        format!("http://{addr}/v1/child/a/events"),
+        Some("1"),
+    )
+    .await;
+    assert_eq!(
+        resumed_a.next_transaction().await,
+        (
+            String::from("3"),
+            transaction(3, "a", 100, "pocket money 3")
+        )
+    );
+
+    let mut resumed_all =
+        open_event_stream(&client, format!("http://{addr}/v1/events"), Some("1")).await;
+    assert_eq!(resumed_all.next_transaction().await.0, "2");
+    assert_eq!(resumed_all.next_transaction().await.0, "3");
+
+    post_give_to_child(
+        &client,
+        addr,
+        "a",
+        r#"{"amount":2,"purpose":"pocket money 4"}"#,
+    )
+    .await;
+    assert_eq!(
+        resumed_a.next_transaction().await,
+        (
+            String::from("4"),
+            transaction(4, "a", 200, "pocket money 4")
+        )
+    );
+    assert_eq!(resumed_all.next_transaction().await.0, "4");
+
+    //
+    // Ids carry on past 255, and streams resume from them
+    //
+    for _ in 5..=300 {
+        post_give_to_child(&client, addr, "c", r#"{"amount":1,"purpose":"chores"}"#).await;
+    }
+    let mut resumed_c = open_event_stream(
+        &client,
+        format!("http://{addr}/v1/child/c/events"),
+        Some("298"),
+    )
+    .await;
+    assert_eq!(resumed_c.next_transaction().await.0, "299");
+    assert_eq!(resumed_c.next_transaction().await.0, "300");
+
+    //
+    // A client further behind than one page gets the page, then is told to
+    // resync and resumes from the page's last id
+    //
+    let mut behind_c = open_event_stream(
+        &client,
+        format!("http://{addr}/v1/child/c/events"),
+        Some("4"),
+    )
+    .await;
+    for id in 5..=36 {
+        assert_eq!(behind_c.next_transaction().await.0, id.to_string());
+    }
+    assert_eq!(behind_c.next_event().await.event, "resync_required");
+    assert!(timeout(Duration::from_secs(1), behind_c.body.next())
+        .await
+        .unwrap()
+        .is_none());
+
+    let mut behind_c = open_event_stream(
+        &client,
+        format!("http://{addr}/v1/child/c/events"),
+        Some("36"),
+    )
+    .await;
+    assert_eq!(behind_c.next_transaction().await.0, "37");
+
+    //
+    // A malformed Last-Event-ID is rejected
+    //
+    let request = Request::builder()
+        .uri(format!("http://{addr}/v1/events"))
+        .header("Last-Event-ID", "not-a-number")
+        .body(Body::empty())
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
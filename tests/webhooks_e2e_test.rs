@@ -0,0 +1,368 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::http::{self, HeaderMap};
+use axum::routing::post;
+use axum::Router;
+use bank_of_dad::appstate::AppState;
+use bank_of_dad::model::webhook::{CreatedWebhook, Webhook, WebhookDelivery};
+use bank_of_dad::notifications::webhooks::{
+    sign_payload, WebhookConfig, WebhookDispatcher, EVENT_HEADER, SIGNATURE_HEADER,
+};
+use bank_of_dad::{db::Db, router_with_state};
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Instant};
+
+#[derive(Clone, Debug)]
+struct ReceivedWebhook {
+    headers: HeaderMap,
+    body: String,
+}
+
+#[derive(Default)]
+struct Receiver {
+    received: Mutex<Vec<ReceivedWebhook>>,
+    failing: AtomicBool,
+}
+
+async fn receive(
+    State(receiver): State<Arc<Receiver>>,
+    headers: HeaderMap,
+    body: String,
+) -> StatusCode {
+    receiver
+        .received
+        .lock()
+        .unwrap()
+        .push(ReceivedWebhook { headers, body });
+
+    if receiver.failing.load(Ordering::SeqCst) {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    }
+}
+
+/// Accepts connections and never responds.
+async fn start_blackholed_receiver() -> SocketAddr {
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+
+    addr
+}
+
+fn start_receiver(receiver: Arc<Receiver>) -> SocketAddr {
+    let app = Router::new()
+        .route("/hook", post(receive))
+        .with_state(receiver);
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    addr
+}
+
+async fn request(
+    client: &Client<HttpConnector>,
+    method: http::Method,
+    uri: String,
+    request_body: Option<&str>,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(String::from(request_body.unwrap_or_default())))
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+
+    let status_code = response.status();
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    (status_code, body)
+}
+
+async fn wait_for<F: Fn() -> bool>(condition: F) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    panic!("condition not met in time");
+}
+
+async fn get_dead_letters(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+) -> Vec<WebhookDelivery> {
+    let (status_code, body) = request(
+        client,
+        http::Method::GET,
+        format!("http://{addr}/v1/webhooks/dead_letters"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    serde_json::from_value(body).unwrap()
+}
+
+#[tokio::test]
+async fn webhooks_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let app_state = AppState::new(Db::new());
+    let webhooks = WebhookDispatcher::start(
+        app_state.get_db(),
+        WebhookConfig {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(100),
+            request_timeout: Duration::from_secs(1),
+            poll_interval: Duration::from_millis(50),
+            max_in_flight: 4,
+        },
+    );
+    let app = router_with_state(Arc::new(app_state.with_event_listener(Arc::new(webhooks))));
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("webhooks_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let gives_receiver = Arc::new(Receiver::default());
+    let gives_addr = start_receiver(gives_receiver.clone());
+    let failing_receiver = Arc::new(Receiver::default());
+    failing_receiver.failing.store(true, Ordering::SeqCst);
+    let failing_addr = start_receiver(failing_receiver.clone());
+
+    //
+    // Registration is validated
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/webhooks"),
+        Some(r#"{"url":"ftp://example.com","event_types":[]}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/webhooks"),
+        Some(&format!(
+            r#"{{"url":"http://{gives_addr}/hook","event_types":["transaction.steal"]}}"#
+        )),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::BAD_REQUEST);
+
+    //
+    // Register one webhook for gives only and one for every event
+    //
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/webhooks"),
+        Some(&format!(
+            r#"{{"url":"http://{gives_addr}/hook","event_types":["transaction.give"]}}"#
+        )),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    let gives_webhook = serde_json::from_value::<CreatedWebhook>(body).unwrap();
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/webhooks"),
+        Some(&format!(r#"{{"url":"http://{failing_addr}/hook"}}"#)),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    let failing_webhook = serde_json::from_value::<CreatedWebhook>(body).unwrap();
+
+    let (status_code, body) = request(
+        &client,
+        http::Method::GET,
+        format!("http://{addr}/v1/webhooks"),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+    assert!(body.to_string().find(&gives_webhook.secret).is_none());
+    assert_eq!(
+        serde_json::from_value::<Vec<Webhook>>(body).unwrap(),
+        vec![
+            gives_webhook.webhook.clone(),
+            failing_webhook.webhook.clone()
+        ]
+    );
+
+    //
+    // A give is delivered, signed with the webhook's secret
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/a/give"),
+        Some(r#"{"amount":5,"purpose":"pocket money"}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    wait_for(|| gives_receiver.received.lock().unwrap().len() == 1).await;
+    let delivery = gives_receiver.received.lock().unwrap()[0].clone();
+    assert_eq!(
+        delivery
+            .headers
+            .get(SIGNATURE_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap(),
+        sign_payload(&gives_webhook.secret, delivery.body.as_bytes())
+    );
+    assert_eq!(
+        delivery.headers.get(EVENT_HEADER).unwrap(),
+        "transaction.give"
+    );
+    let payload = serde_json::from_str::<Value>(&delivery.body).unwrap();
+    assert_eq!(payload["event_type"], "transaction.give");
+    assert_eq!(payload["data"]["child_name"], "a");
+    assert_eq!(payload["data"]["purpose"], "pocket money");
+
+    //
+    // A spend is filtered out for the gives-only webhook
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/child/a/spend"),
+        Some(r#"{"amount":1,"purpose":"sweets"}"#),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    //
+    // The failing receiver gets both events, retried until they are dead-lettered
+    //
+    wait_for(|| failing_receiver.received.lock().unwrap().len() == 6).await;
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(failing_receiver.received.lock().unwrap().len(), 6);
+    assert_eq!(gives_receiver.received.lock().unwrap().len(), 1);
+
+    let dead_letters = get_dead_letters(&client, addr).await;
+    assert_eq!(
+        dead_letters
+            .iter()
+            .map(|d| (d.webhook_id, d.event_type.as_str(), d.attempts))
+            .collect::<Vec<(i64, &str, u32)>>(),
+        vec![
+            (failing_webhook.webhook.id, "transaction.give", 3),
+            (failing_webhook.webhook.id, "transaction.spend", 3)
+        ]
+    );
+    assert!(dead_letters[0].last_error.as_ref().unwrap().contains("500"));
+
+    //
+    // Once the receiver recovers a dead letter can be retried
+    //
+    failing_receiver.failing.store(false, Ordering::SeqCst);
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!(
+            "http://{addr}/v1/webhooks/dead_letters/{}/retry",
+            dead_letters[1].id
+        ),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::ACCEPTED);
+
+    wait_for(|| failing_receiver.received.lock().unwrap().len() == 7).await;
+    let redelivered = failing_receiver.received.lock().unwrap()[6].clone();
+    assert_eq!(
+        serde_json::from_str::<Value>(&redelivered.body).unwrap()["event_type"],
+        "transaction.spend"
+    );
+    sleep(Duration::from_millis(200)).await;
+    let dead_letters = get_dead_letters(&client, addr).await;
+    assert_eq!(dead_letters.len(), 1);
+
+    //
+    // Deleting a webhook drops its dead letters too
+    //
+    let (status_code, _body) = request(
+        &client,
+        http::Method::DELETE,
+        format!("http://{addr}/v1/webhooks/{}", failing_webhook.webhook.id),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NO_CONTENT);
+    assert!(get_dead_letters(&client, addr).await.is_empty());
+
+    let (status_code, _body) = request(
+        &client,
+        http::Method::DELETE,
+        format!("http://{addr}/v1/webhooks/{}", failing_webhook.webhook.id),
+        None,
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::NOT_FOUND);
+
+    //
+    // A receiver that never responds holds up only its own deliveries
+    //
+    let blackholed_addr = start_blackholed_receiver().await;
+    let (status_code, _body) = request(
+        &client,
+        http::Method::POST,
+        format!("http://{addr}/v1/webhooks"),
+        Some(&format!(r#"{{"url":"http://{blackholed_addr}/hook"}}"#)),
+    )
+    .await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    let started = Instant::now();
+    for _ in 0..2 {
+        let (status_code, _body) = request(
+            &client,
+            http::Method::POST,
+            format!("http://{addr}/v1/child/a/give"),
+            Some(r#"{"amount":1,"purpose":"chores"}"#),
+        )
+        .await;
+        assert_eq!(status_code, StatusCode::OK);
+    }
+    wait_for(|| gives_receiver.received.lock().unwrap().len() == 3).await;
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
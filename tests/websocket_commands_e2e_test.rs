@@ -0,0 +1,300 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::http;
+use bank_of_dad::model::amount::Amount;
+use bank_of_dad::model::error::{ErrorCode, ErrorDetails, FieldError, FieldErrorCode};
+use bank_of_dad::model::transaction::Transaction;
+use bank_of_dad::model::websocket_command::{
+    ApprovalRequest, ApprovalRequestNotification, ChildCommandOutcome, ChildCommandReply,
+    ChildCommandResult,
+};
+use bank_of_dad::{db::Db, router};
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn get_next_text_frame_from_socket(socket: &mut Socket) -> String {
+    match timeout(Duration::from_secs(1), socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+    {
+        tungstenite::Message::Text(msg) => msg,
+        other => panic!("unexpected websocket message {other:?}"),
+    }
+}
+
+async fn send_command(socket: &mut Socket, command: &str) {
+    socket
+        .send(tungstenite::Message::Text(String::from(command)))
+        .await
+        .unwrap();
+}
+
+async fn get_next_reply(socket: &mut Socket) -> ChildCommandReply {
+    serde_json::from_str::<ChildCommandReply>(&get_next_text_frame_from_socket(socket).await)
+        .unwrap()
+}
+
+fn error_reply(
+    id: &str,
+    code: ErrorCode,
+    reason: &str,
+    errors: Vec<FieldError>,
+) -> ChildCommandReply {
+    ChildCommandReply {
+        id: id.to_string(),
+        outcome: ChildCommandOutcome::Error(ErrorDetails {
+            status: 400,
+            code,
+            reason: reason.to_string(),
+            errors,
+        }),
+    }
+}
+
+#[tokio::test]
+async fn websocket_commands_e2e_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let db = Db::new();
+    for _ in 0..101 {
+        db.record_transaction_for_child(Transaction::new(
+            0,
+            Utc::now(),
+            String::from("many"),
+            Amount::from_pence(1),
+            String::from("chores"),
+        ))
+        .unwrap();
+    }
+    let app = router(db);
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("websocket_commands_e2e_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/a/give"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(r#"{"amount":5,"purpose":"pocket money"}"#))
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (mut socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/child/a/notifications"))
+            .await
+            .unwrap();
+    let (mut household_socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/notifications"))
+            .await
+            .unwrap();
+    send_command(
+        &mut household_socket,
+        r#"{"action":"subscribe","children":["a"]}"#,
+    )
+    .await;
+    get_next_text_frame_from_socket(&mut household_socket).await;
+
+    //
+    // Balance query
+    //
+    send_command(&mut socket, r#"{"id":"q1","command":"get_balance"}"#).await;
+    assert_eq!(
+        get_next_reply(&mut socket).await,
+        ChildCommandReply {
+            id: String::from("q1"),
+            outcome: ChildCommandOutcome::Result(ChildCommandResult::Balance {
+                balance: Amount::from_pence(500)
+            })
+        }
+    );
+
+    //
+    // Spend goes through the same path as HTTP, so it is also fanned out
+    // before the reply arrives
+    //
+    send_command(
+        &mut socket,
+        r#"{"id":"s1","command":"spend","amount":1.50,"purpose":"sweets"}"#,
+    )
+    .await;
+    let notification =
+        serde_json::from_str::<Transaction>(&get_next_text_frame_from_socket(&mut socket).await)
+            .unwrap();
+    assert_eq!(notification.amount, Amount::from_pence(-150));
+    assert_eq!(
+        serde_json::from_str::<Transaction>(
+            &get_next_text_frame_from_socket(&mut household_socket).await
+        )
+        .unwrap()
+        .id,
+        notification.id
+    );
+
+    let reply = get_next_reply(&mut socket).await;
+    assert_eq!(reply.id, "s1");
+    match reply.outcome {
+        ChildCommandOutcome::Result(ChildCommandResult::Transaction { transaction }) => {
+            assert_eq!(transaction, notification)
+        }
+        other => panic!("unexpected outcome {other:?}"),
+    }
+
+    //
+    // Give takes the same path, crediting the account
+    //
+    send_command(
+        &mut socket,
+        r#"{"id":"g1","command":"give","amount":2,"purpose":"pocket money"}"#,
+    )
+    .await;
+    let given =
+        serde_json::from_str::<Transaction>(&get_next_text_frame_from_socket(&mut socket).await)
+            .unwrap();
+    assert_eq!(given.amount, Amount::from_pence(200));
+    assert_eq!(given.purpose, "pocket money");
+    get_next_text_frame_from_socket(&mut household_socket).await;
+
+    let reply = get_next_reply(&mut socket).await;
+    assert_eq!(reply.id, "g1");
+    match reply.outcome {
+        ChildCommandOutcome::Result(ChildCommandResult::Transaction { transaction }) => {
+            assert_eq!(transaction, given)
+        }
+        other => panic!("unexpected outcome {other:?}"),
+    }
+
+    //
+    // Validation and overdraft errors mirror the HTTP API
+    //
+    send_command(
+        &mut socket,
+        r#"{"id":"s2","command":"spend","amount":100,"purpose":"bike"}"#,
+    )
+    .await;
+    assert_eq!(
+        get_next_reply(&mut socket).await,
+        error_reply(
+            "s2",
+            ErrorCode::InsufficientFunds,
+            "Transaction will take account a negative",
+            vec![]
+        )
+    );
+
+    send_command(
+        &mut socket,
+        r#"{"id":"s3","command":"spend","amount":1,"purpose":""}"#,
+    )
+    .await;
+    assert_eq!(
+        get_next_reply(&mut socket).await,
+        error_reply(
+            "s3",
+            ErrorCode::ValidationFailed,
+            "Must provide a purpose",
+            vec![FieldError::new(
+                "purpose",
+                FieldErrorCode::Empty,
+                "Must provide a purpose"
+            )]
+        )
+    );
+
+    send_command(&mut socket, r#"{"id":"x1","command":"steal"}"#).await;
+    let reply = get_next_reply(&mut socket).await;
+    assert_eq!(reply.id, "x1");
+    assert!(matches!(
+        reply.outcome,
+        ChildCommandOutcome::Error(ErrorDetails { status: 400, .. })
+    ));
+
+    //
+    // Recent transactions, most recent last
+    //
+    send_command(
+        &mut socket,
+        r#"{"id":"l1","command":"list_transactions","limit":1}"#,
+    )
+    .await;
+    let reply = get_next_reply(&mut socket).await;
+    assert_eq!(reply.id, "l1");
+    match reply.outcome {
+        ChildCommandOutcome::Result(ChildCommandResult::Transactions { transactions }) => {
+            assert_eq!(
+                transactions.iter().map(|t| t.id).collect::<Vec<i64>>(),
+                vec![given.id]
+            )
+        }
+        other => panic!("unexpected outcome {other:?}"),
+    }
+
+    let (mut many_socket, _response) =
+        tokio_tungstenite::connect_async(format!("ws://{addr}/v1/child/many/notifications"))
+            .await
+            .unwrap();
+    send_command(
+        &mut many_socket,
+        r#"{"id":"l2","command":"list_transactions","limit":1000}"#,
+    )
+    .await;
+    match get_next_reply(&mut many_socket).await.outcome {
+        ChildCommandOutcome::Result(ChildCommandResult::Transactions { transactions }) => {
+            assert_eq!(
+                transactions.iter().map(|t| t.id).collect::<Vec<i64>>(),
+                (2..=101).collect::<Vec<i64>>()
+            )
+        }
+        other => panic!("unexpected outcome {other:?}"),
+    }
+
+    //
+    // Approval requests reach a parent's household socket
+    //
+    send_command(
+        &mut socket,
+        r#"{"id":"r1","command":"request_approval","amount":20,"purpose":"lego"}"#,
+    )
+    .await;
+    let expected_request = ApprovalRequest {
+        child_name: String::from("a"),
+        amount: Amount::from_pence(2000),
+        purpose: String::from("lego"),
+    };
+
+    assert_eq!(
+        serde_json::from_str::<ApprovalRequestNotification>(
+            &get_next_text_frame_from_socket(&mut household_socket).await
+        )
+        .unwrap()
+        .approval_request,
+        expected_request
+    );
+
+    get_next_text_frame_from_socket(&mut socket).await;
+    assert_eq!(
+        get_next_reply(&mut socket).await,
+        ChildCommandReply {
+            id: String::from("r1"),
+            outcome: ChildCommandOutcome::Result(ChildCommandResult::ApprovalRequested {
+                approval_request: expected_request
+            })
+        }
+    );
+}
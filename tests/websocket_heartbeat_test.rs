@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bank_of_dad::appstate::{AppState, WebsocketConfig};
+use bank_of_dad::model::websocket_msg::WebsocketCounts;
+use bank_of_dad::{db::Db, router_with_state};
+use futures::{SinkExt, StreamExt};
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+async fn open_web_socket(
+    addr: SocketAddr,
+    child_name: &str,
+) -> WebSocketStream<MaybeTlsStream<TcpStream>> {
+    let (socket, _response) = tokio_tungstenite::connect_async(format!(
+        "ws://{addr}/v1/child/{child_name}/notifications"
+    ))
+    .await
+    .unwrap();
+
+    socket
+}
+
+/// A GraphQL websocket subscribed to the child's transactions, which the
+/// caller never reads.
+async fn open_graphql_web_socket(
+    addr: SocketAddr,
+    child_name: &str,
+) -> WebSocketStream<MaybeTlsStream<TcpStream>> {
+    let mut request = format!("ws://{addr}/v1/graphql")
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        hyper::header::SEC_WEBSOCKET_PROTOCOL,
+        hyper::header::HeaderValue::from_static("graphql-transport-ws"),
+    );
+    let (mut socket, _response) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+    for msg in [
+        serde_json::json!({"type": "connection_init"}),
+        serde_json::json!({
+            "id": "1",
+            "type": "subscribe",
+            "payload": {"query": format!(r#"subscription {{ transactions(childName: "{child_name}") {{ id }} }}"#)},
+        }),
+    ] {
+        socket
+            .send(tungstenite::Message::Text(msg.to_string()))
+            .await
+            .unwrap();
+    }
+
+    socket
+}
+
+/// A socket with a tiny receive buffer, so the server's sends soon block
+/// once it stops being read.
+async fn open_stalled_web_socket(addr: SocketAddr, child_name: &str) -> WebSocketStream<TcpStream> {
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.set_recv_buffer_size(4096).unwrap();
+    let stream = socket
+        .connect(SocketAddr::from((Ipv4Addr::LOCALHOST, addr.port())))
+        .await
+        .unwrap();
+    let (socket, _response) = tokio_tungstenite::client_async(
+        format!("ws://{addr}/v1/child/{child_name}/notifications"),
+        stream,
+    )
+    .await
+    .unwrap();
+
+    socket
+}
+
+async fn get_websocket_counts(addr: SocketAddr) -> WebsocketCounts {
+    let request = Request::builder()
+        .uri(format!("http://{addr}/v1/diagnostics/websockets"))
+        .body(Body::empty())
+        .unwrap();
+    let response = Client::new().request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice::<WebsocketCounts>(&body).unwrap()
+}
+
+async fn give(addr: SocketAddr, child_name: &str, purpose: &str) -> StatusCode {
+    let request = Request::builder()
+        .method(hyper::Method::POST)
+        .uri(format!("http://{addr}/v1/child/{child_name}/give"))
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({ "amount": 1, "purpose": purpose }).to_string(),
+        ))
+        .unwrap();
+    Client::new().request(request).await.unwrap().status()
+}
+
+fn websocket_counts(total: usize, by_child: &[(&str, usize)]) -> WebsocketCounts {
+    WebsocketCounts {
+        total,
+        all_children: 0,
+        by_child: by_child
+            .iter()
+            .map(|(child_name, count)| (child_name.to_string(), *count))
+            .collect::<BTreeMap<String, usize>>(),
+    }
+}
+
+#[tokio::test]
+async fn websocket_heartbeat_test() {
+    tracing_subscriber::fmt().with_thread_ids(true).init();
+
+    let app_state = AppState::new(Db::new()).with_websocket_config(WebsocketConfig {
+        ping_interval: Duration::from_millis(100),
+        pong_timeout: Duration::from_millis(200),
+        ..WebsocketConfig::default()
+    });
+    let app = router_with_state(Arc::new(app_state));
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("websocket_heartbeat_test running on port {}", addr);
+    tokio::spawn(server);
+
+    //
+    // A socket that keeps reading answers pings, one that is never polled
+    // behaves like a half-open connection and never answers.
+    //
+    let mut responsive_socket = open_web_socket(addr, "a").await;
+    let responsive_task =
+        tokio::spawn(async move { while let Some(Ok(_)) = responsive_socket.next().await {} });
+
+    let _unresponsive_socket = open_web_socket(addr, "b").await;
+    let _second_unresponsive_socket = open_web_socket(addr, "b").await;
+    let _unresponsive_graphql_socket = open_graphql_web_socket(addr, "b").await;
+
+    // The GraphQL subscription registers once its frames are processed
+    let expected = websocket_counts(4, &[("a", 1), ("b", 3)]);
+    for _ in 0..20 {
+        if get_websocket_counts(addr).await == expected {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(get_websocket_counts(addr).await, expected);
+
+    //
+    // After several ping intervals only the responsive socket is registered
+    //
+    sleep(Duration::from_millis(1000)).await;
+
+    assert_eq!(
+        get_websocket_counts(addr).await,
+        websocket_counts(1, &[("a", 1)])
+    );
+    assert!(!responsive_task.is_finished());
+
+    //
+    // A peer that stops reading fills its TCP buffer, so sends to it block.
+    // They time out rather than holding the socket open until the next ping,
+    // which is too far away to help here.
+    //
+    let app_state = AppState::new(Db::new()).with_websocket_config(WebsocketConfig {
+        ping_interval: Duration::from_secs(60),
+        pong_timeout: Duration::from_millis(200),
+        ..WebsocketConfig::default()
+    });
+    let app = router_with_state(Arc::new(app_state));
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    let _stalled_socket = open_stalled_web_socket(addr, "c").await;
+    let purpose = "x".repeat(100_000);
+    for _ in 0..100 {
+        assert_eq!(give(addr, "c", &purpose).await, StatusCode::OK);
+    }
+
+    sleep(Duration::from_millis(1000)).await;
+
+    assert_eq!(get_websocket_counts(addr).await, websocket_counts(0, &[]));
+}
@@ -0,0 +1,143 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use axum::http;
+use bank_of_dad::model::transaction::Transaction;
+use bank_of_dad::model::websocket_msg::ResyncRequired;
+use bank_of_dad::{db::Db, router};
+use futures::StreamExt;
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper::Client;
+use hyper::Request;
+use hyper::StatusCode;
+use log::info;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+const READING_SOCKETS: usize = 2000;
+const STALLED_SOCKETS: usize = 3;
+const STALLING_TRANSACTIONS: usize = 60;
+const STALLING_PURPOSE_BYTES: usize = 256 * 1024;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn open_web_socket(addr: SocketAddr, child_name: &str) -> Socket {
+    let (socket, _response) = tokio_tungstenite::connect_async(format!(
+        "ws://{addr}/v1/child/{child_name}/notifications"
+    ))
+    .await
+    .unwrap();
+
+    socket
+}
+
+async fn post_give_to_child(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    child_name: &str,
+    purpose: &str,
+) -> Duration {
+    let request = Request::builder()
+        .method(http::Method::POST)
+        .uri(format!("http://{addr}/v1/child/{child_name}/give"))
+        .header(http::header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(format!(
+            r#"{{"amount":1,"purpose":"{purpose}"}}"#
+        )))
+        .unwrap();
+
+    let started = Instant::now();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+    started.elapsed()
+}
+
+async fn get_next_text_frame_from_socket(socket: &mut Socket) -> String {
+    match timeout(Duration::from_secs(5), socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+    {
+        tungstenite::Message::Text(msg) => msg,
+        other => panic!("unexpected websocket message {other:?}"),
+    }
+}
+
+async fn assert_all_receive(sockets: &mut [Socket], expected_id: i64) {
+    for socket in sockets.iter_mut() {
+        let msg = get_next_text_frame_from_socket(socket).await;
+        let trx = serde_json::from_str::<Transaction>(&msg).unwrap();
+        assert_eq!(trx.id, expected_id);
+    }
+}
+
+#[tokio::test]
+async fn websocket_load_test() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::WARN)
+        .init();
+
+    let db = Db::new();
+    let app = router(db);
+    let client = Client::new();
+
+    let server = axum::Server::bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .serve(app.into_make_service());
+    let addr = server.local_addr();
+    info!("websocket_load_test running on port {}", addr);
+    tokio::spawn(server);
+
+    let mut reading_sockets = Vec::with_capacity(READING_SOCKETS);
+    for _ in 0..READING_SOCKETS {
+        reading_sockets.push(open_web_socket(addr, "a").await);
+    }
+
+    //
+    // Every reading socket receives the transaction
+    //
+    post_give_to_child(&client, addr, "a", "first").await;
+    assert_all_receive(&mut reading_sockets, 1).await;
+
+    //
+    // Stall a few sockets for child B by never reading them, then push far
+    // more data at them than the TCP buffers and channels can hold.
+    //
+    let mut stalled_sockets = Vec::with_capacity(STALLED_SOCKETS);
+    for _ in 0..STALLED_SOCKETS {
+        stalled_sockets.push(open_web_socket(addr, "b").await);
+    }
+
+    let large_purpose = "x".repeat(STALLING_PURPOSE_BYTES);
+    for _ in 0..STALLING_TRANSACTIONS {
+        let elapsed = post_give_to_child(&client, addr, "b", &large_purpose).await;
+        assert!(elapsed < Duration::from_secs(2), "give took {elapsed:?}");
+    }
+
+    //
+    // A payment to A still fans out promptly to every reading socket
+    //
+    let expected_id = i64::try_from(STALLING_TRANSACTIONS + 2).unwrap();
+    let elapsed = post_give_to_child(&client, addr, "a", "second").await;
+    assert!(elapsed < Duration::from_secs(2), "give took {elapsed:?}");
+    assert_all_receive(&mut reading_sockets, expected_id).await;
+
+    //
+    // Once the stalled sockets catch up they are told to resync
+    //
+    for socket in stalled_sockets.iter_mut() {
+        let mut resync_required = false;
+        for _ in 0..=STALLING_TRANSACTIONS {
+            let msg = get_next_text_frame_from_socket(socket).await;
+            if serde_json::from_str::<ResyncRequired>(&msg).is_ok() {
+                resync_required = true;
+                break;
+            }
+        }
+        assert!(resync_required);
+    }
+}
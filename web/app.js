@@ -0,0 +1,478 @@
+"use strict";
+
+// The dashboard only uses the public API, so anything it does can be done
+// with curl too.
+const API = "/v1";
+const RECONNECT_DELAY_MS = 2000;
+// Spends made offline, oldest first, until the server has them.
+const QUEUE_KEY = "bank-of-dad:queued-spends";
+// The child last viewed on this device, opened by the installed app.
+const CHILD_KEY = "bank-of-dad:child";
+
+// Child name -> { balance, transactions }. Balances are in pence so live
+// updates don't drift. Transactions are only loaded for the history view.
+const children = new Map();
+// Child name -> WebSocket, one per child on screen.
+const sockets = new Map();
+
+const view = document.getElementById("view");
+
+function pence(amount) {
+  return Math.round(Number(amount) * 100);
+}
+
+function formatPence(value) {
+  const sign = value < 0 ? "-" : "";
+  const abs = Math.abs(value);
+  return `${sign}£${Math.floor(abs / 100)}.${String(abs % 100).padStart(2, "0")}`;
+}
+
+function childPath(childName) {
+  return `${API}/child/${encodeURIComponent(childName)}`;
+}
+
+// Problem details become one readable line, with field errors if any.
+async function problem(response) {
+  try {
+    const body = await response.json();
+    if (body.errors && body.errors.length > 0) {
+      return body.errors.map((e) => e.message).join(". ");
+    }
+    return body.detail || body.title;
+  } catch {
+    return `The server said ${response.status}`;
+  }
+}
+
+async function getJson(path) {
+  const response = await fetch(path, { headers: { Accept: "application/json" } });
+  if (!response.ok) {
+    throw new Error(await problem(response));
+  }
+  return response.json();
+}
+
+async function loadChildren() {
+  for (const child of await getJson(`${API}/children`)) {
+    const known = children.get(child.child_name);
+    if (known) {
+      known.balance = pence(child.balance);
+    } else {
+      children.set(child.child_name, { balance: pence(child.balance), transactions: null });
+    }
+  }
+}
+
+async function loadChild(childName) {
+  const account = await getJson(childPath(childName));
+  children.set(childName, {
+    balance: pence(account.balance),
+    transactions: account.transactions.reverse(),
+  });
+}
+
+// Thrown when the server can't be reached at all, rather than refusing.
+class OfflineError extends Error {}
+
+function newIdempotencyKey() {
+  if (crypto.randomUUID) {
+    return crypto.randomUUID();
+  }
+  // randomUUID is missing outside secure contexts, e.g. plain http on a LAN
+  return [...crypto.getRandomValues(new Uint8Array(16))]
+    .map((b) => b.toString(16).padStart(2, "0"))
+    .join("");
+}
+
+// Amounts go to the server exactly as typed: it wants 2 or 1.50, and
+// JSON.stringify would turn 1.50 into 1.5.
+function transactionBody(amount, purpose) {
+  return `{"amount":${amount},"purpose":${JSON.stringify(purpose)}}`;
+}
+
+function checkAmount(amount) {
+  if (!/^[0-9]+(\.[0-9]{2})?$/.test(amount)) {
+    throw new Error("Enter an amount like 2 or 1.50");
+  }
+}
+
+// The key makes retries safe: the server records each key's transaction
+// only once.
+async function recordTransaction(childName, kind, amount, purpose, idempotencyKey) {
+  let response;
+  try {
+    response = await fetch(`${childPath(childName)}/${kind}`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json", "Idempotency-Key": idempotencyKey },
+      body: transactionBody(amount, purpose),
+    });
+  } catch {
+    throw new OfflineError("The server can't be reached");
+  }
+  if (!response.ok) {
+    throw new Error(await problem(response));
+  }
+  return response.json();
+}
+
+function queuedSpends() {
+  try {
+    return JSON.parse(localStorage.getItem(QUEUE_KEY)) || [];
+  } catch {
+    return [];
+  }
+}
+
+function saveQueuedSpends(queue) {
+  localStorage.setItem(QUEUE_KEY, JSON.stringify(queue));
+}
+
+function queueSpend(childName, amount, purpose, idempotencyKey) {
+  const queue = queuedSpends();
+  queue.push({
+    key: idempotencyKey,
+    child_name: childName,
+    amount,
+    purpose,
+    queued_at: new Date().toISOString(),
+  });
+  saveQueuedSpends(queue);
+}
+
+let replaying = false;
+
+// Sends queued spends in order with the keys they were queued with, so one
+// the server got before the connection dropped isn't recorded again. Stops
+// at the first that can't be sent; one the server refuses is dropped.
+async function replayQueuedSpends() {
+  if (replaying) {
+    return;
+  }
+  replaying = true;
+  try {
+    for (const spend of queuedSpends()) {
+      try {
+        await recordTransaction(spend.child_name, "spend", spend.amount, spend.purpose, spend.key);
+      } catch (e) {
+        if (e instanceof OfflineError) {
+          break;
+        }
+        flash(`"${spend.purpose}" wasn't recorded: ${e.message}`);
+      }
+      saveQueuedSpends(queuedSpends().filter((queued) => queued.key !== spend.key));
+      render();
+    }
+  } finally {
+    replaying = false;
+  }
+}
+
+// Returns true if the transaction's child wasn't known yet.
+function applyTransaction(transaction) {
+  const child = children.get(transaction.child_name);
+  if (!child) {
+    children.set(transaction.child_name, {
+      balance: pence(transaction.amount),
+      transactions: null,
+    });
+    return true;
+  }
+
+  if (!child.transactions || !child.transactions.some((t) => t.id === transaction.id)) {
+    child.balance += pence(transaction.amount);
+    if (child.transactions) {
+      child.transactions.unshift(transaction);
+    }
+  }
+  return false;
+}
+
+function onFrame(childName, data) {
+  let frame;
+  try {
+    frame = JSON.parse(data);
+  } catch {
+    return;
+  }
+
+  if (frame.resync_required) {
+    refresh(childName);
+  } else if (frame.id !== undefined && frame.amount !== undefined) {
+    if (applyTransaction(frame)) {
+      render();
+    } else {
+      update(frame.child_name);
+    }
+  } else if (frame.alert) {
+    flash(frame.alert.message);
+  } else if (frame.approval_request) {
+    const request = frame.approval_request;
+    flash(`${request.child_name} asks to spend ${formatPence(pence(request.amount))} on ${request.purpose}`);
+  }
+}
+
+// Keeps one notifications socket open per child on screen, reconnecting
+// and re-reading the account whenever one drops.
+function watch(names) {
+  for (const [childName, socket] of sockets) {
+    if (!names.includes(childName)) {
+      sockets.delete(childName);
+      socket.close();
+    }
+  }
+
+  for (const childName of names) {
+    if (sockets.has(childName)) {
+      continue;
+    }
+    const scheme = location.protocol === "https:" ? "wss:" : "ws:";
+    const socket = new WebSocket(`${scheme}//${location.host}${childPath(childName)}/notifications`);
+    sockets.set(childName, socket);
+
+    socket.onopen = () => {
+      setConnected(true);
+      replayQueuedSpends();
+    };
+    socket.onmessage = (event) => onFrame(childName, event.data);
+    socket.onclose = () => {
+      if (sockets.get(childName) !== socket) {
+        return;
+      }
+      sockets.delete(childName);
+      setConnected(false);
+      setTimeout(() => {
+        if (currentChildren().includes(childName)) {
+          watch(currentChildren());
+          refresh(childName);
+        }
+      }, RECONNECT_DELAY_MS);
+    };
+  }
+}
+
+function setConnected(connected) {
+  document.getElementById("connection").hidden = connected || !navigator.onLine;
+}
+
+function setOnline() {
+  document.getElementById("offline").hidden = navigator.onLine;
+  setConnected(true);
+}
+
+function flash(message) {
+  const note = document.createElement("p");
+  note.className = "flash";
+  note.textContent = message;
+  document.body.append(note);
+  setTimeout(() => note.remove(), 8000);
+}
+
+function route() {
+  const match = location.hash.match(/^#\/child\/(.+)$/);
+  return match ? { childName: decodeURIComponent(match[1]) } : {};
+}
+
+function childHash(childName) {
+  return `#/child/${encodeURIComponent(childName)}`;
+}
+
+function currentChildren() {
+  const { childName } = route();
+  return childName ? [childName] : [...children.keys()];
+}
+
+function showError(form, message) {
+  const error = form.querySelector(".error");
+  error.textContent = message || "";
+  error.hidden = !message;
+}
+
+function bindForm(form, childNameFor) {
+  form.addEventListener("submit", async (event) => {
+    event.preventDefault();
+    const kind = event.submitter ? event.submitter.value : "give";
+    const childName = childNameFor(form);
+    const amount = form.elements.amount.value.trim();
+    const purpose = form.elements.purpose.value.trim();
+    const idempotencyKey = newIdempotencyKey();
+    showError(form, null);
+    try {
+      checkAmount(amount);
+      try {
+        await recordTransaction(childName, kind, amount, purpose, idempotencyKey);
+      } catch (e) {
+        // Gives need the server's answer, spends can wait for it
+        if (!(e instanceof OfflineError) || kind !== "spend") {
+          throw e;
+        }
+        queueSpend(childName, amount, purpose, idempotencyKey);
+        form.reset();
+        flash("Saved. It'll be sent when you're back online.");
+        render();
+        return;
+      }
+      form.reset();
+      // Known children are updated by their notifications, a new one is
+      // read once and watched from then on
+      if (!children.has(childName)) {
+        await refresh(route().childName);
+        watch(currentChildren());
+      }
+    } catch (e) {
+      showError(form, e.message);
+    }
+  });
+}
+
+function queuedFor(childName) {
+  return queuedSpends().filter((spend) => spend.child_name === childName);
+}
+
+function setBalance(section, child) {
+  const balance = section.querySelector(".balance");
+  balance.textContent = formatPence(child.balance);
+  balance.classList.toggle("negative", child.balance < 0);
+
+  const waiting = queuedFor(section.dataset.child).reduce((sum, spend) => sum + pence(spend.amount), 0);
+  const pending = section.querySelector(".pending");
+  pending.textContent = `${formatPence(waiting)} of spending waiting to be sent`;
+  pending.hidden = waiting === 0;
+}
+
+function card(childName) {
+  const node = document.getElementById("card-template").content.cloneNode(true);
+  const section = node.querySelector(".card");
+  section.dataset.child = childName;
+  const link = node.querySelector(".name");
+  link.textContent = childName;
+  link.href = childHash(childName);
+  setBalance(section, children.get(childName));
+  bindForm(node.querySelector("form"), () => childName);
+  return node;
+}
+
+function history(childName, child) {
+  const node = document.getElementById("history-template").content.cloneNode(true);
+  const body = node.querySelector("tbody");
+  for (const spend of queuedFor(childName).reverse()) {
+    const row = body.insertRow();
+    row.className = "queued";
+    row.insertCell().textContent = "Waiting to send";
+    row.insertCell().textContent = spend.purpose;
+    const amount = row.insertCell();
+    amount.className = "amount negative";
+    amount.textContent = formatPence(-pence(spend.amount));
+  }
+  for (const transaction of child.transactions) {
+    const row = body.insertRow();
+    row.insertCell().textContent = new Date(transaction.timestamp).toLocaleString();
+    row.insertCell().textContent = transaction.purpose;
+    const amount = row.insertCell();
+    amount.className = "amount";
+    amount.textContent = formatPence(pence(transaction.amount));
+    amount.classList.toggle("negative", pence(transaction.amount) < 0);
+  }
+  node.querySelector(".empty").hidden = body.rows.length > 0;
+  return node;
+}
+
+// Updates a child's balance and history in place, so forms being filled in
+// aren't cleared.
+function update(childName) {
+  const child = children.get(childName);
+  const section = [...view.querySelectorAll(".card")].find((c) => c.dataset.child === childName);
+  if (!child || !section) {
+    return;
+  }
+  setBalance(section, child);
+
+  const oldHistory = view.querySelector(".history");
+  if (child.transactions && route().childName === childName) {
+    const newHistory = history(childName, child);
+    if (oldHistory) {
+      oldHistory.replaceWith(newHistory);
+    } else {
+      view.append(newHistory);
+    }
+  }
+}
+
+function render() {
+  const { childName } = route();
+  view.replaceChildren();
+
+  if (childName) {
+    const child = children.get(childName);
+    if (!child) {
+      return;
+    }
+    view.append(card(childName));
+    if (child.transactions) {
+      view.append(history(childName, child));
+    }
+    return;
+  }
+
+  const grid = document.createElement("div");
+  grid.className = "cards";
+  for (const name of [...children.keys()].sort()) {
+    grid.append(card(name));
+  }
+  const newChild = document.getElementById("new-child-template").content.cloneNode(true);
+  bindForm(newChild.querySelector("form"), (form) => form.elements.child_name.value.trim());
+  grid.append(newChild);
+  view.append(grid);
+}
+
+async function refresh(childName) {
+  try {
+    if (childName) {
+      await loadChild(childName);
+      update(childName);
+    } else {
+      const known = children.size;
+      await loadChildren();
+      if (children.size === known) {
+        children.forEach((_, name) => update(name));
+      } else {
+        render();
+      }
+    }
+  } catch (e) {
+    flash(e.message);
+  }
+}
+
+async function show() {
+  // The installed app opens the child last viewed on this device
+  if (location.hash === "#/me") {
+    const saved = localStorage.getItem(CHILD_KEY);
+    location.replace(saved ? childHash(saved) : "#/");
+    return;
+  }
+
+  const { childName } = route();
+  if (childName) {
+    localStorage.setItem(CHILD_KEY, childName);
+    children.set(childName, children.get(childName) || { balance: 0, transactions: [] });
+  }
+  render();
+  await refresh(childName);
+  watch(currentChildren());
+}
+
+window.addEventListener("hashchange", show);
+window.addEventListener("offline", setOnline);
+window.addEventListener("online", () => {
+  setOnline();
+  replayQueuedSpends();
+  refresh(route().childName);
+});
+
+if ("serviceWorker" in navigator) {
+  navigator.serviceWorker.register("/sw.js").catch((e) => console.warn("no offline support", e));
+}
+
+setOnline();
+show();
+replayQueuedSpends();
@@ -0,0 +1,74 @@
+"use strict";
+
+// Keeps the dashboard and the last balances and histories it read
+// available offline. Everything is network first, so a new build or
+// balance is always used when the server can be reached.
+const SHELL_CACHE = "bank-of-dad-shell-v1";
+const DATA_CACHE = "bank-of-dad-data-v1";
+const SHELL = [
+  "/",
+  "/static/app.js",
+  "/static/style.css",
+  "/manifest.webmanifest",
+  "/static/icons/icon-192.png",
+];
+
+self.addEventListener("install", (event) => {
+  event.waitUntil(
+    caches
+      .open(SHELL_CACHE)
+      .then((cache) => cache.addAll(SHELL))
+      .then(() => self.skipWaiting()),
+  );
+});
+
+self.addEventListener("activate", (event) => {
+  event.waitUntil(
+    caches
+      .keys()
+      .then((names) =>
+        Promise.all(
+          names
+            .filter((name) => name !== SHELL_CACHE && name !== DATA_CACHE)
+            .map((name) => caches.delete(name)),
+        ),
+      )
+      .then(() => self.clients.claim()),
+  );
+});
+
+// Only the reads the dashboard shows offline are kept.
+function isCachedApiRead(url) {
+  return url.pathname === "/v1/children" || /^\/v1\/child\/[^/]+$/.test(url.pathname);
+}
+
+async function networkFirst(request, cacheName) {
+  const cache = await caches.open(cacheName);
+  try {
+    const response = await fetch(request);
+    if (response.ok) {
+      await cache.put(request, response.clone());
+    }
+    return response;
+  } catch (e) {
+    const cached = await cache.match(request, { ignoreSearch: true });
+    if (cached) {
+      return cached;
+    }
+    throw e;
+  }
+}
+
+self.addEventListener("fetch", (event) => {
+  const url = new URL(event.request.url);
+  // Writes are never cached, the page queues offline spends itself
+  if (event.request.method !== "GET" || url.origin !== location.origin) {
+    return;
+  }
+
+  if (isCachedApiRead(url)) {
+    event.respondWith(networkFirst(event.request, DATA_CACHE));
+  } else if (!url.pathname.startsWith("/v1/")) {
+    event.respondWith(networkFirst(event.request, SHELL_CACHE));
+  }
+});